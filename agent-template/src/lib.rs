@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use utils::log;
 use regex::Regex;
+use uomi_common::{Address, AddressError, Amount, ChainError, ErrorKind, FormatOptions};
 
+// The offchain API block in utils is kept verbatim, lint and all
+#[allow(clippy::let_and_return)]
 mod utils;
 
 #[link(wasm_import_module = "env")]
@@ -15,11 +18,454 @@ extern "C" {
     fn get_input_file(ptr: i32, len: i32);
 }
 
-const WALLET_REGEX: &str = r"0x[a-fA-F0-9]{40}";
+const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
 
-fn extract_wallet_address(text: &str) -> Option<String> {
+/// Like `utils::call_blockchain_service`, but reads the host's error payload into a
+/// `ChainError` instead of dropping it.
+fn query_blockchain(request: &str) -> Result<Vec<u8>, ChainError> {
+    let mut response = vec![0u8; MAX_RESPONSE_SIZE + 4];
+
+    let code = unsafe {
+        call_blockchain(
+            request.as_ptr() as i32,
+            request.len() as i32,
+            response.as_mut_ptr() as i32,
+            response.len() as i32
+        )
+    };
+
+    // The host leaves the buffer empty when not even its error payload fits
+    let data_len = (u32::from_le_bytes(response[..4].try_into().unwrap()) as usize).min(MAX_RESPONSE_SIZE);
+    let payload = &response[4..data_len + 4];
+
+    if code == 0 {
+        Ok(payload.to_vec())
+    } else {
+        Err(ChainError::from_payload(payload, code))
+    }
+}
+
+// Word boundaries keep 64-digit transaction hashes from matching as addresses
+const WALLET_REGEX: &str = r"\b0x[a-fA-F0-9]{40}\b";
+
+const TX_HASH_REGEX: &str = r"\b0x[a-fA-F0-9]{64}\b";
+
+fn extract_transaction_hash(text: &str) -> Option<String> {
+    let re = Regex::new(TX_HASH_REGEX).unwrap();
+    re.find(text).map(|m| m.as_str().to_lowercase())
+}
+
+const TRANSFER_REGEX: &str = r"(?i)\b(?:send|transfer|pay)\s+([0-9]+(?:\.[0-9]+)?)\s+([a-z][a-z0-9]*)\s+to\s+(0x[a-fA-F0-9]{40})\b";
+
+const SENDER_REGEX: &str = r"(?i)\bfrom\s+(0x[a-fA-F0-9]{40})\b";
+
+/// A transfer the user asked for: amount, symbol, recipient and, if given, sender.
+struct TransferIntent {
+    amount: String,
+    symbol: String,
+    to: String,
+    from: Option<String>,
+}
+
+fn extract_transfer(text: &str) -> Option<TransferIntent> {
+    let captures = Regex::new(TRANSFER_REGEX).unwrap().captures(text)?;
+    let from = Regex::new(SENDER_REGEX).unwrap()
+        .captures(text)
+        .map(|captures| captures[1].to_string());
+    Some(TransferIntent {
+        amount: captures[1].to_string(),
+        symbol: captures[2].to_uppercase(),
+        to: captures[3].to_string(),
+        from,
+    })
+}
+
+fn extract_wallet_address(text: &str) -> Option<Result<Address, AddressError>> {
     let re = Regex::new(WALLET_REGEX).unwrap();
-    re.find(text).map(|m| m.as_str().to_string())
+    re.find(text).map(|m| m.as_str().parse())
+}
+
+fn is_transactions_request(text: &str) -> bool {
+    let text = text.to_lowercase();
+    ["transaction", "history", "activity", "recent"].iter().any(|keyword| text.contains(keyword))
+}
+
+// Whole words only, so "coffee", "feedback" or "Las Vegas" don't read as fee questions
+const GAS_REGEX: &str = r"(?i)\b(?:gas|fees?|cost to send|transfer cost)\b";
+
+fn is_gas_request(text: &str) -> bool {
+    Regex::new(GAS_REGEX).unwrap().is_match(text)
+}
+
+/// The network's native currency, as `get_network` reports it from the host's configuration.
+#[derive(Deserialize, Debug)]
+struct NativeCurrency {
+    #[serde(rename = "native_symbol")]
+    symbol: String,
+    decimals: u8,
+}
+
+impl NativeCurrency {
+    /// Ask the host for the default network's currency, assuming UOMI if it can't say.
+    fn fetch() -> Self {
+        let request = serde_json::json!({ "action": "get_network" });
+        match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(native) => return native,
+                Err(e) => log(&format!("Invalid network info: {}", e)),
+            },
+            Err(e) => log(&format!("Error fetching network info: {:?}", e)),
+        }
+        NativeCurrency { symbol: "UOMI".to_string(), decimals: 18 }
+    }
+}
+
+/// Format a raw integer amount for display, truncating (never rounding up) to `precision`.
+fn display_amount(raw: &str, decimals: u8, precision: u8) -> String {
+    match Amount::from_raw(raw, decimals) {
+        Ok(amount) => amount.format(&FormatOptions::new().precision(precision).thousands_separator(',')),
+        Err(e) => {
+            log(&format!("Invalid amount {:?}: {}", raw, e));
+            "unknown".to_string()
+        }
+    }
+}
+
+/// A token balance in whole units, or in raw units when the token's decimals are unknown.
+fn display_token_amount(token: &TokenInfo) -> String {
+    match token.decimals {
+        Some(decimals) => display_amount(&token.balance, decimals, 2),
+        None => format!("{} raw units", token.balance),
+    }
+}
+
+/// USD value of a raw native amount at `rate`, with cents.
+fn display_usd(raw: &str, decimals: u8, rate: f64) -> Option<String> {
+    let value = Amount::from_raw(raw, decimals).ok()?.value_at(&rate.to_string()).ok()?;
+    Some(value.format(&FormatOptions::new().precision(2).thousands_separator(',').pad_zeros()))
+}
+
+/// A reply explaining why fetching `what` failed.
+fn describe_error(what: &str, error: &ChainError) -> String {
+    match error.kind {
+        ErrorKind::InvalidAddress => format!("That doesn't look like a valid address: {}", error.message),
+        ErrorKind::NotFound => format!("I couldn't find {}.", what),
+        ErrorKind::RateLimited => format!("The explorer is rate limiting requests, so I couldn't fetch {}. Please try again in a minute.", what),
+        ErrorKind::Timeout => format!("The explorer took too long to answer, so I couldn't fetch {}. Please try again.", what),
+        ErrorKind::UpstreamError => match error.status {
+            Some(status) => format!("The explorer returned an error ({}) while fetching {}. Please try again later.", status, what),
+            None => format!("The explorer is unreachable right now, so I couldn't fetch {}. Please try again later.", what),
+        },
+        _ => format!("Sorry, I couldn't fetch {} at the moment. Please try again later.", what),
+    }
+}
+
+/// A user-facing message for an error payload written by `call_ai` in place of a model response.
+fn describe_ai_error(error: &AiError) -> String {
+    match error.code.as_str() {
+        "quota_exceeded" => "I've reached my AI usage limit for this request, so I can't answer that right now.".to_string(),
+        "model_unavailable" => "The AI model is unavailable right now. Please try again in a few minutes.".to_string(),
+        _ => "Sorry, I couldn't get an answer from the AI model. Please try again later.".to_string(),
+    }
+}
+
+fn save_response(response_text: &str) {
+    let json_response = serde_json::json!({
+        "response": response_text,
+        "time_taken": 0.5, // Placeholder - could be calculated
+        "tokens_per_second": 50.0, // Placeholder
+        "total_tokens_generated": 100 // Placeholder
+    });
+    let response_json = serde_json::to_string(&json_response).unwrap();
+    utils::save_output(response_json.as_bytes());
+}
+
+fn handle_transactions_request(wallet_address: &str) {
+    log(&format!("Processing transactions request for wallet: {}", wallet_address));
+
+    let request = serde_json::json!({
+        "action": "get_transactions",
+        "address": wallet_address
+    });
+    let page = match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+        Ok(data) => serde_json::from_slice::<TransactionPage>(&data).ok(),
+        Err(e) => {
+            log(&format!("Error fetching transactions: {:?}", e));
+            save_response(&describe_error("the transaction history", &e));
+            return;
+        }
+    };
+
+    let page = match page {
+        Some(page) => page,
+        None => {
+            save_response("Sorry, I couldn't fetch the transaction history at the moment. Please try again later.");
+            return;
+        }
+    };
+
+    if page.items.is_empty() {
+        save_response(&format!("No transactions found for {}.", wallet_address));
+        return;
+    }
+
+    let native = NativeCurrency::fetch();
+    let mut response_parts = vec![format!("Recent transactions for {}:", wallet_address)];
+    for tx in page.items.iter().take(10) {
+        let direction = if tx.from.eq_ignore_ascii_case(wallet_address) { "OUT" } else { "IN" };
+        let counterparty = if direction == "OUT" {
+            tx.to.clone().unwrap_or_else(|| "contract creation".to_string())
+        } else {
+            tx.from.clone()
+        };
+        response_parts.push(format!(
+            "\n{} {} {} {} {} {}\n  {} | method: {} | status: {}",
+            tx.timestamp.as_deref().unwrap_or("pending"),
+            direction,
+            display_amount(&tx.value, native.decimals, 6),
+            native.symbol,
+            if direction == "OUT" { "to" } else { "from" },
+            counterparty,
+            tx.hash,
+            tx.method.as_deref().unwrap_or("transfer"),
+            tx.status.as_deref().unwrap_or("pending"),
+        ));
+    }
+    if page.next_cursor.is_some() {
+        response_parts.push("\nMore transactions are available.".to_string());
+    }
+
+    save_response(&response_parts.join("\n"));
+}
+
+fn handle_transaction_lookup(hash: &str) {
+    log(&format!("Processing transaction lookup: {}", hash));
+
+    let request = serde_json::json!({
+        "action": "get_transaction",
+        "hash": hash
+    });
+    let tx = match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+        Ok(data) => serde_json::from_slice::<TransactionDetails>(&data).ok(),
+        Err(e) => {
+            log(&format!("Error fetching transaction: {:?}", e));
+            save_response(&describe_error(&format!("transaction {}", hash), &e));
+            return;
+        }
+    };
+    let tx = match tx {
+        Some(tx) => tx,
+        None => {
+            save_response(&format!("Sorry, I couldn't find transaction {}.", hash));
+            return;
+        }
+    };
+
+    let native = NativeCurrency::fetch();
+    let mut response_parts = vec![
+        format!("Transaction {}:", tx.hash),
+        format!("\nStatus: {}", tx.status),
+        format!("Block: {}", tx.block_number.map(|n| n.to_string()).unwrap_or_else(|| "pending".to_string())),
+        format!("From: {}", tx.from),
+        format!("To: {}", tx.to.as_deref().or(tx.contract_address.as_deref()).unwrap_or("contract creation")),
+        format!("Value: {} {}", display_amount(&tx.value, native.decimals, 6), native.symbol),
+    ];
+    if let Some(fee) = tx.fee.as_deref() {
+        let gas_used = tx.gas_used.as_deref().unwrap_or("unknown");
+        response_parts.push(format!(
+            "Fee: {} {} (gas used: {})",
+            display_amount(fee, native.decimals, 8),
+            native.symbol,
+            gas_used
+        ));
+    }
+
+    if let Some(method) = &tx.method {
+        response_parts.push(format!("\nMethod: {}", method.signature));
+        for argument in &method.arguments {
+            let name = argument.get("name").and_then(|name| name.as_str()).unwrap_or_default();
+            let value = match argument.get("value") {
+                Some(serde_json::Value::String(value)) => value.clone(),
+                Some(value) => value.to_string(),
+                None => String::new(),
+            };
+            response_parts.push(format!("- {}: {}", name, value));
+        }
+    }
+
+    if !tx.token_transfers.is_empty() {
+        response_parts.push("\nToken Transfers:".to_string());
+        for transfer in &tx.token_transfers {
+            let symbol = transfer.symbol.as_deref().unwrap_or(&transfer.token);
+            let amount = match (&transfer.value_formatted, &transfer.token_id) {
+                (_, Some(token_id)) => format!("#{}", token_id),
+                (Some(value), None) => value.clone(),
+                (None, None) => transfer.value.clone().unwrap_or_default(),
+            };
+            response_parts.push(format!("- {} {} from {} to {}", amount, symbol, transfer.from, transfer.to));
+        }
+    }
+
+    save_response(&response_parts.join("\n"));
+}
+
+/// Lines explaining a contract: its proxy setup and verified source. Falls back to a bare
+/// note when the lookup fails.
+fn describe_contract(address: &str) -> Vec<String> {
+    let request = serde_json::json!({
+        "action": "get_contract",
+        "address": address
+    });
+    let contract = match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+        Ok(data) => serde_json::from_slice::<ContractSummary>(&data).ok(),
+        Err(e) => {
+            log(&format!("Error fetching contract: {:?}", e));
+            None
+        }
+    };
+    let contract = match contract {
+        Some(contract) => contract,
+        None => return vec!["\nNote: This is a smart contract address".to_string()],
+    };
+
+    let mut lines = vec![format!("\nSmart Contract: {}", contract.name.as_deref().unwrap_or("unnamed"))];
+    if let Some(proxy) = &contract.proxy {
+        let kind = proxy.proxy_type.as_deref().unwrap_or("unknown");
+        match &proxy.implementation {
+            Some(implementation) => lines.push(format!("Proxy ({}) delegating to {}", kind, implementation)),
+            None => lines.push(format!("Proxy ({})", kind)),
+        }
+        if let Some(admin) = &proxy.admin {
+            lines.push(format!("Upgradeable by admin {}", admin));
+        }
+    }
+
+    // For a proxy, the implementation holds the logic worth describing
+    match contract.implementation_source.as_ref().or(contract.source.as_ref()) {
+        Some(source) => {
+            lines.push(format!(
+                "Verified source: {} ({}, {})",
+                source.name.as_deref().unwrap_or("unnamed"),
+                source.compiler_version.as_deref().unwrap_or("unknown compiler"),
+                source.license_type.as_deref().unwrap_or("no license")
+            ));
+            lines.push(format!(
+                "{} read functions, {} write functions, {} events",
+                source.read_functions.len(),
+                source.write_functions.len(),
+                source.events.len()
+            ));
+            if !source.write_functions.is_empty() {
+                let shown: Vec<&str> = source.write_functions.iter().take(8).map(String::as_str).collect();
+                lines.push(format!("Write functions: {}", shown.join(", ")));
+            }
+        }
+        None => lines.push("Source: not verified".to_string()),
+    }
+    for note in &contract.notes {
+        lines.push(format!("Note: {}", note));
+    }
+    lines
+}
+
+fn handle_transfer_request(intent: TransferIntent) {
+    log(&format!("Preparing transfer of {} {} to {}", intent.amount, intent.symbol, intent.to));
+
+    let native = NativeCurrency::fetch();
+    if !intent.symbol.eq_ignore_ascii_case(&native.symbol) {
+        save_response(&format!("I can only prepare {} transfers for now, not {}.", native.symbol, intent.symbol));
+        return;
+    }
+    for address in std::iter::once(&intent.to).chain(intent.from.as_ref()) {
+        if let Err(e) = address.parse::<Address>() {
+            save_response(&format!("That doesn't look like a valid address: {}", e));
+            return;
+        }
+    }
+
+    let request = serde_json::json!({
+        "action": "prepare_transaction",
+        "to": intent.to,
+        "from": intent.from,
+        "amount": intent.amount
+    });
+    let prepared = match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+        Ok(data) => serde_json::from_slice::<PreparedTransaction>(&data).ok(),
+        Err(e) => {
+            log(&format!("Error preparing transaction: {:?}", e));
+            save_response(&describe_error("what's needed to prepare this transfer", &e));
+            return;
+        }
+    };
+    let prepared = match prepared {
+        Some(prepared) => prepared,
+        None => {
+            save_response("Sorry, I couldn't prepare this transfer. Please try again later.");
+            return;
+        }
+    };
+
+    let usd = prepared.max_fee_cost_usd.as_deref().map(|usd| format!(" (${} USD)", usd)).unwrap_or_default();
+    let mut response_parts = vec![
+        format!("Here is a transaction sending {} {} to {}.", prepared.amount, native.symbol, prepared.recipient),
+        format!(
+            "\nMaximum network fee: {} {}{}",
+            display_amount(&prepared.max_fee_cost, native.decimals, 8),
+            native.symbol,
+            usd
+        ),
+        format!("\nOpen it in your wallet: {}", prepared.payment_uri),
+        format!("\nOr sign these parameters in your wallet:\n{}", serde_json::to_string_pretty(&prepared.transaction).unwrap()),
+    ];
+    for warning in &prepared.warnings {
+        response_parts.push(format!("\nWarning: {}", warning));
+    }
+    response_parts.push("\nI never hold your keys: review and sign it in your own wallet.".to_string());
+
+    save_response(&response_parts.join("\n"));
+}
+
+fn handle_gas_request() {
+    log("Processing gas price request");
+
+    let request = serde_json::json!({ "action": "get_gas" });
+    let gas = match query_blockchain(&serde_json::to_string(&request).unwrap()) {
+        Ok(data) => serde_json::from_slice::<GasReport>(&data).ok(),
+        Err(e) => {
+            log(&format!("Error fetching gas prices: {:?}", e));
+            save_response(&describe_error("current gas prices", &e));
+            return;
+        }
+    };
+    let gas = match gas {
+        Some(gas) => gas,
+        None => {
+            save_response("Sorry, I couldn't read the current gas prices. Please try again later.");
+            return;
+        }
+    };
+
+    let mut response_parts = vec![format!(
+        "Current gas price: {} gwei",
+        gas.gas_price_gwei.as_deref().unwrap_or(&gas.gas_price)
+    )];
+    if let Some(base_fee) = &gas.base_fee_gwei {
+        response_parts.push(format!("Base fee: {} gwei", base_fee));
+    }
+    let native = NativeCurrency::fetch();
+    response_parts.push(format!("\nA {} transfer would cost:", native.symbol));
+    for tier in &gas.tiers {
+        let usd = tier.cost_usd.as_deref().map(|usd| format!(" (${} USD)", usd)).unwrap_or_default();
+        response_parts.push(format!(
+            "- {}: {} {}{}",
+            tier.name,
+            display_amount(&tier.cost, native.decimals, 8),
+            native.symbol,
+            usd
+        ));
+    }
+
+    save_response(&response_parts.join("\n"));
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,7 +478,7 @@ struct Message {
 struct TokenInfo {
     balance: String,
     contract_address: String,
-    decimals: u8,
+    decimals: Option<u8>,
     name: String,
     symbol: String,
     type_token: String,
@@ -43,6 +489,120 @@ struct TokenResponse {
     items: Vec<TokenInfo>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+struct TransactionSummary {
+    hash: String,
+    from: String,
+    to: Option<String>,
+    value: String,
+    method: Option<String>,
+    status: Option<String>,
+    timestamp: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TransactionPage {
+    items: Vec<TransactionSummary>,
+    next_cursor: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct DecodedCall {
+    signature: String,
+    arguments: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TokenTransfer {
+    token: String,
+    symbol: Option<String>,
+    from: String,
+    to: String,
+    value: Option<String>,
+    value_formatted: Option<String>,
+    token_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct TransactionDetails {
+    hash: String,
+    status: String,
+    block_number: Option<u64>,
+    from: String,
+    to: Option<String>,
+    contract_address: Option<String>,
+    value: String,
+    gas_used: Option<String>,
+    fee: Option<String>,
+    method: Option<DecodedCall>,
+    token_transfers: Vec<TokenTransfer>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PreparedTransaction {
+    recipient: String,
+    amount: String,
+    max_fee_cost: String,
+    max_fee_cost_usd: Option<String>,
+    transaction: serde_json::Value,
+    payment_uri: String,
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct FeeTier {
+    name: String,
+    cost: String,
+    cost_usd: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct GasReport {
+    gas_price: String,
+    gas_price_gwei: Option<String>,
+    base_fee_gwei: Option<String>,
+    tiers: Vec<FeeTier>,
+}
+
+/// `{"error": {"code": ..., "message": ...}}` written by `call_ai` when the model isn't called
+/// or fails.
+#[derive(Serialize, Deserialize, Debug)]
+struct AiErrorPayload {
+    error: AiError,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct AiError {
+    code: String,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ProxyInfo {
+    proxy_type: Option<String>,
+    implementation: Option<String>,
+    admin: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct VerifiedSource {
+    name: Option<String>,
+    compiler_version: Option<String>,
+    license_type: Option<String>,
+    read_functions: Vec<String>,
+    write_functions: Vec<String>,
+    events: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct ContractSummary {
+    name: Option<String>,
+    proxy: Option<ProxyInfo>,
+    source: Option<VerifiedSource>,
+    implementation_source: Option<VerifiedSource>,
+    notes: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct ExplorerResponse {
     block_number_balance_updated_at: u64,
@@ -63,13 +623,40 @@ pub extern "C" fn run() {
     
     // Get the last user message
     let last_message = messages.iter()
-        .filter(|msg| msg.role == "user")
-        .last()
+        .rfind(|msg| msg.role == "user")
         .map(|msg| msg.content.clone())
         .unwrap_or_default();
     
-    // Check if it's a balance request
-    if let Some(wallet_address) = extract_wallet_address(&last_message) {
+    if let Some(hash) = extract_transaction_hash(&last_message) {
+        handle_transaction_lookup(&hash);
+        return;
+    }
+
+    if let Some(intent) = extract_transfer(&last_message) {
+        handle_transfer_request(intent);
+        return;
+    }
+
+    // Addresses are shown and sent in checksummed form
+    let wallet_address = match extract_wallet_address(&last_message) {
+        Some(Ok(address)) => Some(address.to_checksum()),
+        Some(Err(e)) => {
+            save_response(&format!("That doesn't look like a valid address: {}", e));
+            return;
+        }
+        None => None,
+    };
+
+    if wallet_address.is_none() && is_gas_request(&last_message) {
+        handle_gas_request();
+        return;
+    }
+
+    // Check if it's a transaction history request
+    if let Some(wallet_address) = wallet_address.as_deref().filter(|_| is_transactions_request(&last_message)) {
+        handle_transactions_request(wallet_address);
+    } else if let Some(wallet_address) = wallet_address {
+        // Balance request
         log(&format!("Processing balance request for wallet: {}", wallet_address));
         
         // Call blockchain service
@@ -78,23 +665,26 @@ pub extern "C" fn run() {
             "address": wallet_address
         });
         
-        let response = utils::call_blockchain_service(&serde_json::to_string(&balance_request).unwrap());
+        let response = query_blockchain(&serde_json::to_string(&balance_request).unwrap());
         
         match response {
             Ok(balance_data) => {
                 // Parse balance response
                 if let Ok(balance) = serde_json::from_slice::<ExplorerResponse>(&balance_data) {
-                    // Convert wei to ETH (1 ETH = 10^18 wei)
-                    let eth_balance = balance.coin_balance.parse::<f64>().unwrap_or_default() / 1e18;
-                    
+                    let native = NativeCurrency::fetch();
                     let mut response_parts = vec![
                         format!("Wallet Balance for {}:", balance.hash),
-                        format!("\nNative Balance: {:.6} UOMI", eth_balance)
+                        format!(
+                            "\nNative Balance: {} {}",
+                            display_amount(&balance.coin_balance, native.decimals, 6),
+                            native.symbol
+                        )
                     ];
 
-                    if let Some(rate) = balance.exchange_rate {
-                        let usd_value = eth_balance * rate;
-                        response_parts.push(format!("(${:.2} USD)", usd_value));
+                    if let Some(usd_value) = balance.exchange_rate
+                        .and_then(|rate| display_usd(&balance.coin_balance, native.decimals, rate))
+                    {
+                        response_parts.push(format!("(${} USD)", usd_value));
                     }
 
                     // Get token balances
@@ -103,7 +693,7 @@ pub extern "C" fn run() {
                         "address": balance.hash
                     });
 
-                    if let Ok(token_data) = utils::call_blockchain_service(&serde_json::to_string(&token_request).unwrap()) {
+                    if let Ok(token_data) = query_blockchain(&serde_json::to_string(&token_request).unwrap()) {
                         if let Ok(tokens) = serde_json::from_slice::<TokenResponse>(&token_data) {
                             response_parts.push("\nToken Balances:".to_string());
                             
@@ -112,16 +702,16 @@ pub extern "C" fn run() {
                             for token in &tokens.items {
                                 if token.symbol.to_uppercase() == "UOMI" {
                                     found_uomi = true;
-                                    let token_balance = token.balance.parse::<f64>().unwrap_or_default() / 10f64.powi(token.decimals as i32);
-                                    response_parts.push(format!("🔹 UOMI: {:.2} tokens", token_balance));
+                                    let token_balance = display_token_amount(token);
+                                    response_parts.push(format!("🔹 UOMI: {} tokens", token_balance));
                                 }
                             }
 
                             // Show other tokens
                             for token in &tokens.items {
                                 if token.symbol.to_uppercase() != "UOMI" {
-                                    let token_balance = token.balance.parse::<f64>().unwrap_or_default() / 10f64.powi(token.decimals as i32);
-                                    response_parts.push(format!("- {} ({}): {:.2}", token.name, token.symbol, token_balance));
+                                    let token_balance = display_token_amount(token);
+                                    response_parts.push(format!("- {} ({}): {}", token.name, token.symbol, token_balance));
                                 }
                             }
 
@@ -134,7 +724,7 @@ pub extern "C" fn run() {
                     response_parts.push(format!("\nLast updated at block: {}", balance.block_number_balance_updated_at));
                     
                     if balance.is_contract {
-                        response_parts.push("\nNote: This is a smart contract address".to_string());
+                        response_parts.extend(describe_contract(&balance.hash));
                     }
 
                     let response_text = response_parts.join("\n");
@@ -163,7 +753,7 @@ pub extern "C" fn run() {
             Err(e) => {
                 log(&format!("Error fetching balance: {:?}", e));
                 let error_response = serde_json::json!({
-                    "response": "Sorry, I couldn't fetch your balance at the moment. Please try again later.",
+                    "response": describe_error("your balance", &e),
                     "time_taken": 0.1,
                     "tokens_per_second": 10.0,
                     "total_tokens_generated": 20
@@ -182,6 +772,11 @@ pub extern "C" fn run() {
         
         let request = utils::prepare_request(&modified_messages_str);
         let response = utils::call_ai_service(1, request);
+        if let Ok(payload) = serde_json::from_slice::<AiErrorPayload>(&response) {
+            log(&format!("AI call failed: {} ({})", payload.error.code, payload.error.message));
+            save_response(&describe_ai_error(&payload.error));
+            return;
+        }
         
         // Ensure AI response is also JSON formatted
         let ai_response_str = String::from_utf8_lossy(&response);
@@ -203,3 +798,73 @@ pub extern "C" fn run() {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gas_requests() {
+        assert!(is_gas_request("What's the gas price?"));
+        assert!(is_gas_request("How high are FEES right now"));
+        assert!(is_gas_request("what fee would I pay"));
+        assert!(is_gas_request("What is the cost to send 1 UOMI?"));
+    }
+
+    #[test]
+    fn words_containing_gas_or_fee_are_not_gas_requests() {
+        assert!(!is_gas_request("I feel lucky"));
+        assert!(!is_gas_request("Any feedback on my wallet?"));
+        assert!(!is_gas_request("Buy me a coffee"));
+        assert!(!is_gas_request("Greetings from Las Vegas"));
+        assert!(!is_gas_request("The gasket is leaking"));
+    }
+
+    const ADDRESS: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const HASH: &str = "0x88df016429689c079f3b2f6ad39fa052532c56795b733da78a91ebe6a713944b";
+
+    #[test]
+    fn pasted_hashes_are_transactions_not_wallets() {
+        let text = format!("What happened in {}?", HASH);
+        assert_eq!(extract_transaction_hash(&text).as_deref(), Some(HASH));
+        assert!(extract_wallet_address(&text).is_none());
+        assert!(!Regex::new(WALLET_REGEX).unwrap().is_match(&HASH[..HASH.len() - 2]));
+        // Uppercase hex is accepted and lowercased
+        assert_eq!(extract_transaction_hash(&HASH.to_uppercase().replace("0X", "0x")).as_deref(), Some(HASH));
+    }
+
+    #[test]
+    fn wallet_addresses_are_checksum_validated() {
+        let text = format!("Balance of {} please", ADDRESS);
+        assert_eq!(extract_wallet_address(&text).unwrap().unwrap().to_string(), ADDRESS);
+        assert!(extract_transaction_hash(&text).is_none());
+        // All-lowercase addresses carry no checksum
+        assert!(extract_wallet_address(&ADDRESS.to_lowercase()).unwrap().is_ok());
+        let typo = ADDRESS.replace("5aAeb", "5AAeb");
+        assert!(matches!(extract_wallet_address(&typo), Some(Err(AddressError::InvalidChecksum))));
+    }
+
+    #[test]
+    fn transfer_requests() {
+        let intent = extract_transfer(&format!("send 5 UOMI to {}", ADDRESS)).unwrap();
+        assert_eq!((intent.amount.as_str(), intent.symbol.as_str(), intent.to.as_str()), ("5", "UOMI", ADDRESS));
+        assert_eq!(intent.from, None);
+
+        let intent = extract_transfer(&format!("Please transfer 0.25 usdc to {} from {}", ADDRESS, ADDRESS.to_lowercase())).unwrap();
+        assert_eq!((intent.amount.as_str(), intent.symbol.as_str()), ("0.25", "USDC"));
+        assert_eq!(intent.from.as_deref(), Some(ADDRESS.to_lowercase().as_str()));
+
+        assert_eq!(extract_transfer(&format!("PAY 1.5 uomi to {}.", ADDRESS)).unwrap().amount, "1.5");
+    }
+
+    #[test]
+    fn text_that_is_not_a_transfer() {
+        // An address running into more hex digits is not an address
+        assert!(extract_transfer(&format!("send 5 UOMI to {}ab", ADDRESS)).is_none());
+        assert!(extract_transfer(&format!("send 5 UOMI to {}", HASH)).is_none());
+        assert!(extract_transfer(&format!("send UOMI to {}", ADDRESS)).is_none());
+        assert!(extract_transfer(&format!("send 5. UOMI to {}", ADDRESS)).is_none());
+        assert!(extract_transfer(&format!("send .5 UOMI to {}", ADDRESS)).is_none());
+        assert!(extract_transfer(&format!("resend 5 UOMI to {}", ADDRESS)).is_none());
+        assert!(extract_transfer("send 5 UOMI to my friend").is_none());
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+use crate::keccak256;
+
+/// A 20-byte account address. Parsing is strict, and mixed-case input must carry a
+/// valid EIP-55 checksum. Displays in checksummed form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Address([u8; 20]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    MissingPrefix,
+    InvalidLength(usize),
+    InvalidCharacter { character: char, position: usize },
+    InvalidChecksum,
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::MissingPrefix => write!(f, "address must start with 0x"),
+            AddressError::InvalidLength(length) => {
+                write!(f, "address must have 40 hex characters after 0x, got {}", length)
+            }
+            AddressError::InvalidCharacter { character, position } => {
+                write!(f, "invalid hex character '{}' at position {}", character, position)
+            }
+            AddressError::InvalidChecksum => {
+                write!(f, "checksum does not match; check the address for typos")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AddressError {}
+
+impl Address {
+    pub fn from_bytes(bytes: [u8; 20]) -> Self {
+        Address(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 20] {
+        &self.0
+    }
+
+    /// EIP-55 checksummed representation.
+    pub fn to_checksum(&self) -> String {
+        let lower: String = self.0.iter().map(|b| format!("{:02x}", b)).collect();
+        let hash = keccak256(lower.as_bytes());
+
+        let mut checksummed = String::with_capacity(42);
+        checksummed.push_str("0x");
+        for (i, c) in lower.chars().enumerate() {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            checksummed.push(if nibble >= 8 { c.to_ascii_uppercase() } else { c });
+        }
+        checksummed
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let hex = text.strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .ok_or(AddressError::MissingPrefix)?;
+        if hex.len() != 40 {
+            return Err(AddressError::InvalidLength(hex.len()));
+        }
+        if let Some((position, character)) = hex.char_indices().find(|(_, c)| !c.is_ascii_hexdigit()) {
+            return Err(AddressError::InvalidCharacter { character, position: position + 2 });
+        }
+
+        let mut bytes = [0u8; 20];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| AddressError::InvalidLength(hex.len()))?;
+        }
+        let address = Address(bytes);
+
+        // All-lowercase and all-uppercase input carries no checksum
+        let mixed_case = hex.chars().any(|c| c.is_ascii_lowercase()) && hex.chars().any(|c| c.is_ascii_uppercase());
+        // A mismatch usually means a mistyped address, so don't suggest the "correct" form
+        if mixed_case && address.to_checksum()[2..] != *hex {
+            return Err(AddressError::InvalidChecksum);
+        }
+        Ok(address)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_checksum())
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_checksum())
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Test vectors from EIP-55.
+    const EIP55_VECTORS: &[&str] = &[
+        "0x52908400098527886E0F7030069857D2E4169EE7",
+        "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+        "0xde709f2102306220921060314715629080e2fb77",
+        "0x27b1fdb04752bbc536007a920d24acb045561c26",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ];
+
+    #[test]
+    fn checksums_eip55_vectors() {
+        for vector in EIP55_VECTORS {
+            let address: Address = vector.parse().unwrap();
+            assert_eq!(address.to_checksum(), *vector);
+        }
+    }
+
+    #[test]
+    fn accepts_single_case_input() {
+        let expected = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+        let lower: Address = expected.to_lowercase().parse().unwrap();
+        let upper: Address = format!("0x{}", &expected[2..].to_uppercase()).parse().unwrap();
+        assert_eq!(lower.to_checksum(), expected);
+        assert_eq!(upper, lower);
+    }
+
+    #[test]
+    fn rejects_wrong_checksum() {
+        // Last letter's case flipped
+        let error = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD".parse::<Address>().unwrap_err();
+        assert_eq!(error, AddressError::InvalidChecksum);
+        assert!(!error.to_string().contains("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed".parse::<Address>(), Err(AddressError::MissingPrefix));
+        assert_eq!("0x5aAeb6".parse::<Address>(), Err(AddressError::InvalidLength(6)));
+        assert_eq!(
+            "0xgaaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse::<Address>(),
+            Err(AddressError::InvalidCharacter { character: 'g', position: 2 })
+        );
+    }
+}
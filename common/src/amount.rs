@@ -0,0 +1,252 @@
+use primitive_types::U256;
+use std::fmt;
+
+/// Fraction digits kept from a price in [`Amount::value_at`].
+const MAX_PRICE_DECIMALS: usize = 18;
+
+/// An exact token amount: an integer in the token's smallest unit together with
+/// the token's decimals. Never goes through floating point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Amount {
+    raw: U256,
+    decimals: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AmountError {
+    Empty,
+    InvalidCharacter { character: char, position: usize },
+    TooManyDecimals { decimals: u8, found: usize },
+    Overflow,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Empty => write!(f, "amount is empty"),
+            AmountError::InvalidCharacter { character, position } => {
+                write!(f, "invalid character '{}' at position {}", character, position)
+            }
+            AmountError::TooManyDecimals { decimals, found } => {
+                write!(f, "amount has {} fraction digits, the token only has {}", found, decimals)
+            }
+            AmountError::Overflow => write!(f, "amount does not fit in 256 bits"),
+        }
+    }
+}
+
+impl std::error::Error for AmountError {}
+
+/// How [`Amount::format`] renders an amount.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FormatOptions {
+    /// Maximum fraction digits. Extra digits are truncated, never rounded up. `None` keeps all.
+    pub precision: Option<u8>,
+    /// Separator between groups of three integer digits.
+    pub thousands_separator: Option<char>,
+    /// Keep trailing zeros up to `precision` (`1.50` rather than `1.5`).
+    pub pad_zeros: bool,
+}
+
+impl FormatOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn precision(mut self, precision: u8) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    pub fn thousands_separator(mut self, separator: char) -> Self {
+        self.thousands_separator = Some(separator);
+        self
+    }
+
+    pub fn pad_zeros(mut self) -> Self {
+        self.pad_zeros = true;
+        self
+    }
+}
+
+impl Amount {
+    pub fn new(raw: U256, decimals: u8) -> Self {
+        Amount { raw, decimals }
+    }
+
+    /// Parse a raw integer amount, as decimal digits or a `0x` hex quantity.
+    pub fn from_raw(raw: &str, decimals: u8) -> Result<Self, AmountError> {
+        let raw = raw.trim();
+        let value = match raw.strip_prefix("0x") {
+            Some("") => return Err(AmountError::Empty),
+            Some(hex) => {
+                check_digits(hex, 2, |c| c.is_ascii_hexdigit())?;
+                U256::from_str_radix(hex, 16).map_err(|_| AmountError::Overflow)?
+            }
+            None => parse_decimal_digits(raw)?,
+        };
+        Ok(Amount::new(value, decimals))
+    }
+
+    /// Parse a human-readable amount such as `1.5` into smallest units. More fraction
+    /// digits than `decimals` is an error rather than a silent truncation.
+    pub fn from_decimal(text: &str, decimals: u8) -> Result<Self, AmountError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(AmountError::Empty);
+        }
+        let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if fraction.len() > decimals as usize {
+            return Err(AmountError::TooManyDecimals { decimals, found: fraction.len() });
+        }
+        check_digits(fraction, integer.len() + 1, |c| c.is_ascii_digit())?;
+
+        let digits = format!("{}{:0<width$}", integer, fraction, width = decimals as usize);
+        Ok(Amount::new(parse_decimal_digits(&digits)?, decimals))
+    }
+
+    pub fn raw(&self) -> U256 {
+        self.raw
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.decimals
+    }
+
+    /// Exact value of this amount at `price` per whole unit, e.g. its USD value at an
+    /// exchange rate. Price digits beyond the 18th fraction digit are dropped.
+    pub fn value_at(&self, price: &str) -> Result<Amount, AmountError> {
+        let price = price.trim();
+        let (integer, fraction) = price.split_once('.').unwrap_or((price, ""));
+        check_digits(fraction, integer.len() + 1, |c| c.is_ascii_digit())?;
+        let fraction = &fraction[..fraction.len().min(MAX_PRICE_DECIMALS)];
+
+        let scaled_price = parse_decimal_digits(&format!("{}{}", integer, fraction))?;
+        let raw = self.raw.checked_mul(scaled_price).ok_or(AmountError::Overflow)?;
+        let decimals = self.decimals.checked_add(fraction.len() as u8).ok_or(AmountError::Overflow)?;
+        Ok(Amount::new(raw, decimals))
+    }
+
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let digits = self.raw.to_string();
+        let decimals = self.decimals as usize;
+        let digits = format!("{:0>width$}", digits, width = decimals + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - decimals);
+
+        let mut fraction = match options.precision {
+            Some(precision) => fraction[..fraction.len().min(precision as usize)].to_string(),
+            None => fraction.to_string(),
+        };
+        if options.pad_zeros {
+            if let Some(precision) = options.precision {
+                fraction = format!("{:0<width$}", fraction, width = precision as usize);
+            }
+        } else {
+            fraction.truncate(fraction.trim_end_matches('0').len());
+        }
+
+        let integer = match options.thousands_separator {
+            Some(separator) => group_thousands(integer, separator),
+            None => integer.to_string(),
+        };
+        if fraction.is_empty() {
+            integer
+        } else {
+            format!("{}.{}", integer, fraction)
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(&FormatOptions::default()))
+    }
+}
+
+fn parse_decimal_digits(digits: &str) -> Result<U256, AmountError> {
+    if digits.is_empty() {
+        return Err(AmountError::Empty);
+    }
+    check_digits(digits, 0, |c| c.is_ascii_digit())?;
+    U256::from_dec_str(digits).map_err(|_| AmountError::Overflow)
+}
+
+fn check_digits(digits: &str, offset: usize, valid: impl Fn(char) -> bool) -> Result<(), AmountError> {
+    match digits.char_indices().find(|(_, c)| !valid(*c)) {
+        Some((position, character)) => Err(AmountError::InvalidCharacter { character, position: position + offset }),
+        None => Ok(()),
+    }
+}
+
+fn group_thousands(integer: &str, separator: char) -> String {
+    let mut grouped = String::with_capacity(integer.len() + integer.len() / 3);
+    for (i, c) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i).is_multiple_of(3) {
+            grouped.push(separator);
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_decimal_amounts() {
+        for (text, decimals, raw) in [
+            ("0", 18, "0"),
+            ("1", 18, "1000000000000000000"),
+            ("1.5", 18, "1500000000000000000"),
+            ("0.000000000000000001", 18, "1"),
+            ("123.456789", 6, "123456789"),
+            ("0.000001", 6, "1"),
+            ("42", 0, "42"),
+        ] {
+            let amount = Amount::from_decimal(text, decimals).unwrap();
+            assert_eq!(amount.raw(), U256::from_dec_str(raw).unwrap(), "{}", text);
+            assert_eq!(amount.to_string(), text);
+            assert_eq!(Amount::from_decimal(&amount.to_string(), decimals).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn round_trips_u256_max() {
+        let max = Amount::new(U256::MAX, 18);
+        let text = max.to_string();
+        assert_eq!(text, "115792089237316195423570985008687907853269984665640564039457.584007913129639935");
+        assert_eq!(Amount::from_decimal(&text, 18).unwrap(), max);
+        assert_eq!(Amount::from_raw(&U256::MAX.to_string(), 0).unwrap().raw(), U256::MAX);
+        assert_eq!(Amount::from_decimal("115792089237316195423570985008687907853269984665640564039458", 18), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn rejects_too_many_fraction_digits() {
+        assert_eq!(Amount::from_decimal("1.0000001", 6), Err(AmountError::TooManyDecimals { decimals: 6, found: 7 }));
+        assert_eq!(Amount::from_decimal("0.5", 0), Err(AmountError::TooManyDecimals { decimals: 0, found: 1 }));
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        assert_eq!(Amount::from_decimal("", 18), Err(AmountError::Empty));
+        assert_eq!(Amount::from_decimal("1.2x", 18), Err(AmountError::InvalidCharacter { character: 'x', position: 3 }));
+        assert_eq!(Amount::from_decimal("-1", 18), Err(AmountError::InvalidCharacter { character: '-', position: 0 }));
+        assert_eq!(Amount::from_raw("0x", 18), Err(AmountError::Empty));
+    }
+
+    #[test]
+    fn parses_raw_hex_and_decimal() {
+        assert_eq!(Amount::from_raw("0xde0b6b3a7640000", 18).unwrap().to_string(), "1");
+        assert_eq!(Amount::from_raw("2500000", 6).unwrap().to_string(), "2.5");
+    }
+
+    #[test]
+    fn formats_with_options() {
+        let amount = Amount::from_decimal("1234567.891", 18).unwrap();
+        assert_eq!(amount.format(&FormatOptions::new().precision(2)), "1234567.89");
+        assert_eq!(amount.format(&FormatOptions::new().precision(4).pad_zeros()), "1234567.8910");
+        assert_eq!(amount.format(&FormatOptions::new().precision(0).thousands_separator(',')), "1,234,567");
+        assert_eq!(Amount::new(U256::zero(), 6).format(&FormatOptions::new().precision(2).pad_zeros()), "0.00");
+    }
+}
@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Why a `call_blockchain` request failed. Each kind has its own return code, and its
+/// snake_case name is the `code` of the error payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Any failure not covered by a more specific kind.
+    #[serde(rename = "blockchain_error")]
+    Other,
+    UnknownAction,
+    InvalidAddress,
+    InvalidRequest,
+    UnknownNetwork,
+    NotFound,
+    /// The explorer or node answered 429 Too Many Requests.
+    RateLimited,
+    Timeout,
+    /// The explorer or node failed, answered with an error status or is cut off by the
+    /// host's circuit breaker.
+    UpstreamError,
+    /// The node rejected the JSON-RPC request, e.g. a reverted `eth_call`.
+    RpcError,
+    AbiError,
+    /// The host signer refused the transaction under its policy.
+    PolicyRejected,
+    /// The explorer or node answered with a body that couldn't be parsed.
+    InvalidResponse,
+    /// The host's configuration doesn't allow the request, e.g. a network without `rpc_url`.
+    ConfigError,
+    /// The host signer couldn't sign, e.g. a missing keystore or a wrong password.
+    SignerError,
+}
+
+impl ErrorKind {
+    const ALL: [ErrorKind; 15] = [
+        ErrorKind::Other,
+        ErrorKind::UnknownAction,
+        ErrorKind::InvalidAddress,
+        ErrorKind::InvalidRequest,
+        ErrorKind::UnknownNetwork,
+        ErrorKind::NotFound,
+        ErrorKind::RateLimited,
+        ErrorKind::Timeout,
+        ErrorKind::UpstreamError,
+        ErrorKind::RpcError,
+        ErrorKind::AbiError,
+        ErrorKind::PolicyRejected,
+        ErrorKind::InvalidResponse,
+        ErrorKind::ConfigError,
+        ErrorKind::SignerError,
+    ];
+
+    /// Return code of `call_blockchain`; `0` is success.
+    pub fn code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::UnknownAction => 2,
+            ErrorKind::InvalidAddress => 3,
+            ErrorKind::InvalidRequest => 4,
+            ErrorKind::UnknownNetwork => 5,
+            ErrorKind::NotFound => 6,
+            ErrorKind::RateLimited => 7,
+            ErrorKind::Timeout => 8,
+            ErrorKind::UpstreamError => 9,
+            ErrorKind::RpcError => 10,
+            ErrorKind::AbiError => 11,
+            ErrorKind::PolicyRejected => 12,
+            ErrorKind::InvalidResponse => 13,
+            ErrorKind::ConfigError => 14,
+            ErrorKind::SignerError => 15,
+        }
+    }
+
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.code() == code)
+    }
+
+    /// Whether the same request may succeed if sent again later.
+    pub fn is_transient(self) -> bool {
+        matches!(self, ErrorKind::RateLimited | ErrorKind::Timeout | ErrorKind::UpstreamError)
+    }
+}
+
+/// The error payload `call_blockchain` writes to the output buffer, inside `{"error": ...}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainError {
+    #[serde(rename = "code")]
+    pub kind: ErrorKind,
+    pub message: String,
+    /// HTTP status of an upstream error.
+    #[serde(default)]
+    pub status: Option<u16>,
+    /// How long to wait before retrying, when known.
+    #[serde(default)]
+    pub retry_after_ms: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorPayload {
+    error: ChainError,
+}
+
+impl ChainError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        ChainError { kind, message: message.into(), status: None, retry_after_ms: None }
+    }
+
+    /// The `{"error": ...}` payload.
+    pub fn to_payload(&self) -> Vec<u8> {
+        serde_json::to_vec(&ErrorPayload { error: self.clone() }).unwrap_or_default()
+    }
+
+    /// Read the payload written alongside a non-zero return `code`. Falls back to the
+    /// kind the code stands for if the payload can't be parsed.
+    pub fn from_payload(payload: &[u8], code: i32) -> Self {
+        match serde_json::from_slice::<ErrorPayload>(payload) {
+            Ok(payload) => payload.error,
+            Err(_) => ChainError::new(
+                ErrorKind::from_code(code).unwrap_or(ErrorKind::Other),
+                format!("blockchain call failed with code {}", code),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ChainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_kind_round_trips_through_its_code() {
+        for kind in ErrorKind::ALL {
+            assert_eq!(ErrorKind::from_code(kind.code()), Some(kind));
+        }
+        assert_eq!(ErrorKind::from_code(0), None);
+        assert_eq!(ErrorKind::from_code(ErrorKind::ALL.len() as i32 + 1), None);
+    }
+
+    #[test]
+    fn codes_are_unique_and_non_zero() {
+        let mut codes: Vec<i32> = ErrorKind::ALL.iter().map(|kind| kind.code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), ErrorKind::ALL.len());
+        assert!(codes.iter().all(|code| *code > 0));
+    }
+
+    #[test]
+    fn all_lists_every_kind() {
+        // Exhaustive on purpose: a new kind stops this compiling until it is counted here
+        // and, through the assertion below, added to `ALL`
+        fn listed(kind: ErrorKind) -> usize {
+            match kind {
+                ErrorKind::Other
+                | ErrorKind::UnknownAction
+                | ErrorKind::InvalidAddress
+                | ErrorKind::InvalidRequest
+                | ErrorKind::UnknownNetwork
+                | ErrorKind::NotFound
+                | ErrorKind::RateLimited
+                | ErrorKind::Timeout
+                | ErrorKind::UpstreamError
+                | ErrorKind::RpcError
+                | ErrorKind::AbiError
+                | ErrorKind::PolicyRejected
+                | ErrorKind::InvalidResponse
+                | ErrorKind::ConfigError
+                | ErrorKind::SignerError => 15,
+            }
+        }
+        assert_eq!(ErrorKind::ALL.len(), listed(ErrorKind::Other));
+        for code in 1..=ErrorKind::ALL.len() as i32 {
+            assert!(ErrorKind::from_code(code).is_some(), "no kind for code {}", code);
+        }
+    }
+
+    #[test]
+    fn payload_round_trips() {
+        let mut error = ChainError::new(ErrorKind::RateLimited, "slow down");
+        error.status = Some(429);
+        error.retry_after_ms = Some(1500);
+        assert_eq!(ChainError::from_payload(&error.to_payload(), error.kind.code()), error);
+    }
+
+    #[test]
+    fn non_json_payload_falls_back_to_the_code() {
+        let error = ChainError::from_payload(b"Failed to call blockchain service", ErrorKind::Timeout.code());
+        assert_eq!(error.kind, ErrorKind::Timeout);
+        assert_eq!(error.message, "blockchain call failed with code 8");
+
+        let error = ChainError::from_payload(b"", 99);
+        assert_eq!(error.kind, ErrorKind::Other);
+    }
+}
@@ -0,0 +1,20 @@
+//! Types shared by the host and the agent.
+
+mod address;
+mod amount;
+mod error;
+
+pub use address::{Address, AddressError};
+pub use amount::{Amount, AmountError, FormatOptions};
+pub use error::{ChainError, ErrorKind};
+pub use primitive_types::U256;
+
+use tiny_keccak::{Hasher, Keccak};
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
@@ -0,0 +1,1032 @@
+use primitive_types::U256;
+use serde::Deserialize;
+use thiserror::Error;
+use uomi_common::{keccak256, Address};
+
+#[derive(Error, Debug)]
+pub enum AbiError {
+    #[error("Invalid type: {0}")]
+    InvalidType(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid value for {kind}: {value}")]
+    InvalidValue { kind: String, value: String },
+    #[error("Expected {expected} arguments, got {actual}")]
+    ArgumentCount { expected: usize, actual: usize },
+    #[error("Function not found in ABI: {0}")]
+    FunctionNotFound(String),
+    #[error("Output data too short to decode {0}")]
+    DataTooShort(String),
+    #[error("Type too large to encode: {0}")]
+    TooLarge(String),
+    #[error("Decoded data has more than {0} values")]
+    TooManyValues(usize),
+    #[error("Event {event} expects {expected} topics, log has {actual}")]
+    TopicCount { event: String, expected: usize, actual: usize },
+}
+
+/// Largest length accepted for a fixed-size array type such as `uint256[N]`.
+const MAX_FIXED_ARRAY_SIZE: usize = 65_536;
+
+/// Most values one `decode` may produce. Dynamic offsets can point many arrays at the same
+/// bytes, so the data length alone doesn't bound the output.
+const MAX_DECODED_VALUES: usize = 100_000;
+
+/// A Solidity ABI type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamType {
+    Address,
+    Bool,
+    Uint(usize),
+    Int(usize),
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<ParamType>),
+    FixedArray(Box<ParamType>, usize),
+    Tuple(Vec<Param>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub kind: ParamType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    pub outputs: Vec<Param>,
+}
+
+/// An event, with the `indexed` flag of each input.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub name: String,
+    pub inputs: Vec<(Param, bool)>,
+}
+
+/// Events decoded without the caller supplying a signature. ERC-20 and ERC-721 share
+/// topic hashes and are told apart by how many topics a log carries.
+const KNOWN_EVENTS: &[&str] = &[
+    "Transfer(address indexed from, address indexed to, uint256 value)",
+    "Transfer(address indexed from, address indexed to, uint256 indexed tokenId)",
+    "Approval(address indexed owner, address indexed spender, uint256 value)",
+    "Approval(address indexed owner, address indexed approved, uint256 indexed tokenId)",
+    "ApprovalForAll(address indexed owner, address indexed operator, bool approved)",
+];
+
+/// Local 4-byte table: common functions decoded when no verified ABI is available.
+const KNOWN_FUNCTIONS: &[&str] = &[
+    "transfer(address to, uint256 value)",
+    "transferFrom(address from, address to, uint256 value)",
+    "approve(address spender, uint256 value)",
+    "increaseAllowance(address spender, uint256 addedValue)",
+    "decreaseAllowance(address spender, uint256 subtractedValue)",
+    "safeTransferFrom(address from, address to, uint256 tokenId)",
+    "safeTransferFrom(address from, address to, uint256 tokenId, bytes data)",
+    "safeTransferFrom(address from, address to, uint256 id, uint256 value, bytes data)",
+    "safeBatchTransferFrom(address from, address to, uint256[] ids, uint256[] values, bytes data)",
+    "setApprovalForAll(address operator, bool approved)",
+    "mint(address to, uint256 amount)",
+    "burn(uint256 amount)",
+    "deposit()",
+    "withdraw(uint256 amount)",
+    "multicall(bytes[] data)",
+    "swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline)",
+    "swapTokensForExactTokens(uint256 amountOut, uint256 amountInMax, address[] path, address to, uint256 deadline)",
+    "swapExactETHForTokens(uint256 amountOutMin, address[] path, address to, uint256 deadline)",
+    "swapExactTokensForETH(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline)",
+    "addLiquidity(address tokenA, address tokenB, uint256 amountADesired, uint256 amountBDesired, uint256 amountAMin, uint256 amountBMin, address to, uint256 deadline)",
+    "removeLiquidity(address tokenA, address tokenB, uint256 liquidity, uint256 amountAMin, uint256 amountBMin, address to, uint256 deadline)",
+];
+
+/// An entry of a JSON ABI, as emitted by solc.
+#[derive(Debug, Deserialize)]
+pub struct AbiEntry {
+    #[serde(rename = "type", default)]
+    pub entry_type: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub inputs: Vec<AbiParam>,
+    #[serde(default)]
+    pub outputs: Vec<AbiParam>,
+    /// `pure`, `view`, `nonpayable` or `payable`; older compilers set `constant` instead.
+    #[serde(rename = "stateMutability", default)]
+    pub state_mutability: String,
+    #[serde(default)]
+    pub constant: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AbiParam {
+    #[serde(default)]
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub components: Vec<AbiParam>,
+    /// Set on event inputs.
+    #[serde(default)]
+    pub indexed: bool,
+}
+
+impl ParamType {
+    /// Parse a type such as `uint256`, `address[]`, `bytes32[2]` or `(uint256,address)[]`.
+    pub fn parse(kind: &str) -> Result<ParamType, AbiError> {
+        let kind = kind.trim();
+        if let Some(inner) = kind.strip_suffix(']') {
+            let open = inner.rfind('[').ok_or_else(|| AbiError::InvalidType(kind.to_string()))?;
+            let element = Box::new(ParamType::parse(&inner[..open])?);
+            let size = &inner[open + 1..];
+            return if size.is_empty() {
+                Ok(ParamType::Array(element))
+            } else {
+                let size = fixed_array_size(size).ok_or_else(|| AbiError::InvalidType(kind.to_string()))?;
+                Ok(ParamType::FixedArray(element, size))
+            };
+        }
+        if let Some(inner) = kind.strip_prefix('(').and_then(|k| k.strip_suffix(')')) {
+            // An empty tuple takes no space, so arrays of it would expand without reading any data
+            let params = parse_params(inner)?;
+            if params.is_empty() {
+                return Err(AbiError::InvalidType(kind.to_string()));
+            }
+            return Ok(ParamType::Tuple(params));
+        }
+
+        let sized = |prefix: &str, default: usize, max: usize| -> Result<Option<usize>, AbiError> {
+            match kind.strip_prefix(prefix) {
+                Some("") => Ok(Some(default)),
+                Some(size) => match size.parse::<usize>() {
+                    Ok(size) if size > 0 && size <= max && (prefix == "bytes" || size.is_multiple_of(8)) => Ok(Some(size)),
+                    _ => Err(AbiError::InvalidType(kind.to_string())),
+                },
+                None => Ok(None),
+            }
+        };
+
+        match kind {
+            "address" => Ok(ParamType::Address),
+            "bool" => Ok(ParamType::Bool),
+            "string" => Ok(ParamType::String),
+            "bytes" => Ok(ParamType::Bytes),
+            _ => {
+                if let Some(bits) = sized("uint", 256, 256)? {
+                    Ok(ParamType::Uint(bits))
+                } else if let Some(bits) = sized("int", 256, 256)? {
+                    Ok(ParamType::Int(bits))
+                } else if let Some(size) = sized("bytes", 32, 32)? {
+                    Ok(ParamType::FixedBytes(size))
+                } else {
+                    Err(AbiError::InvalidType(kind.to_string()))
+                }
+            }
+        }
+    }
+
+    fn from_abi(param: &AbiParam) -> Result<ParamType, AbiError> {
+        match param.kind.strip_prefix("tuple") {
+            Some(suffix) => {
+                if param.components.is_empty() {
+                    return Err(AbiError::InvalidType(param.kind.clone()));
+                }
+                let components = param.components.iter()
+                    .map(Param::from_abi)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut kind = ParamType::Tuple(components);
+                // Wrap in one array level per `[]` / `[n]` suffix, innermost first
+                for dimension in suffix.split_inclusive(']') {
+                    let size = dimension.trim_start_matches('[').trim_end_matches(']');
+                    kind = if size.is_empty() {
+                        ParamType::Array(Box::new(kind))
+                    } else {
+                        let size = fixed_array_size(size).ok_or_else(|| AbiError::InvalidType(param.kind.clone()))?;
+                        ParamType::FixedArray(Box::new(kind), size)
+                    };
+                }
+                Ok(kind)
+            }
+            None => ParamType::parse(&param.kind),
+        }
+    }
+
+    /// Canonical type string used in function signatures.
+    pub fn canonical(&self) -> String {
+        match self {
+            ParamType::Address => "address".to_string(),
+            ParamType::Bool => "bool".to_string(),
+            ParamType::Uint(bits) => format!("uint{}", bits),
+            ParamType::Int(bits) => format!("int{}", bits),
+            ParamType::FixedBytes(size) => format!("bytes{}", size),
+            ParamType::Bytes => "bytes".to_string(),
+            ParamType::String => "string".to_string(),
+            ParamType::Array(element) => format!("{}[]", element.canonical()),
+            ParamType::FixedArray(element, size) => format!("{}[{}]", element.canonical(), size),
+            ParamType::Tuple(params) => format!("({})", canonical_list(params)),
+        }
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => true,
+            ParamType::FixedArray(element, _) => element.is_dynamic(),
+            ParamType::Tuple(params) => params.iter().any(|p| p.kind.is_dynamic()),
+            _ => false,
+        }
+    }
+
+    /// Size of the type in the head of an encoding. Nested fixed arrays can multiply
+    /// past `usize`, which is reported instead of overflowing.
+    fn head_size(&self) -> Result<usize, AbiError> {
+        let too_large = || AbiError::TooLarge(self.canonical());
+        match self {
+            _ if self.is_dynamic() => Ok(32),
+            ParamType::FixedArray(element, size) => element.head_size()?.checked_mul(*size).ok_or_else(too_large),
+            ParamType::Tuple(params) => total_head_size(params.iter().map(|p| &p.kind)).map_err(|_| too_large()),
+            _ => Ok(32),
+        }
+    }
+}
+
+fn total_head_size<'a>(kinds: impl IntoIterator<Item = &'a ParamType>) -> Result<usize, AbiError> {
+    let mut total: usize = 0;
+    for kind in kinds {
+        total = total.checked_add(kind.head_size()?).ok_or_else(|| AbiError::TooLarge(kind.canonical()))?;
+    }
+    Ok(total)
+}
+
+/// The `N` of `T[N]`, if it is a positive size no larger than [`MAX_FIXED_ARRAY_SIZE`].
+fn fixed_array_size(size: &str) -> Option<usize> {
+    size.parse().ok().filter(|size| (1..=MAX_FIXED_ARRAY_SIZE).contains(size))
+}
+
+impl Param {
+    fn from_abi(param: &AbiParam) -> Result<Param, AbiError> {
+        Ok(Param { name: param.name.clone(), kind: ParamType::from_abi(param)? })
+    }
+}
+
+fn canonical_list(params: &[Param]) -> String {
+    params.iter().map(|p| p.kind.canonical()).collect::<Vec<_>>().join(",")
+}
+
+/// Parse a comma-separated parameter list. Each entry is a type optionally followed
+/// by modifiers and a name, e.g. `address indexed owner`.
+fn parse_params(list: &str) -> Result<Vec<Param>, AbiError> {
+    split_params(list).into_iter()
+        .map(|entry| {
+            let (kind, name) = split_type_and_name(entry)?;
+            Ok(Param { name, kind: ParamType::parse(kind)? })
+        })
+        .collect()
+}
+
+/// Split a parameter list at commas outside of parentheses.
+fn split_params(list: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let list = list.trim();
+    if list.is_empty() {
+        return entries;
+    }
+
+    for (i, c) in list.char_indices().chain(std::iter::once((list.len(), ','))) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(list[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+fn split_type_and_name(entry: &str) -> Result<(&str, String), AbiError> {
+    // Tuple types contain spaces inside their parentheses, so split after the last ')'
+    let type_end = match entry.rfind(')') {
+        Some(close) => close + entry[close..].find(' ').unwrap_or(entry.len() - close),
+        None => entry.find(' ').unwrap_or(entry.len()),
+    };
+    let kind = entry[..type_end].trim();
+    if kind.is_empty() {
+        return Err(AbiError::InvalidSignature(entry.to_string()));
+    }
+    let name = entry[type_end..]
+        .split_whitespace()
+        .rfind(|word| !matches!(*word, "memory" | "calldata" | "storage" | "indexed"))
+        .unwrap_or_default()
+        .to_string();
+    Ok((kind, name))
+}
+
+impl Function {
+    /// Parse a human-readable signature: `balanceOf(address)`, `balanceOf(address)(uint256)`
+    /// or `function balanceOf(address owner) view returns (uint256)`.
+    pub fn parse(signature: &str) -> Result<Function, AbiError> {
+        let invalid = || AbiError::InvalidSignature(signature.to_string());
+        let signature = signature.trim().trim_start_matches("function ").trim();
+        let open = signature.find('(').ok_or_else(invalid)?;
+        let name = signature[..open].trim().to_string();
+        let close = matching_paren(signature, open).ok_or_else(invalid)?;
+        let inputs = parse_params(&signature[open + 1..close])?;
+
+        let rest = &signature[close + 1..];
+        let outputs = match rest.find('(') {
+            Some(open) => {
+                let close = matching_paren(rest, open).ok_or_else(invalid)?;
+                parse_params(&rest[open + 1..close])?
+            }
+            None => Vec::new(),
+        };
+
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Function { name, inputs, outputs })
+    }
+
+    /// Pick a function from a JSON ABI: a single fragment, or an array searched by `name`.
+    pub fn from_abi(abi: &serde_json::Value, name: Option<&str>) -> Result<Function, AbiError> {
+        let entries: Vec<AbiEntry> = match abi {
+            serde_json::Value::Array(_) => serde_json::from_value(abi.clone()),
+            _ => serde_json::from_value(abi.clone()).map(|entry| vec![entry]),
+        }
+        .map_err(|e| AbiError::InvalidSignature(e.to_string()))?;
+
+        let entry = entries.iter()
+            .filter(|entry| entry.entry_type.is_empty() || entry.entry_type == "function")
+            .find(|entry| name.is_none_or(|name| entry.name == name || Function::matches_signature(entry, name)))
+            .ok_or_else(|| AbiError::FunctionNotFound(name.unwrap_or_default().to_string()))?;
+        Function::from_entry(entry)
+    }
+
+    pub fn from_entry(entry: &AbiEntry) -> Result<Function, AbiError> {
+        Ok(Function {
+            name: entry.name.clone(),
+            inputs: entry.inputs.iter().map(Param::from_abi).collect::<Result<_, _>>()?,
+            outputs: entry.outputs.iter().map(Param::from_abi).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The function of a JSON ABI whose selector starts `calldata`, if any.
+    pub fn from_abi_by_selector(abi: &serde_json::Value, calldata: &[u8]) -> Option<Function> {
+        let entries: Vec<AbiEntry> = serde_json::from_value(abi.clone()).ok()?;
+        entries.iter()
+            .filter(|entry| entry.entry_type == "function")
+            .filter_map(|entry| Function::from_entry(entry).ok())
+            .find(|function| calldata.starts_with(&function.selector()))
+    }
+
+    /// Look up the selector of `calldata` in the local table of common functions.
+    pub fn known(calldata: &[u8]) -> Option<Function> {
+        KNOWN_FUNCTIONS.iter()
+            .filter_map(|signature| Function::parse(signature).ok())
+            .find(|function| calldata.starts_with(&function.selector()))
+    }
+
+    /// Lets callers pick an overload from an ABI by its full signature.
+    fn matches_signature(entry: &AbiEntry, signature: &str) -> bool {
+        Function::from_entry(entry).map(|f| f.signature() == signature).unwrap_or(false)
+    }
+
+    /// Canonical signature, e.g. `transfer(address,uint256)`.
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, canonical_list(&self.inputs))
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        let hash = keccak256(self.signature().as_bytes());
+        [hash[0], hash[1], hash[2], hash[3]]
+    }
+
+    /// Calldata for a call with JSON arguments.
+    pub fn encode_call(&self, args: &[serde_json::Value]) -> Result<Vec<u8>, AbiError> {
+        if args.len() != self.inputs.len() {
+            return Err(AbiError::ArgumentCount { expected: self.inputs.len(), actual: args.len() });
+        }
+        let kinds: Vec<ParamType> = self.inputs.iter().map(|p| p.kind.clone()).collect();
+        let mut data = self.selector().to_vec();
+        data.extend(encode(&kinds, args)?);
+        Ok(data)
+    }
+
+    /// Decode return data into `{name, type, value}` entries.
+    pub fn decode_output(&self, data: &[u8]) -> Result<Vec<serde_json::Value>, AbiError> {
+        decode_named(&self.outputs, data)
+    }
+
+    /// Decode the arguments of calldata (selector included) into `{name, type, value}` entries.
+    pub fn decode_input(&self, calldata: &[u8]) -> Result<Vec<serde_json::Value>, AbiError> {
+        let data = calldata.get(4..).ok_or_else(|| AbiError::DataTooShort(self.signature()))?;
+        decode_named(&self.inputs, data)
+    }
+}
+
+fn decode_named(params: &[Param], data: &[u8]) -> Result<Vec<serde_json::Value>, AbiError> {
+    let kinds: Vec<ParamType> = params.iter().map(|p| p.kind.clone()).collect();
+    let values = decode(&kinds, data)?;
+    Ok(params.iter().zip(values)
+        .map(|(param, value)| serde_json::json!({
+            "name": param.name,
+            "type": param.kind.canonical(),
+            "value": value,
+        }))
+        .collect())
+}
+
+impl Event {
+    /// Parse a human-readable event: `Transfer(address indexed from, address indexed to, uint256 value)`,
+    /// optionally prefixed with `event`.
+    pub fn parse(signature: &str) -> Result<Event, AbiError> {
+        let invalid = || AbiError::InvalidSignature(signature.to_string());
+        let signature = signature.trim().trim_start_matches("event ").trim();
+        let open = signature.find('(').ok_or_else(invalid)?;
+        let name = signature[..open].trim().to_string();
+        let close = matching_paren(signature, open).ok_or_else(invalid)?;
+        if name.is_empty() {
+            return Err(invalid());
+        }
+
+        let inputs = split_params(&signature[open + 1..close]).into_iter()
+            .map(|entry| {
+                let (kind, name) = split_type_and_name(entry)?;
+                let indexed = entry[kind.len()..].split_whitespace().any(|word| word == "indexed");
+                Ok((Param { name, kind: ParamType::parse(kind)? }, indexed))
+            })
+            .collect::<Result<_, AbiError>>()?;
+        Ok(Event { name, inputs })
+    }
+
+    pub fn from_entry(entry: &AbiEntry) -> Result<Event, AbiError> {
+        Ok(Event {
+            name: entry.name.clone(),
+            inputs: entry.inputs.iter()
+                .map(|param| Ok((Param::from_abi(param)?, param.indexed)))
+                .collect::<Result<_, AbiError>>()?,
+        })
+    }
+
+    /// The built-in ERC-20/721 events, optionally only those called `name`.
+    pub fn known(name: Option<&str>) -> Vec<Event> {
+        KNOWN_EVENTS.iter()
+            .filter_map(|signature| Event::parse(signature).ok())
+            .filter(|event| name.is_none_or(|name| event.name == name))
+            .collect()
+    }
+
+    /// Canonical signature, e.g. `Transfer(address,address,uint256)`.
+    pub fn signature(&self) -> String {
+        let kinds: Vec<String> = self.inputs.iter().map(|(param, _)| param.kind.canonical()).collect();
+        format!("{}({})", self.name, kinds.join(","))
+    }
+
+    /// `topics[0]` of a log emitted by this event.
+    pub fn topic(&self) -> [u8; 32] {
+        keccak256(self.signature().as_bytes())
+    }
+
+    /// Decode a log into `{name, type, indexed, value}` entries. Indexed strings, bytes,
+    /// arrays and tuples are only stored as their hash, which is returned as-is.
+    pub fn decode_log(&self, topics: &[Vec<u8>], data: &[u8]) -> Result<Vec<serde_json::Value>, AbiError> {
+        let indexed_count = self.inputs.iter().filter(|(_, indexed)| *indexed).count();
+        if topics.len() != indexed_count + 1 || topics[0] != self.topic() {
+            return Err(AbiError::TopicCount {
+                event: self.signature(),
+                expected: indexed_count + 1,
+                actual: topics.len(),
+            });
+        }
+
+        let data_kinds: Vec<ParamType> = self.inputs.iter()
+            .filter(|(_, indexed)| !indexed)
+            .map(|(param, _)| param.kind.clone())
+            .collect();
+        let mut data_values = decode(&data_kinds, data)?.into_iter();
+        let mut topic_values = topics[1..].iter();
+
+        self.inputs.iter()
+            .map(|(param, indexed)| {
+                let value = if *indexed {
+                    let topic = topic_values.next().ok_or_else(|| too_short(&param.kind))?;
+                    if param.kind.is_dynamic() || matches!(param.kind, ParamType::Tuple(_) | ParamType::FixedArray(..)) {
+                        serde_json::Value::String(format!("0x{}", to_hex(topic)))
+                    } else {
+                        decode_value(&param.kind, topic, &mut 1)?
+                    }
+                } else {
+                    data_values.next().ok_or_else(|| too_short(&param.kind))?
+                };
+                Ok(serde_json::json!({
+                    "name": param.name,
+                    "type": param.kind.canonical(),
+                    "indexed": indexed,
+                    "value": value,
+                }))
+            })
+            .collect()
+    }
+}
+
+fn matching_paren(text: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in text[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// ABI-encode a list of values as a tuple.
+pub fn encode(kinds: &[ParamType], values: &[serde_json::Value]) -> Result<Vec<u8>, AbiError> {
+    let head_size = total_head_size(kinds)?;
+    let mut head = Vec::with_capacity(head_size);
+    let mut tail = Vec::new();
+
+    for (kind, value) in kinds.iter().zip(values) {
+        if kind.is_dynamic() {
+            head.extend(word(U256::from(head_size + tail.len())));
+            tail.extend(encode_value(kind, value)?);
+        } else {
+            head.extend(encode_value(kind, value)?);
+        }
+    }
+    head.extend(tail);
+    Ok(head)
+}
+
+fn encode_value(kind: &ParamType, value: &serde_json::Value) -> Result<Vec<u8>, AbiError> {
+    let invalid = || AbiError::InvalidValue { kind: kind.canonical(), value: value.to_string() };
+
+    match kind {
+        ParamType::Address => {
+            let address: Address = value.as_str().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+            Ok(left_pad(address.as_bytes()))
+        }
+        ParamType::Bool => {
+            let flag = match value {
+                serde_json::Value::Bool(flag) => *flag,
+                serde_json::Value::String(text) if text == "true" || text == "false" => text == "true",
+                _ => return Err(invalid()),
+            };
+            Ok(word(U256::from(flag as u8)).to_vec())
+        }
+        ParamType::Uint(bits) => {
+            let number = parse_uint(value).ok_or_else(invalid)?;
+            if number.bits() > *bits {
+                return Err(invalid());
+            }
+            Ok(word(number).to_vec())
+        }
+        ParamType::Int(bits) => {
+            let (negative, magnitude) = parse_int(value).ok_or_else(invalid)?;
+            let limit = U256::one() << (bits - 1);
+            if (!negative && magnitude >= limit) || (negative && magnitude > limit) {
+                return Err(invalid());
+            }
+            let number = if negative { (!magnitude).overflowing_add(U256::one()).0 } else { magnitude };
+            Ok(word(number).to_vec())
+        }
+        ParamType::FixedBytes(size) => {
+            let bytes = hex_bytes(value.as_str().ok_or_else(invalid)?).ok_or_else(invalid)?;
+            if bytes.len() != *size {
+                return Err(invalid());
+            }
+            Ok(right_pad(&bytes))
+        }
+        ParamType::Bytes => {
+            let bytes = hex_bytes(value.as_str().ok_or_else(invalid)?).ok_or_else(invalid)?;
+            Ok(encode_dynamic_bytes(&bytes))
+        }
+        ParamType::String => Ok(encode_dynamic_bytes(value.as_str().ok_or_else(invalid)?.as_bytes())),
+        ParamType::Array(element) => {
+            let items = value.as_array().ok_or_else(invalid)?;
+            let mut encoded = word(U256::from(items.len())).to_vec();
+            encoded.extend(encode(&vec![(**element).clone(); items.len()], items)?);
+            Ok(encoded)
+        }
+        ParamType::FixedArray(element, size) => {
+            let items = value.as_array().filter(|items| items.len() == *size).ok_or_else(invalid)?;
+            encode(&vec![(**element).clone(); *size], items)
+        }
+        ParamType::Tuple(params) => {
+            // Tuples are given as arrays, or as objects keyed by component name
+            let items: Vec<serde_json::Value> = match value {
+                serde_json::Value::Array(items) => items.clone(),
+                serde_json::Value::Object(map) => params.iter()
+                    .map(|p| map.get(&p.name).cloned().ok_or_else(invalid))
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(invalid()),
+            };
+            if items.len() != params.len() {
+                return Err(invalid());
+            }
+            let kinds: Vec<ParamType> = params.iter().map(|p| p.kind.clone()).collect();
+            encode(&kinds, &items)
+        }
+    }
+}
+
+fn encode_dynamic_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = word(U256::from(bytes.len())).to_vec();
+    for chunk in bytes.chunks(32) {
+        encoded.extend(right_pad(chunk));
+    }
+    encoded
+}
+
+/// ABI-decode a tuple of values into JSON. Integers are returned as decimal strings.
+pub fn decode(kinds: &[ParamType], data: &[u8]) -> Result<Vec<serde_json::Value>, AbiError> {
+    let mut budget = MAX_DECODED_VALUES;
+    decode_tuple(kinds, data, &mut budget)
+}
+
+/// Decode `kinds`, taking each value from `budget`.
+fn decode_tuple(kinds: &[ParamType], data: &[u8], budget: &mut usize) -> Result<Vec<serde_json::Value>, AbiError> {
+    // The heads alone must fit in the data, which bounds fixed arrays before they are expanded
+    if total_head_size(kinds)? > data.len() {
+        return Err(kinds.first().map(too_short).unwrap_or_else(|| AbiError::DataTooShort("()".to_string())));
+    }
+    *budget = budget.checked_sub(kinds.len()).ok_or(AbiError::TooManyValues(MAX_DECODED_VALUES))?;
+    let mut values = Vec::with_capacity(kinds.len());
+    let mut offset = 0;
+    for kind in kinds {
+        let value = if kind.is_dynamic() {
+            let start = read_usize(data, offset, kind)?;
+            decode_value(kind, data.get(start..).ok_or_else(|| too_short(kind))?, budget)?
+        } else {
+            decode_value(kind, data.get(offset..).ok_or_else(|| too_short(kind))?, budget)?
+        };
+        values.push(value);
+        offset += kind.head_size()?;
+    }
+    Ok(values)
+}
+
+fn decode_value(kind: &ParamType, data: &[u8], budget: &mut usize) -> Result<serde_json::Value, AbiError> {
+    // Checked before the element types are cloned out for the array
+    let expand = |length: usize| if length > *budget { Err(AbiError::TooManyValues(MAX_DECODED_VALUES)) } else { Ok(()) };
+    match kind {
+        ParamType::Address => {
+            let word = read_word(data, 0, kind)?;
+            let mut bytes = [0u8; 20];
+            bytes.copy_from_slice(&word[12..]);
+            Ok(serde_json::Value::String(Address::from_bytes(bytes).to_checksum()))
+        }
+        ParamType::Bool => Ok(serde_json::Value::Bool(!read_u256(data, 0, kind)?.is_zero())),
+        ParamType::Uint(_) => Ok(serde_json::Value::String(read_u256(data, 0, kind)?.to_string())),
+        ParamType::Int(bits) => {
+            let number = read_u256(data, 0, kind)?;
+            let text = if number.bit(bits - 1) {
+                // Sign-extended two's complement
+                format!("-{}", (!number).overflowing_add(U256::one()).0)
+            } else {
+                number.to_string()
+            };
+            Ok(serde_json::Value::String(text))
+        }
+        ParamType::FixedBytes(size) => {
+            let word = read_word(data, 0, kind)?;
+            Ok(serde_json::Value::String(format!("0x{}", to_hex(&word[..*size]))))
+        }
+        ParamType::Bytes => Ok(serde_json::Value::String(format!("0x{}", to_hex(read_dynamic_bytes(data, kind)?)))),
+        ParamType::String => {
+            Ok(serde_json::Value::String(String::from_utf8_lossy(read_dynamic_bytes(data, kind)?).into_owned()))
+        }
+        ParamType::Array(element) => {
+            let length = read_usize(data, 0, kind)?;
+            // Every element takes at least one word, so a longer length can't be real
+            if length > (data.len() - 32) / 32 {
+                return Err(too_short(kind));
+            }
+            expand(length)?;
+            let items = decode_tuple(&vec![(**element).clone(); length], &data[32..], budget)?;
+            Ok(serde_json::Value::Array(items))
+        }
+        ParamType::FixedArray(element, size) => {
+            expand(*size)?;
+            Ok(serde_json::Value::Array(decode_tuple(&vec![(**element).clone(); *size], data, budget)?))
+        }
+        ParamType::Tuple(params) => {
+            let kinds: Vec<ParamType> = params.iter().map(|p| p.kind.clone()).collect();
+            let values = decode_tuple(&kinds, data, budget)?;
+            if params.iter().all(|p| !p.name.is_empty()) {
+                Ok(serde_json::Value::Object(
+                    params.iter().map(|p| p.name.clone()).zip(values).collect(),
+                ))
+            } else {
+                Ok(serde_json::Value::Array(values))
+            }
+        }
+    }
+}
+
+fn too_short(kind: &ParamType) -> AbiError {
+    AbiError::DataTooShort(kind.canonical())
+}
+
+fn read_word<'a>(data: &'a [u8], offset: usize, kind: &ParamType) -> Result<&'a [u8], AbiError> {
+    data.get(offset..offset + 32).ok_or_else(|| too_short(kind))
+}
+
+fn read_u256(data: &[u8], offset: usize, kind: &ParamType) -> Result<U256, AbiError> {
+    Ok(U256::from_big_endian(read_word(data, offset, kind)?))
+}
+
+fn read_usize(data: &[u8], offset: usize, kind: &ParamType) -> Result<usize, AbiError> {
+    let number = read_u256(data, offset, kind)?;
+    if number > U256::from(data.len()) {
+        return Err(too_short(kind));
+    }
+    Ok(number.as_usize())
+}
+
+fn read_dynamic_bytes<'a>(data: &'a [u8], kind: &ParamType) -> Result<&'a [u8], AbiError> {
+    let length = read_usize(data, 0, kind)?;
+    data.get(32..32 + length).ok_or_else(|| too_short(kind))
+}
+
+fn word(number: U256) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    number.to_big_endian(&mut bytes);
+    bytes
+}
+
+fn left_pad(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = vec![0u8; 32 - bytes.len()];
+    padded.extend_from_slice(bytes);
+    padded
+}
+
+fn right_pad(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    padded.resize(32, 0);
+    padded
+}
+
+/// Accepts JSON numbers, decimal strings and `0x`-prefixed hex strings.
+fn parse_uint(value: &serde_json::Value) -> Option<U256> {
+    match value {
+        serde_json::Value::Number(number) => number.as_u64().map(U256::from),
+        serde_json::Value::String(text) => match text.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok(),
+            None => U256::from_dec_str(text).ok(),
+        },
+        _ => None,
+    }
+}
+
+fn parse_int(value: &serde_json::Value) -> Option<(bool, U256)> {
+    match value {
+        serde_json::Value::Number(number) => {
+            let number = number.as_i64()?;
+            Some((number < 0, U256::from(number.unsigned_abs())))
+        }
+        serde_json::Value::String(text) => match text.strip_prefix('-') {
+            Some(magnitude) => parse_uint(&serde_json::Value::String(magnitude.to_string())).map(|m| (true, m)),
+            None => parse_uint(value).map(|m| (false, m)),
+        },
+        _ => None,
+    }
+}
+
+pub fn hex_bytes(text: &str) -> Option<Vec<u8>> {
+    let hex = text.strip_prefix("0x").unwrap_or(text);
+    // `from_str_radix` would also take a leading `+`
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Concatenate 32-byte words. Short words are left-padded, like integers.
+    fn words(words: &[&str]) -> Vec<u8> {
+        words.iter().flat_map(|word| hex_bytes(&format!("{:0>64}", word)).unwrap()).collect()
+    }
+
+    fn kinds(types: &[&str]) -> Vec<ParamType> {
+        types.iter().map(|kind| ParamType::parse(kind).unwrap()).collect()
+    }
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    #[test]
+    fn computes_selectors() {
+        assert_eq!(Function::parse("balanceOf(address)").unwrap().selector(), [0x70, 0xa0, 0x82, 0x31]);
+        assert_eq!(
+            Function::parse("function transfer(address to, uint256 amount) returns (bool)").unwrap().selector(),
+            [0xa9, 0x05, 0x9c, 0xbb]
+        );
+        let transfer = Event::parse("Transfer(address indexed from, address indexed to, uint256 value)").unwrap();
+        assert_eq!(to_hex(&transfer.topic()), "ddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef");
+    }
+
+    /// `sam(bytes,bool,uint256[])` example from the Solidity ABI specification.
+    #[test]
+    fn encodes_spec_example_with_dynamic_types() {
+        let function = Function::parse("sam(bytes,bool,uint256[])").unwrap();
+        let calldata = function.encode_call(&[json!("0x64617665"), json!(true), json!([1, 2, 3])]).unwrap();
+
+        let mut expected = hex_bytes("a5643bf2").unwrap();
+        expected.extend(words(&[
+            "60",
+            "1",
+            "a0",
+            "4",
+            "6461766500000000000000000000000000000000000000000000000000000000",
+            "3",
+            "1",
+            "2",
+            "3",
+        ]));
+        assert_eq!(calldata, expected);
+    }
+
+    /// `f(uint256,uint32[],bytes10,bytes)` example from the Solidity ABI specification.
+    #[test]
+    fn round_trips_spec_example_with_fixed_bytes() {
+        let types = kinds(&["uint256", "uint32[]", "bytes10", "bytes"]);
+        let values = [json!("0x123"), json!([1110, 1929]), json!("0x31323334353637383930"), json!("0x48656c6c6f2c20776f726c6421")];
+        let encoded = encode(&types, &values).unwrap();
+        assert_eq!(encoded, words(&[
+            "123",
+            "80",
+            "3132333435363738393000000000000000000000000000000000000000000000",
+            "e0",
+            "2",
+            "456",
+            "789",
+            "d",
+            "48656c6c6f2c20776f726c642100000000000000000000000000000000000000",
+        ]));
+
+        assert_eq!(decode(&types, &encoded).unwrap(), vec![
+            json!("291"),
+            json!(["1110", "1929"]),
+            json!("0x31323334353637383930"),
+            json!("0x48656c6c6f2c20776f726c6421"),
+        ]);
+    }
+
+    /// `g(uint256[][],string[])` example from the Solidity ABI specification.
+    #[test]
+    fn round_trips_nested_dynamic_arrays() {
+        let types = kinds(&["uint256[][]", "string[]"]);
+        let values = [json!([[1, 2], [3]]), json!(["one", "two", "three"])];
+        let encoded = encode(&types, &values).unwrap();
+        assert_eq!(encoded, words(&[
+            "40",
+            "140",
+            "2",
+            "40",
+            "a0",
+            "2",
+            "1",
+            "2",
+            "1",
+            "3",
+            "3",
+            "60",
+            "a0",
+            "e0",
+            "3",
+            "6f6e650000000000000000000000000000000000000000000000000000000000",
+            "3",
+            "74776f0000000000000000000000000000000000000000000000000000000000",
+            "5",
+            "7468726565000000000000000000000000000000000000000000000000000000",
+        ]));
+        assert_eq!(decode(&types, &encoded).unwrap(), vec![json!([["1", "2"], ["3"]]), json!(["one", "two", "three"])]);
+    }
+
+    #[test]
+    fn round_trips_nested_tuples() {
+        let types = kinds(&["(uint256 amount,(address owner,bool active)[] entries)"]);
+        let encoded = encode(&types, &[json!([5, [[ALICE, true]]])]).unwrap();
+        assert_eq!(encoded, words(&["20", "5", "40", "1", &ALICE[2..].to_lowercase(), "1"]));
+        assert_eq!(
+            decode(&types, &encoded).unwrap(),
+            vec![json!({ "amount": "5", "entries": [{ "owner": ALICE, "active": true }] })]
+        );
+    }
+
+    #[test]
+    fn round_trips_static_fixed_arrays() {
+        let types = kinds(&["uint8[2][2]", "address"]);
+        let encoded = encode(&types, &[json!([[1, 2], [3, 4]]), json!(ALICE)]).unwrap();
+        assert_eq!(encoded, words(&["1", "2", "3", "4", &ALICE[2..].to_lowercase()]));
+        assert_eq!(decode(&types, &encoded).unwrap(), vec![json!([["1", "2"], ["3", "4"]]), json!(ALICE)]);
+    }
+
+    #[test]
+    fn encodes_signed_integers() {
+        let minus_one = "f".repeat(64);
+        let min_int8 = format!("{}80", "f".repeat(62));
+        assert_eq!(encode(&kinds(&["int256"]), &[json!(-1)]).unwrap(), words(&[&minus_one]));
+        assert_eq!(encode(&kinds(&["int8"]), &[json!("-128")]).unwrap(), words(&[&min_int8]));
+        assert_eq!(encode(&kinds(&["int8"]), &[json!(127)]).unwrap(), words(&["7f"]));
+
+        assert_eq!(decode(&kinds(&["int256"]), &words(&[&minus_one])).unwrap(), vec![json!("-1")]);
+        assert_eq!(decode(&kinds(&["int8"]), &words(&[&min_int8])).unwrap(), vec![json!("-128")]);
+        assert_eq!(decode(&kinds(&["int8"]), &words(&["7f"])).unwrap(), vec![json!("127")]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_values() {
+        assert!(matches!(encode(&kinds(&["int8"]), &[json!(128)]), Err(AbiError::InvalidValue { .. })));
+        assert!(matches!(encode(&kinds(&["int8"]), &[json!(-129)]), Err(AbiError::InvalidValue { .. })));
+        assert!(matches!(encode(&kinds(&["uint8"]), &[json!(256)]), Err(AbiError::InvalidValue { .. })));
+        assert!(matches!(encode(&kinds(&["bytes2"]), &[json!("0x010203")]), Err(AbiError::InvalidValue { .. })));
+        assert!(matches!(encode(&kinds(&["uint8[2]"]), &[json!([1])]), Err(AbiError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn rejects_invalid_types() {
+        for kind in ["uint7", "uint264", "bytes33", "int0", "uint256[0]", "uint256[65537]", "()", "()[2]", "(uint256,())"] {
+            assert!(matches!(ParamType::parse(kind), Err(AbiError::InvalidType(_))), "{}", kind);
+        }
+        assert!(ParamType::parse("uint256[65536]").is_ok());
+    }
+
+    #[test]
+    fn rejects_types_larger_than_memory() {
+        let kind = kinds(&["uint256[65536][65536][65536][65536]"]);
+        assert!(matches!(decode(&kind, &[]), Err(AbiError::TooLarge(_))));
+        assert!(matches!(decode(&kinds(&["uint256[65536][65536]"]), &[]), Err(AbiError::DataTooShort(_))));
+    }
+
+    #[test]
+    fn rejects_short_data() {
+        assert!(matches!(decode(&kinds(&["uint256"]), &[0u8; 31]), Err(AbiError::DataTooShort(_))));
+        // An offset past the end of the data
+        assert!(matches!(decode(&kinds(&["bytes"]), &words(&["40"])), Err(AbiError::DataTooShort(_))));
+        // A string claiming more bytes than follow it
+        assert!(matches!(decode(&kinds(&["string"]), &words(&["20", "40"])), Err(AbiError::DataTooShort(_))));
+    }
+
+    #[test]
+    fn rejects_array_lengths_beyond_the_data() {
+        assert!(matches!(decode(&kinds(&["uint256[]"]), &words(&["20", "3", "1", "2"])), Err(AbiError::DataTooShort(_))));
+        assert!(matches!(decode(&kinds(&["uint256[]"]), &words(&["20", &"f".repeat(64)])), Err(AbiError::DataTooShort(_))));
+    }
+
+    #[test]
+    fn caps_values_from_shared_offsets() {
+        // 400 inner arrays that all point at the same 400 words decode to 160,000 values
+        let length = 400;
+        let mut data = vec!["20".to_string(), format!("{:x}", length)];
+        data.extend(std::iter::repeat_n(format!("{:x}", length * 32), length));
+        data.push(format!("{:x}", length));
+        data.extend(std::iter::repeat_n("1".to_string(), length));
+        let data: Vec<&str> = data.iter().map(String::as_str).collect();
+        assert!(matches!(decode(&kinds(&["uint256[][]"]), &words(&data)), Err(AbiError::TooManyValues(_))));
+    }
+
+    #[test]
+    fn decodes_transfer_log() {
+        let event = Event::parse("Transfer(address indexed from, address indexed to, uint256 value)").unwrap();
+        let topics = vec![event.topic().to_vec(), words(&[&ALICE[2..]]), words(&["1"])];
+        let decoded = event.decode_log(&topics, &words(&["3e8"])).unwrap();
+        assert_eq!(decoded[0]["value"], json!(ALICE));
+        assert_eq!(decoded[1]["value"], json!("0x0000000000000000000000000000000000000001"));
+        assert_eq!(decoded[2], json!({ "name": "value", "type": "uint256", "indexed": false, "value": "1000" }));
+        assert!(matches!(event.decode_log(&topics[..2], &[]), Err(AbiError::TopicCount { .. })));
+    }
+
+    #[test]
+    fn parses_hex_strictly() {
+        assert_eq!(hex_bytes("0x0a0B"), Some(vec![0x0a, 0x0b]));
+        assert_eq!(hex_bytes("ff"), Some(vec![0xff]));
+        assert_eq!(hex_bytes("0x"), Some(Vec::new()));
+        assert_eq!(hex_bytes("0x+1+2"), None);
+        assert_eq!(hex_bytes("0x1"), None);
+        assert_eq!(hex_bytes("0xzz"), None);
+    }
+}
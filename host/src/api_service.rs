@@ -4,9 +4,16 @@ use std::{collections::HashMap, path::Path, time::Duration};
 use thiserror::Error;
 use reqwest::StatusCode;
 
+use crate::audit::AuditRecord;
+use crate::circuit_breaker::{self, CircuitOpen};
+use crate::rate_limiter;
+use crate::usage::TokenUsage;
+
 
 
 const DEFAULT_API_URL: &str = "http://localhost:8888/run";
+/// Longest wait honoured from a provider's `Retry-After`, so a provider can't stall a run.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
 
 #[derive(Error, Debug)]
 pub enum ServiceError {
@@ -29,6 +36,21 @@ pub enum ServiceError {
         status: StatusCode,
         message: String,
     },
+    #[error("{0}")]
+    CircuitOpen(#[from] CircuitOpen),
+}
+
+impl ServiceError {
+    /// Whether the error means the endpoint is down, so a fallback model is worth trying.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            ServiceError::Network(_) | ServiceError::CircuitOpen(_) => true,
+            ServiceError::RequestFailed { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
 }
 
 // Config Structures - come prima...
@@ -40,6 +62,18 @@ pub struct UomiConfig {
     pub models: HashMap<String, ModelConfig>,
     #[serde(default)]
     pub ipfs: IpfsConfig,
+    #[serde(default = "default_agent_id")]
+    pub agent_id: String,
+    #[serde(default)]
+    pub quota: QuotaConfig,
+    #[serde(default)]
+    pub usage: UsageConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreakerConfig,
+    #[serde(default)]
+    pub blockchain: BlockchainConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -57,8 +91,316 @@ pub struct ModelConfig {
     pub name: String,
     pub url: Option<String>,
     pub api_key: Option<String>,  
+    #[serde(default)]
+    pub pricing: ModelPricing,
+    /// Model IDs to try, in order, when this model's endpoint is unavailable.
+    #[serde(default)]
+    pub fallback_models: Vec<String>,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+}
+
+/// Client-side token-bucket limits. Calls over the limit wait for capacity.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u64>,
+}
+
+/// Prices per 1,000 tokens, used to estimate the cost of each call.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ModelPricing {
+    #[serde(default)]
+    pub input_per_1k: f64,
+    #[serde(default)]
+    pub output_per_1k: f64,
+}
+
+/// Limits on AI usage within one run. Unset limits are not enforced. Calls are checked
+/// before they are made, so the call that crosses `max_tokens` or `max_cost` still completes.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct QuotaLimits {
+    pub max_calls: Option<u32>,
+    pub max_tokens: Option<u64>,
+    pub max_cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct QuotaConfig {
+    /// Limits applied to every run.
+    #[serde(flatten)]
+    pub per_run: QuotaLimits,
+    /// Per-run limits for a given `agent_id`, overriding `per_run`. They apply to each
+    /// run of that agent on its own; nothing is budgeted across runs.
+    #[serde(default)]
+    pub agents: HashMap<String, QuotaLimits>,
+}
+
+impl QuotaConfig {
+    /// The per-run limits of `agent_id`.
+    pub fn limits_for(&self, agent_id: &str) -> QuotaLimits {
+        let mut limits = self.per_run.clone();
+        if let Some(agent) = self.agents.get(agent_id) {
+            limits.max_calls = agent.max_calls.or(limits.max_calls);
+            limits.max_tokens = agent.max_tokens.or(limits.max_tokens);
+            limits.max_cost = agent.max_cost.or(limits.max_cost);
+        }
+        limits
+    }
+}
+
+
+/// Where usage accounting is written, relative to the host working directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageConfig {
+    #[serde(default = "default_usage_report_path")]
+    pub report_path: String,
+    #[serde(default = "default_usage_ledger_path")]
+    pub ledger_path: String,
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            report_path: default_usage_report_path(),
+            ledger_path: default_usage_ledger_path(),
+        }
+    }
+}
+
+/// Opt-in JSONL log of every outbound request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_audit_path")]
+    pub path: String,
+    /// JSON fields whose values are replaced before logging (case-insensitive).
+    #[serde(default = "default_redact_fields")]
+    pub redact_fields: Vec<String>,
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: false,
+            path: default_audit_path(),
+            redact_fields: default_redact_fields(),
+            max_body_bytes: default_max_body_bytes(),
+        }
+    }
+}
+
+/// Per-endpoint circuit breaker shared by model and explorer requests.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Consecutive failures before the breaker opens.
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before a probe request is let through.
+    #[serde(default = "default_open_ms")]
+    pub open_ms: u64,
+    #[serde(default = "default_breaker_state_path")]
+    pub state_path: String,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            enabled: true,
+            failure_threshold: default_failure_threshold(),
+            open_ms: default_open_ms(),
+            state_path: default_breaker_state_path(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockchainConfig {
+    /// Limits for requests to the blockchain explorer. JSON-RPC calls use the network's
+    /// `rpc_rate_limit` instead.
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    /// Network used when a request does not name one.
+    #[serde(default = "default_network_name")]
+    pub default_network: String,
+    #[serde(default = "default_networks")]
+    pub networks: HashMap<String, NetworkConfig>,
+    /// Limits for `batch` requests.
+    #[serde(default)]
+    pub batch: BatchConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Host-side transaction signing for operator agents; off by default.
+    #[serde(default)]
+    pub signer: SignerConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SignerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Encrypted Web3 Secret Storage (v3) keystore holding the signing key.
+    #[serde(default = "default_keystore_path")]
+    pub keystore_path: String,
+    /// Environment variable holding the keystore password.
+    #[serde(default = "default_password_env")]
+    pub password_env: String,
+    /// Networks the signer may send on. Empty means none.
+    #[serde(default)]
+    pub networks: Vec<String>,
+    /// Addresses that may receive native coins or tokens.
+    #[serde(default)]
+    pub allowed_recipients: Vec<String>,
+    /// ERC-20 contracts that may be transferred, with their limits in whole tokens.
+    #[serde(default)]
+    pub allowed_contracts: HashMap<String, SpendLimits>,
+    /// Limits on native coins sent, in whole units. Fees count at their maximum.
+    /// Each network has its own daily total.
+    #[serde(default)]
+    pub native_limits: SpendLimits,
+    /// Native limits for specific networks, in their own coin, replacing `native_limits` there.
+    #[serde(default)]
+    pub native_limits_by_network: HashMap<String, SpendLimits>,
+    /// Record of what was sent each day, for the daily limits.
+    #[serde(default = "default_spend_ledger_path")]
+    pub ledger_path: String,
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig {
+            enabled: false,
+            keystore_path: default_keystore_path(),
+            password_env: default_password_env(),
+            networks: Vec::new(),
+            allowed_recipients: Vec::new(),
+            allowed_contracts: HashMap::new(),
+            native_limits: SpendLimits::default(),
+            native_limits_by_network: HashMap::new(),
+            ledger_path: default_spend_ledger_path(),
+        }
+    }
+}
+
+/// Caps in whole units, e.g. `"1.5"`. A missing cap allows nothing.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SpendLimits {
+    pub max_per_tx: Option<String>,
+    /// Per UTC day.
+    pub max_per_day: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BatchConfig {
+    /// Requests of a batch that may run at the same time.
+    #[serde(default = "default_batch_concurrency")]
+    pub max_concurrency: usize,
+    /// Largest number of requests accepted in one batch.
+    #[serde(default = "default_max_batch_size")]
+    pub max_size: usize,
+}
+
+/// Response cache for blockchain requests, persisted between runs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Longest time a response about the latest state is reused. It is dropped sooner
+    /// once a newer block is seen.
+    #[serde(default = "default_cache_ttl_ms")]
+    pub ttl_ms: u64,
+    /// How long a read of the latest block number is trusted before checking again.
+    #[serde(default = "default_head_ttl_ms")]
+    pub head_ttl_ms: u64,
+    /// Blocks a pinned read must be below the head before it is cached for good; reads
+    /// closer to the head could still be reorganized or clamped, so they expire like latest ones.
+    #[serde(default = "default_cache_confirmations")]
+    pub confirmations: u64,
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+    #[serde(default = "default_cache_path")]
+    pub path: String,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            ttl_ms: default_cache_ttl_ms(),
+            head_ttl_ms: default_head_ttl_ms(),
+            confirmations: default_cache_confirmations(),
+            max_entries: default_cache_max_entries(),
+            path: default_cache_path(),
+        }
+    }
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_concurrency: default_batch_concurrency(),
+            max_size: default_max_batch_size(),
+        }
+    }
+}
+
+impl Default for BlockchainConfig {
+    fn default() -> Self {
+        BlockchainConfig {
+            rate_limit: RateLimitConfig::default(),
+            default_network: default_network_name(),
+            networks: default_networks(),
+            batch: BatchConfig::default(),
+            cache: CacheConfig::default(),
+            signer: SignerConfig::default(),
+        }
+    }
 }
 
+impl BlockchainConfig {
+    pub fn network(&self, name: Option<&str>) -> Option<&NetworkConfig> {
+        self.networks.get(name.unwrap_or(&self.default_network))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NetworkConfig {
+    /// Blockscout API base, e.g. `https://explorer.uomi.ai/api/v2`.
+    pub explorer_url: String,
+    #[serde(default)]
+    pub rpc_url: Option<String>,
+    pub chain_id: u64,
+    #[serde(default = "default_native_symbol")]
+    pub native_symbol: String,
+    #[serde(default = "default_native_decimals")]
+    pub decimals: u8,
+    #[serde(default = "default_blockchain_timeout")]
+    pub timeout_ms: u64,
+    /// Largest block range requested in one `eth_getLogs` call.
+    #[serde(default = "default_log_chunk_size")]
+    pub log_chunk_size: u64,
+    /// Where balances and chain state come from.
+    #[serde(default)]
+    pub source: ChainSource,
+    /// Limits for JSON-RPC calls to `rpc_url`, kept apart from the explorer's `blockchain.rate_limit`.
+    #[serde(default)]
+    pub rpc_rate_limit: RateLimitConfig,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChainSource {
+    /// The Blockscout explorer API at `explorer_url`.
+    #[default]
+    Explorer,
+    /// A JSON-RPC node at `rpc_url`.
+    Rpc,
+}
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct IpfsConfig {
@@ -82,6 +424,52 @@ struct InputWrapper {
 fn default_timeout() -> u64 { 30000 }
 fn default_retry_attempts() -> u32 { 3 }
 fn default_ipfs_gateway() -> String { "https://ipfs.io/ipfs".to_string() }
+fn default_agent_id() -> String { "agent_template".to_string() }
+fn default_usage_report_path() -> String { "./src/usage_report.json".to_string() }
+fn default_usage_ledger_path() -> String { "./usage_ledger.jsonl".to_string() }
+fn default_audit_path() -> String { "./audit_log.jsonl".to_string() }
+fn default_redact_fields() -> Vec<String> {
+    ["api_key", "authorization", "password", "private_key", "secret", "access_token"]
+        .iter()
+        .map(|f| f.to_string())
+        .collect()
+}
+fn default_max_body_bytes() -> usize { 64 * 1024 }
+fn default_true() -> bool { true }
+fn default_failure_threshold() -> u32 { 3 }
+fn default_open_ms() -> u64 { 60000 }
+fn default_breaker_state_path() -> String { "./circuit_breakers.json".to_string() }
+fn default_network_name() -> String { "uomi".to_string() }
+fn default_native_symbol() -> String { "UOMI".to_string() }
+fn default_native_decimals() -> u8 { 18 }
+fn default_blockchain_timeout() -> u64 { 10000 }
+fn default_log_chunk_size() -> u64 { 5000 }
+fn default_batch_concurrency() -> usize { 8 }
+fn default_max_batch_size() -> usize { 100 }
+fn default_cache_ttl_ms() -> u64 { 60000 }
+fn default_head_ttl_ms() -> u64 { 5000 }
+fn default_cache_confirmations() -> u64 { 12 }
+fn default_cache_max_entries() -> usize { 1000 }
+fn default_cache_path() -> String { "./blockchain_cache.json".to_string() }
+fn default_keystore_path() -> String { "./keystore.json".to_string() }
+fn default_password_env() -> String { "UOMI_SIGNER_PASSWORD".to_string() }
+fn default_spend_ledger_path() -> String { "./signer_ledger.json".to_string() }
+fn default_networks() -> HashMap<String, NetworkConfig> {
+    HashMap::from([(
+        default_network_name(),
+        NetworkConfig {
+            explorer_url: "https://explorer.uomi.ai/api/v2".to_string(),
+            rpc_url: None,
+            chain_id: 4386,
+            native_symbol: default_native_symbol(),
+            decimals: default_native_decimals(),
+            timeout_ms: default_blockchain_timeout(),
+            log_chunk_size: default_log_chunk_size(),
+            source: ChainSource::Explorer,
+            rpc_rate_limit: RateLimitConfig::default(),
+        },
+    )])
+}
 
 pub fn get_config() -> Result<UomiConfig, ServiceError> {
     let input_str = include_str!("../../uomi.config.json");
@@ -107,31 +495,60 @@ pub async fn get_file_from_cid(cid: &str) -> Result<Vec<u8>, ServiceError> {
         .timeout(Duration::from_millis(config.ipfs.timeout_ms))
         .build()?;
         
-    let response = client
-        .get(&url)
-        .send()
-        .await?;
-        
-    if !response.status().is_success() {
-        return Err(ServiceError::RequestFailed {
-            status: response.status(),
-            message: response.text().await.unwrap_or_default(),
-        });
+    let audit = AuditRecord::request("ipfs", "GET", &url, None);
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            audit.error(&e).log();
+            return Err(e.into());
+        }
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        let message = response.text().await.unwrap_or_default();
+        audit.response(status.as_u16(), message.as_bytes()).log();
+        return Err(ServiceError::RequestFailed { status, message });
     }
-    
-    Ok(response.bytes().await?.to_vec())
+
+    let bytes = response.bytes().await?.to_vec();
+    audit.response(status.as_u16(), &bytes).log();
+    Ok(bytes)
 }
 
+/// Call `model_id`, moving on to its `fallback_models` while endpoints are unavailable.
+/// Returns the ID of the model that answered along with its response. `attempts` counts
+/// every model that was called, whether it answered or not.
 #[tokio::main]
-pub async fn call_service_api(model_id: i32, data: Vec<u8>) -> Result<Vec<u8>, ServiceError> {
+pub async fn call_service_api(model_id: i32, data: Vec<u8>, attempts: &mut u32) -> Result<(i32, Vec<u8>), ServiceError> {
     let config = get_config()?;
-    
+
     let model = config.models.get(&model_id.to_string())
         .ok_or_else(|| ServiceError::Model(format!("Invalid model ID: {}", model_id)))?;
+    let input_str = String::from_utf8(data)?;
+
+    let mut last_error = None;
+    for candidate in std::iter::once(model_id.to_string()).chain(model.fallback_models.iter().cloned()) {
+        *attempts += 1;
+        match call_model(&config, &candidate, &input_str).await {
+            Ok(output) => return Ok((candidate.parse().unwrap_or(model_id), output)),
+            Err(e) if e.is_unavailable() => {
+                eprintln!("Model {} unavailable: {}", candidate, e);
+                last_error = Some(e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| ServiceError::Model(format!("No model available for ID: {}", model_id))))
+}
+
+async fn call_model(config: &UomiConfig, model_id: &str, input_str: &str) -> Result<Vec<u8>, ServiceError> {
+    let model = config.models.get(model_id)
+        .ok_or_else(|| ServiceError::Model(format!("Invalid model ID: {}", model_id)))?;
 
     // Parse input data
-    let input_str = String::from_utf8(data)?;
-    let input: InputWrapper = serde_json::from_str(&input_str)?;
+    let input: InputWrapper = serde_json::from_str(input_str)?;
 
     let (url, request_body) = if let Some(model_url) = &model.url {
         if model_url.contains("openai.com") {
@@ -220,8 +637,16 @@ pub async fn call_service_api(model_id: i32, data: Vec<u8>) -> Result<Vec<u8>, S
     }
 
     let request_json = serde_json::to_string(&request_body)?;
+    let endpoint = circuit_breaker::endpoint_key(&url);
+
+    let limiter_key = format!("model:{}", model_id);
+    let estimated_tokens = rate_limiter::estimate_tokens(&request_json);
 
     for attempt in 0..config.api.retry_attempts {
+        rate_limiter::acquire(&limiter_key, &model.rate_limit, estimated_tokens).await;
+        circuit_breaker::acquire(&endpoint)?;
+        let mut retry_after = Duration::from_secs(1);
+        let audit = AuditRecord::request("model", "POST", &url, Some(request_json.as_bytes()));
         let response = client
             .post(&url)
             .headers(headers.clone())
@@ -231,21 +656,42 @@ pub async fn call_service_api(model_id: i32, data: Vec<u8>) -> Result<Vec<u8>, S
 
         match response {
             Ok(resp) => {
-                if resp.status().is_success() {
-                    return Ok(resp.bytes().await?.to_vec());
+                let status = resp.status();
+                if status.is_success() {
+                    let bytes = resp.bytes().await?.to_vec();
+                    audit.response(status.as_u16(), &bytes).log();
+                    circuit_breaker::record_success(&endpoint);
+                    let usage = TokenUsage::from_response(&bytes);
+                    rate_limiter::settle_tokens(&limiter_key, estimated_tokens, usage.prompt_tokens + usage.completion_tokens);
+                    return Ok(bytes);
+                }
+                if status == StatusCode::TOO_MANY_REQUESTS {
+                    // Honour the provider's Retry-After (in seconds) before the next attempt
+                    if let Some(seconds) = resp.headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok())
+                    {
+                        retry_after = Duration::from_secs(seconds).min(MAX_RETRY_AFTER);
+                    }
+                }
+                // A 4xx, 429 included, is the endpoint answering; it closes a half-open breaker
+                if status.is_server_error() {
+                    circuit_breaker::record_failure(&endpoint, &status.to_string());
+                } else {
+                    circuit_breaker::record_success(&endpoint);
                 }
                 eprintln!(
                     "Request failed (attempt {}/{}): {}",
                     attempt + 1,
                     config.api.retry_attempts,
-                    resp.status()
+                    status
                 );
-                
+
+                let message = resp.text().await.unwrap_or_default();
+                audit.response(status.as_u16(), message.as_bytes()).log();
                 if attempt == config.api.retry_attempts - 1 {
-                    return Err(ServiceError::RequestFailed {
-                        status: resp.status(),
-                        message: resp.text().await.unwrap_or_default(),
-                    });
+                    return Err(ServiceError::RequestFailed { status, message });
                 }
             },
             Err(e) => {
@@ -255,6 +701,8 @@ pub async fn call_service_api(model_id: i32, data: Vec<u8>) -> Result<Vec<u8>, S
                     config.api.retry_attempts,
                     e
                 );
+                audit.error(&e).log();
+                circuit_breaker::record_failure(&endpoint, &e.to_string());
                 
                 if attempt == config.api.retry_attempts - 1 {
                     return Err(ServiceError::Network(e));
@@ -263,7 +711,7 @@ pub async fn call_service_api(model_id: i32, data: Vec<u8>) -> Result<Vec<u8>, S
         }
 
         if attempt < config.api.retry_attempts - 1 {
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            tokio::time::sleep(retry_after).await;
         }
     }
 
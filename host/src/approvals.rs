@@ -0,0 +1,331 @@
+use primitive_types::U256;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::abi::Function;
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{explorer_get, format_units, token_decimals, validate_address, BlockchainError, ExplorerResponse};
+use crate::logs::{self, LogEntry, LogQuery, TopicFilter};
+use crate::rpc::RpcClient;
+
+/// Allowances at or above `2^96 - 1` are treated as unlimited. This covers `type(uint256).max`
+/// and tokens that store allowances as `uint96`.
+const UNLIMITED_BITS: usize = 96;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Risk {
+    /// The approval was revoked or spent.
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+/// Every ERC-20 approval found for a wallet, with its live allowance and risk.
+#[derive(Debug, Serialize)]
+pub struct ApprovalReport {
+    pub owner: String,
+    pub live: usize,
+    pub unlimited: usize,
+    pub high_risk: usize,
+    pub items: Vec<Approval>,
+    /// Blocks whose `Approval` logs were scanned; approvals granted outside them aren't in this report.
+    pub scanned_from_block: u64,
+    pub scanned_to_block: u64,
+    /// Scan the next range from here: older blocks when `from_block` is unset, later blocks otherwise.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Approval {
+    pub token: String,
+    pub symbol: Option<String>,
+    pub spender: String,
+    pub spender_name: Option<String>,
+    /// Current allowance from `allowance(owner, spender)`, as a raw amount.
+    pub allowance: String,
+    pub allowance_formatted: Option<String>,
+    pub live: bool,
+    pub unlimited: bool,
+    /// Block and transaction of the latest `Approval` event for this pair.
+    pub approved_at_block: Option<u64>,
+    pub transaction_hash: Option<String>,
+    /// `unlimited`, `scam`, `unverified_contract`, `not_a_contract` or `unknown_spender`.
+    pub flags: Vec<&'static str>,
+    pub risk: Risk,
+}
+
+struct TokenInfo {
+    symbol: Option<String>,
+    decimals: Option<u8>,
+}
+
+/// Scan `Approval` logs where `owner` is the approver and check each token/spender
+/// pair's live allowance. Without `from_block`, the scan covers the most recent window
+/// of blocks below `to_block` (default latest) and `next_cursor` steps back to older
+/// windows; with it, the scan runs forward from `from_block` and `next_cursor` continues it.
+///
+/// Needs the network's `rpc_url`: the explorer can't list approvals by owner or read live
+/// allowances, so there is no explorer fallback.
+pub async fn get_approvals(
+    network_name: &str,
+    network: &NetworkConfig,
+    owner: &str,
+    from_block: Option<String>,
+    to_block: Option<String>,
+    cursor: Option<String>,
+) -> Result<ApprovalReport, BlockchainError> {
+    let owner = validate_address(owner)?.to_string();
+    if network.rpc_url.is_none() {
+        return Err(BlockchainError::Config(format!(
+            "get_approvals needs an rpc_url, and network {} has none configured",
+            network_name
+        )));
+    }
+    let rpc = RpcClient::new(network)?;
+    let head = rpc.block_number().await?;
+    let to = match to_block.as_deref() {
+        Some(block) => logs::resolve_block(block, head)?.min(head),
+        None => head,
+    };
+    let window = network.log_chunk_size.max(1).saturating_mul(logs::MAX_CHUNKS_PER_CALL);
+    let (from, to, backwards) = match (from_block.as_deref(), cursor.as_deref()) {
+        (Some(_), Some(cursor)) => (parse_cursor(cursor)?, to, false),
+        (Some(block), None) => (logs::resolve_block(block, head)?, to, false),
+        (None, cursor) => {
+            let end = cursor.map(parse_cursor).transpose()?.map_or(to, |cursor| cursor.min(to));
+            (end.saturating_sub(window - 1), end, true)
+        }
+    };
+    if from > to {
+        return Err(BlockchainError::InvalidRequest(format!("from_block {} is after to_block {}", from, to)));
+    }
+
+    let mut entries = Vec::new();
+    let mut start = from;
+    let next = loop {
+        let query = LogQuery {
+            event: Some("Approval".to_string()),
+            topics: vec![None, Some(TopicFilter::One(owner.clone()))],
+            from_block: Some(start.to_string()),
+            to_block: Some(to.to_string()),
+            limit: Some(usize::MAX),
+            ..Default::default()
+        };
+        let page = logs::get_rpc_logs(network, &query).await?;
+        entries.extend(page.items);
+        match page.next_cursor {
+            // A backwards scan finishes its window, so the report never skips blocks near the head
+            Some(cursor) if backwards => start = parse_cursor(&cursor)?,
+            next => break next,
+        }
+    };
+    let (scanned_to_block, next_cursor) = match next {
+        _ if backwards => (to, (from > 0).then(|| (from - 1).to_string())),
+        Some(cursor) => (parse_cursor(&cursor)?.saturating_sub(1), Some(cursor)),
+        None => (to, None),
+    };
+
+    // ERC-721 approvals carry a fourth topic (the token ID) and have no allowance
+    let mut latest: BTreeMap<(String, String), &LogEntry> = BTreeMap::new();
+    for log in entries.iter().filter(|log| log.topics.len() == 3) {
+        let Some(spender) = decoded_address(log, 1) else { continue };
+        latest.insert((log.address.clone(), spender), log);
+    }
+
+    let mut tokens: HashMap<String, TokenInfo> = HashMap::new();
+    let mut spenders: HashMap<String, Option<ExplorerResponse>> = HashMap::new();
+    let mut items = Vec::new();
+
+    for ((token, spender), log) in latest {
+        let allowance = match call(&rpc, &token, "allowance(address,address)(uint256)", &[&owner, &spender]).await {
+            Ok(allowance) => parse_uint(&allowance).unwrap_or_default(),
+            // Not an ERC-20 after all: the call reverts or returns nothing
+            Err(e @ (BlockchainError::Rpc { .. } | BlockchainError::Abi(_))) => {
+                println!("Skipping approval on {}: {}", token, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if !tokens.contains_key(&token) {
+            tokens.insert(token.clone(), token_info(&rpc, &token).await);
+        }
+        if !spenders.contains_key(&spender) {
+            spenders.insert(spender.clone(), spender_info(network, &spender).await);
+        }
+        let info = &tokens[&token];
+        let spender_info = spenders[&spender].as_ref();
+
+        let live = !allowance.is_zero();
+        let unlimited = is_unlimited(allowance);
+        let flags = risk_flags(unlimited, spender_info);
+        let allowance = allowance.to_string();
+        items.push(Approval {
+            symbol: info.symbol.clone(),
+            allowance_formatted: info.decimals.and_then(|decimals| format_units(&allowance, decimals)),
+            allowance,
+            live,
+            unlimited: live && unlimited,
+            risk: if live { risk_level(&flags) } else { Risk::None },
+            flags: if live { flags } else { Vec::new() },
+            spender_name: spender_info.and_then(|info| info.name.clone()),
+            approved_at_block: log.block_number,
+            transaction_hash: log.transaction_hash.clone(),
+            token,
+            spender,
+        });
+    }
+
+    // Riskiest first, revoked approvals last
+    items.sort_by_key(|item| std::cmp::Reverse(item.risk));
+    Ok(ApprovalReport {
+        owner,
+        live: items.iter().filter(|item| item.live).count(),
+        unlimited: items.iter().filter(|item| item.unlimited).count(),
+        high_risk: items.iter().filter(|item| item.risk == Risk::High).count(),
+        items,
+        scanned_from_block: from,
+        scanned_to_block,
+        next_cursor,
+    })
+}
+
+fn is_unlimited(allowance: U256) -> bool {
+    allowance >= (U256::one() << UNLIMITED_BITS) - 1
+}
+
+fn risk_flags(unlimited: bool, spender: Option<&ExplorerResponse>) -> Vec<&'static str> {
+    let mut flags = Vec::new();
+    if unlimited {
+        flags.push("unlimited");
+    }
+    match spender {
+        None => flags.push("unknown_spender"),
+        Some(spender) if spender.is_scam => flags.push("scam"),
+        Some(spender) if !spender.is_contract => flags.push("not_a_contract"),
+        Some(spender) if !spender.is_verified => flags.push("unverified_contract"),
+        Some(_) => {}
+    }
+    flags
+}
+
+/// Scam-tagged spenders are always high risk, as is an unlimited approval to a spender
+/// whose code can't be inspected. Any other flag is medium risk.
+fn risk_level(flags: &[&str]) -> Risk {
+    let unlimited = flags.contains(&"unlimited");
+    let opaque = flags.iter().any(|flag| matches!(*flag, "not_a_contract" | "unverified_contract" | "unknown_spender"));
+    if flags.contains(&"scam") || (unlimited && opaque) {
+        Risk::High
+    } else if !flags.is_empty() {
+        Risk::Medium
+    } else {
+        Risk::Low
+    }
+}
+
+/// Explorer metadata for a spender, or `None` if the explorer doesn't know it.
+async fn spender_info(network: &NetworkConfig, spender: &str) -> Option<ExplorerResponse> {
+    match explorer_get(network, &format!("/addresses/{}", spender)).await {
+        Ok(info) => Some(info),
+        Err(e) => {
+            println!("No explorer data for spender {}: {}", spender, e);
+            None
+        }
+    }
+}
+
+async fn token_info(rpc: &RpcClient<'_>, token: &str) -> TokenInfo {
+    let symbol = call(rpc, token, "symbol()(string)", &[]).await.ok()
+        .and_then(|value| value.as_str().map(|symbol| symbol.to_string()));
+    let decimals = call(rpc, token, "decimals()(uint8)", &[]).await.ok()
+        .and_then(|value| parse_uint(&value))
+        .and_then(|decimals| token_decimals(token, decimals).ok());
+    TokenInfo { symbol, decimals }
+}
+
+/// Call a view function returning a single value and decode it.
+async fn call(rpc: &RpcClient<'_>, contract: &str, signature: &str, args: &[&str]) -> Result<serde_json::Value, BlockchainError> {
+    let function = Function::parse(signature)?;
+    let args: Vec<serde_json::Value> = args.iter().map(|arg| serde_json::json!(arg)).collect();
+    let output = rpc.eth_call(contract, &function.encode_call(&args)?, "latest").await?;
+    let outputs = function.decode_output(&output)?;
+    Ok(outputs.into_iter().next()
+        .and_then(|output| output.get("value").cloned())
+        .unwrap_or_default())
+}
+
+fn decoded_address(log: &LogEntry, index: usize) -> Option<String> {
+    let value = log.decoded.as_ref()?.get(index)?.get("value")?.as_str()?;
+    Some(value.to_string())
+}
+
+fn parse_cursor(cursor: &str) -> Result<u64, BlockchainError> {
+    cursor.parse().map_err(|_| BlockchainError::InvalidRequest(format!("invalid cursor {}", cursor)))
+}
+
+fn parse_uint(value: &serde_json::Value) -> Option<U256> {
+    U256::from_dec_str(value.as_str()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spender(is_contract: bool, is_verified: bool, is_scam: bool) -> ExplorerResponse {
+        ExplorerResponse { is_contract, is_verified, is_scam, ..Default::default() }
+    }
+
+    #[test]
+    fn unlimited_starts_at_uint96_max() {
+        let threshold = (U256::one() << UNLIMITED_BITS) - 1;
+        assert_eq!(threshold, U256::from_dec_str("79228162514264337593543950335").unwrap());
+        assert!(is_unlimited(threshold));
+        assert!(is_unlimited(U256::MAX));
+        assert!(!is_unlimited(threshold - 1));
+        assert!(!is_unlimited(U256::zero()));
+    }
+
+    #[test]
+    fn flags_spenders() {
+        let verified = spender(true, true, false);
+        assert_eq!(risk_flags(false, Some(&verified)), Vec::<&str>::new());
+        assert_eq!(risk_flags(true, Some(&verified)), ["unlimited"]);
+        assert_eq!(risk_flags(false, None), ["unknown_spender"]);
+        assert_eq!(risk_flags(true, Some(&spender(true, true, true))), ["unlimited", "scam"]);
+        assert_eq!(risk_flags(false, Some(&spender(false, false, false))), ["not_a_contract"]);
+        assert_eq!(risk_flags(false, Some(&spender(true, false, false))), ["unverified_contract"]);
+    }
+
+    #[test]
+    fn rates_risk_from_flags() {
+        assert_eq!(risk_level(&[]), Risk::Low);
+        assert_eq!(risk_level(&["unlimited"]), Risk::Medium);
+        assert_eq!(risk_level(&["unverified_contract"]), Risk::Medium);
+        assert_eq!(risk_level(&["scam"]), Risk::High);
+        for opaque in ["not_a_contract", "unverified_contract", "unknown_spender"] {
+            assert_eq!(risk_level(&["unlimited", opaque]), Risk::High);
+        }
+    }
+
+    #[test]
+    fn riskiest_sorts_first() {
+        let mut risks = vec![Risk::Low, Risk::None, Risk::High, Risk::Medium];
+        risks.sort_by_key(|risk| std::cmp::Reverse(*risk));
+        assert_eq!(risks, [Risk::High, Risk::Medium, Risk::Low, Risk::None]);
+    }
+
+    #[tokio::test]
+    async fn names_the_network_without_an_rpc_url() {
+        let network: NetworkConfig = serde_json::from_value(serde_json::json!({
+            "explorer_url": "http://localhost",
+            "chain_id": 1,
+        }))
+        .unwrap();
+        let error = get_approvals("testnet", &network, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", None, None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(&error, BlockchainError::Config(message) if message.contains("testnet")));
+    }
+}
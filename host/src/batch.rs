@@ -0,0 +1,140 @@
+use futures::stream::{self, StreamExt};
+use futures::FutureExt;
+use serde::Serialize;
+
+use uomi_common::ChainError;
+
+use crate::api_service::BatchConfig;
+use crate::blockchain_service::{dispatch, BlockchainCall, BlockchainError, BlockchainRequest};
+
+/// Results of a batch, in the order the requests were given.
+#[derive(Debug, Serialize)]
+pub struct BatchResponse {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub results: Vec<BatchItem>,
+}
+
+/// Outcome of one request: `result` on success, `error` otherwise.
+#[derive(Debug, Serialize)]
+pub struct BatchItem {
+    pub index: usize,
+    pub action: Option<String>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<ChainError>,
+}
+
+/// Run `requests` with at most `concurrency` in flight (capped by the config). Requests
+/// without a `network` use the batch's. A failing request doesn't fail the batch.
+pub async fn run(
+    config: &BatchConfig,
+    network: Option<String>,
+    requests: Vec<serde_json::Value>,
+    concurrency: Option<usize>,
+) -> Result<BatchResponse, BlockchainError> {
+    if requests.len() > config.max_size {
+        return Err(BlockchainError::InvalidRequest(format!(
+            "batch of {} requests exceeds the limit of {}",
+            requests.len(),
+            config.max_size
+        )));
+    }
+    let concurrency = concurrency.unwrap_or(config.max_concurrency).clamp(1, config.max_concurrency.max(1));
+
+    let results: Vec<BatchItem> = stream::iter(requests.into_iter().enumerate())
+        .map(|(index, request)| {
+            let network = network.clone();
+            async move {
+                let action = request.get("action")
+                    .and_then(|action| action.as_str())
+                    .map(|action| action.to_string());
+                let result = run_one(request, network).await;
+                match result {
+                    Ok(result) => BatchItem { index, action, result: Some(result), error: None },
+                    Err(e) => {
+                        println!("Batch request {} failed: {}", index, e);
+                        BatchItem { index, action, result: None, error: Some(e.to_chain_error()) }
+                    }
+                }
+            }
+        })
+        .buffered(concurrency)
+        .collect()
+        .await;
+
+    let failed = results.iter().filter(|item| item.error.is_some()).count();
+    Ok(BatchResponse { succeeded: results.len() - failed, failed, results })
+}
+
+async fn run_one(request: serde_json::Value, network: Option<String>) -> Result<serde_json::Value, BlockchainError> {
+    let mut call = BlockchainCall::from_value(request)?;
+    match call.request {
+        BlockchainRequest::Batch { .. } => {
+            return Err(BlockchainError::InvalidRequest("batches cannot be nested".to_string()))
+        }
+        // Every transaction the host signs should be asked for on its own
+        BlockchainRequest::SendTransaction(_) => {
+            return Err(BlockchainError::InvalidRequest("send_transaction cannot be batched".to_string()))
+        }
+        _ => {}
+    }
+    call.network = call.network.or(network);
+    // Boxed because `dispatch` is what runs the batch in the first place
+    dispatch(call).boxed_local().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use uomi_common::ErrorKind;
+
+    fn config() -> BatchConfig {
+        BatchConfig { max_concurrency: 2, max_size: 3 }
+    }
+
+    #[tokio::test]
+    async fn rejects_forbidden_requests_per_item() {
+        let requests = vec![
+            json!({"action": "get_network"}),
+            json!({"action": "batch", "requests": [{"action": "get_network"}]}),
+            json!({"action": "send_transaction", "to": "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed", "value": "1"}),
+        ];
+        let response = run(&config(), None, requests, None).await.unwrap();
+
+        assert_eq!((response.succeeded, response.failed), (1, 2));
+        let indexes: Vec<usize> = response.results.iter().map(|item| item.index).collect();
+        assert_eq!(indexes, [0, 1, 2]);
+        assert!(response.results[0].result.is_some());
+        for item in &response.results[1..] {
+            let error = item.error.as_ref().unwrap();
+            assert_eq!(error.kind, ErrorKind::InvalidRequest, "{:?}", item.action);
+        }
+        assert_eq!(response.results[1].action.as_deref(), Some("batch"));
+        assert_eq!(response.results[2].action.as_deref(), Some("send_transaction"));
+    }
+
+    #[tokio::test]
+    async fn rejects_an_oversized_nested_batch_per_item() {
+        let nested: Vec<_> = (0..10).map(|_| json!({"action": "get_network"})).collect();
+        let requests = vec![json!({"action": "get_network"}), json!({"action": "batch", "requests": nested})];
+        let response = run(&config(), None, requests, Some(8)).await.unwrap();
+        assert_eq!((response.succeeded, response.failed), (1, 1));
+        assert_eq!(response.results[1].error.as_ref().unwrap().kind, ErrorKind::InvalidRequest);
+    }
+
+    #[tokio::test]
+    async fn rejects_an_oversized_batch_before_running_it() {
+        let requests: Vec<_> = (0..4).map(|_| json!({"action": "get_network"})).collect();
+        assert!(matches!(run(&config(), None, requests, None).await, Err(BlockchainError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn unparseable_items_fail_alone() {
+        let requests = vec![json!({"action": "no_such_action"}), json!("not an object"), json!({"action": "get_network"})];
+        let response = run(&config(), None, requests, None).await.unwrap();
+        assert_eq!((response.succeeded, response.failed), (1, 2));
+        assert_eq!(response.results[0].action.as_deref(), Some("no_such_action"));
+        assert_eq!(response.results[1].action, None);
+    }
+}
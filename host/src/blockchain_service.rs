@@ -1,26 +1,386 @@
 use reqwest::{Client, header::HeaderMap};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::time::Duration;
 use thiserror::Error;
 
+use uomi_common::{Address, AddressError, Amount, ChainError, ErrorKind, U256};
+
+use crate::abi::{to_hex, AbiError, Function};
+use crate::api_service::{get_config, CacheConfig, ChainSource, NetworkConfig, UomiConfig};
+use crate::audit::AuditRecord;
+use crate::batch;
+use crate::cache::{self, CachePolicy};
+use crate::circuit_breaker::{self, CircuitOpen};
+use crate::contract;
+use crate::gas::{self, GasCall};
+use crate::approvals;
+use crate::history;
+use crate::logs::{self, LogQuery};
+use crate::nft;
+use crate::prepare::{self, TransferRequest};
+use crate::transaction;
+use crate::rate_limiter;
+use crate::signer;
+use crate::rpc::{block_tag, RpcClient};
+
 #[derive(Error, Debug)]
 pub enum BlockchainError {
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
-    #[error("Invalid address format: {0}")]
-    InvalidAddress(String),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(#[from] AddressError),
     #[error("API error: {status}, {message}")]
     ApiError { status: u16, message: String },
     #[error("JSON parsing error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    CircuitOpen(#[from] CircuitOpen),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Unknown action: {0}")]
+    UnknownAction(String),
+    #[error("Unknown network: {0}")]
+    UnknownNetwork(String),
+    #[error("Configuration error: {0}")]
+    Config(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("ABI error: {0}")]
+    Abi(#[from] AbiError),
+    #[error("Signer error: {0}")]
+    Signer(String),
+    #[error("Rejected by signer policy: {0}")]
+    PolicyViolation(String),
 }
 
+impl BlockchainError {
+    /// Return code handed back to the agent by `call_blockchain`.
+    pub fn code(&self) -> i32 {
+        self.kind().code()
+    }
 
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            BlockchainError::UnknownAction(_) => ErrorKind::UnknownAction,
+            BlockchainError::InvalidAddress(_) => ErrorKind::InvalidAddress,
+            BlockchainError::InvalidRequest(_) => ErrorKind::InvalidRequest,
+            BlockchainError::UnknownNetwork(_) => ErrorKind::UnknownNetwork,
+            BlockchainError::NotFound(_) => ErrorKind::NotFound,
+            BlockchainError::ApiError { status: 404, .. } => ErrorKind::NotFound,
+            BlockchainError::ApiError { status: 429, .. } => ErrorKind::RateLimited,
+            BlockchainError::Network(e) if e.is_timeout() => ErrorKind::Timeout,
+            BlockchainError::Network(_) | BlockchainError::ApiError { .. } | BlockchainError::CircuitOpen(_) => {
+                ErrorKind::UpstreamError
+            }
+            BlockchainError::Rpc { .. } => ErrorKind::RpcError,
+            BlockchainError::Abi(_) => ErrorKind::AbiError,
+            BlockchainError::PolicyViolation(_) => ErrorKind::PolicyRejected,
+            BlockchainError::Json(_) => ErrorKind::InvalidResponse,
+            BlockchainError::Config(_) => ErrorKind::ConfigError,
+            BlockchainError::Signer(_) => ErrorKind::SignerError,
+        }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
+    /// The structured error written to the agent's output buffer.
+    pub fn to_chain_error(&self) -> ChainError {
+        let mut error = ChainError::new(self.kind(), self.to_string());
+        match self {
+            BlockchainError::ApiError { status, .. } => error.status = Some(*status),
+            BlockchainError::Network(e) => error.status = e.status().map(|status| status.as_u16()),
+            BlockchainError::CircuitOpen(open) => error.retry_after_ms = Some(open.retry_in_ms),
+            _ => {}
+        }
+        error
+    }
+}
+
+const ERC20: &str = "ERC-20";
+
+/// The most decimals a token can have: `10^77` is the largest power of ten a U256 holds,
+/// so beyond that not even one whole unit fits.
+const MAX_TOKEN_DECIMALS: u8 = 77;
+
+/// Requests accepted by `call_blockchain`, selected by their `action` field.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action")]
+pub enum BlockchainRequest {
+    #[serde(rename = "get_balance")]
+    Balance { address: String },
+    #[serde(rename = "get_tokens")]
+    Tokens {
+        address: String,
+        /// Page through `/tokens` instead of returning every balance at once.
+        #[serde(default)]
+        paginated: bool,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+    #[serde(rename = "get_transactions")]
+    Transactions {
+        address: String,
+        #[serde(default)]
+        filter: Option<TransactionFilter>,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+    #[serde(rename = "get_block_number")]
+    BlockNumber,
+    /// The network's chain ID and native currency, from the configuration.
+    #[serde(rename = "get_network")]
+    Network,
+    #[serde(rename = "get_transaction_count")]
+    TransactionCount {
+        address: String,
+        #[serde(default)]
+        block: Option<String>,
+    },
+    #[serde(rename = "get_code")]
+    Code {
+        address: String,
+        #[serde(default)]
+        block: Option<String>,
+    },
+    #[serde(rename = "get_transaction_receipt")]
+    TransactionReceipt { hash: String },
+    /// Read-only contract call. The function is given either as a `signature` such as
+    /// `balanceOf(address)(uint256)`, or as a JSON `abi` fragment (an array is searched by `function`).
+    #[serde(rename = "contract_call")]
+    ContractCall {
+        contract: String,
+        #[serde(default)]
+        signature: Option<String>,
+        #[serde(default)]
+        abi: Option<serde_json::Value>,
+        #[serde(default)]
+        function: Option<String>,
+        #[serde(default)]
+        args: Vec<serde_json::Value>,
+        #[serde(default)]
+        block: Option<String>,
+    },
+    #[serde(rename = "get_logs")]
+    Logs(LogQuery),
+    #[serde(rename = "get_transaction")]
+    Transaction { hash: String },
+    /// Native balance, or ERC-20 balance of `token`, at a block or timestamp.
+    #[serde(rename = "get_historical_balance")]
+    HistoricalBalance {
+        address: String,
+        #[serde(default)]
+        token: Option<String>,
+        #[serde(default)]
+        block: Option<String>,
+        #[serde(default)]
+        timestamp: Option<serde_json::Value>,
+    },
+    /// ERC-20 approvals granted by `address`, with live allowances and a risk rating.
+    #[serde(rename = "get_approvals")]
+    Approvals {
+        address: String,
+        #[serde(default)]
+        from_block: Option<String>,
+        #[serde(default)]
+        to_block: Option<String>,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+    #[serde(rename = "get_nfts")]
+    Nfts {
+        address: String,
+        /// `ERC-721` or `ERC-1155`; both when unset.
+        #[serde(default, rename = "type")]
+        token_type: Option<String>,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+    /// Proxy implementations and verified source metadata of a contract.
+    #[serde(rename = "get_contract")]
+    Contract {
+        address: String,
+        /// Also return the full JSON ABI of the contract and its implementation.
+        #[serde(default)]
+        include_abi: bool,
+    },
+    #[serde(rename = "get_balance_history")]
+    BalanceHistory {
+        address: String,
+        #[serde(default)]
+        cursor: Option<String>,
+    },
+    /// Current gas prices and the cost of a native transfer at each speed tier.
+    #[serde(rename = "get_gas")]
+    Gas {
+        #[serde(default)]
+        blocks: Option<u64>,
+    },
+    #[serde(rename = "estimate_gas")]
+    EstimateGas(GasCall),
+    /// Unsigned EIP-1559 transfer for the user to sign in their wallet.
+    #[serde(rename = "prepare_transaction")]
+    PrepareTransaction(TransferRequest),
+    /// Transfer signed by the host's keystore and sent, if `blockchain.signer` allows it.
+    #[serde(rename = "send_transaction")]
+    SendTransaction(TransferRequest),
+    /// Several requests run concurrently, each answered with its own result or error.
+    #[serde(rename = "batch")]
+    Batch {
+        requests: Vec<serde_json::Value>,
+        /// Lower the configured concurrency for this batch.
+        #[serde(default)]
+        concurrency: Option<usize>,
+    },
+}
+
+/// A request together with the network it targets (the default network when unset).
+#[derive(Debug)]
+pub struct BlockchainCall {
+    pub network: Option<String>,
+    pub request: BlockchainRequest,
+    /// The raw request without `network`, used as the cache key.
+    pub params: serde_json::Value,
+}
+
+impl BlockchainCall {
+    /// Parse a raw request, telling an unknown action apart from malformed parameters.
+    pub fn parse(request: &[u8]) -> Result<Self, BlockchainError> {
+        let value: serde_json::Value = serde_json::from_slice(request)
+            .map_err(|e| BlockchainError::InvalidRequest(e.to_string()))?;
+        Self::from_value(value)
+    }
+
+    pub fn from_value(value: serde_json::Value) -> Result<Self, BlockchainError> {
+        let action = value.get("action")
+            .and_then(|action| action.as_str())
+            .ok_or_else(|| BlockchainError::InvalidRequest("missing \"action\" field".to_string()))?;
+        if !BlockchainRequest::ACTIONS.contains(&action) {
+            return Err(BlockchainError::UnknownAction(action.to_string()));
+        }
+        let network = value.get("network")
+            .and_then(|network| network.as_str())
+            .map(|network| network.to_string());
+        let mut params = value.clone();
+        if let Some(params) = params.as_object_mut() {
+            params.remove("network");
+        }
+        let request = serde_json::from_value(value)
+            .map_err(|e| BlockchainError::InvalidRequest(e.to_string()))?;
+        Ok(BlockchainCall { network, request, params })
+    }
+
+    /// The timestamp of a `get_historical_balance` query that isn't pinned to a block yet.
+    fn unpinned_timestamp(&self) -> Option<&serde_json::Value> {
+        match &self.request {
+            BlockchainRequest::HistoricalBalance { block: None, timestamp: Some(timestamp), .. } => Some(timestamp),
+            _ => None,
+        }
+    }
+
+    /// Replace the timestamp of a `get_historical_balance` query with the block it resolved
+    /// to, so it is cached like any read pinned to a block.
+    fn pin_to_block(&mut self, number: u64) {
+        let pinned = format!("0x{:x}", number);
+        if let BlockchainRequest::HistoricalBalance { block, timestamp, .. } = &mut self.request {
+            *block = Some(pinned.clone());
+            *timestamp = None;
+        }
+        if let Some(params) = self.params.as_object_mut() {
+            params.remove("timestamp");
+            params.insert("block".to_string(), serde_json::json!(pinned));
+        }
+    }
+}
+
+impl BlockchainRequest {
+    const ACTIONS: &'static [&'static str] = &[
+        "get_balance",
+        "get_tokens",
+        "get_transactions",
+        "get_block_number",
+        "get_network",
+        "get_transaction_count",
+        "get_code",
+        "get_transaction_receipt",
+        "contract_call",
+        "get_logs",
+        "get_transaction",
+        "get_historical_balance",
+        "get_balance_history",
+        "get_approvals",
+        "get_nfts",
+        "get_contract",
+        "get_gas",
+        "estimate_gas",
+        "prepare_transaction",
+        "send_transaction",
+        "batch",
+    ];
+
+    /// Requests whose response is never cached.
+    fn is_uncached(&self) -> bool {
+        matches!(
+            self,
+            BlockchainRequest::BlockNumber
+                | BlockchainRequest::Network
+                | BlockchainRequest::PrepareTransaction(_)
+                | BlockchainRequest::SendTransaction(_)
+                | BlockchainRequest::Batch { .. }
+        )
+    }
+
+    /// The block a read is pinned to, when its response can be cached for good once that
+    /// block is deep enough below the head.
+    fn pinned_block(&self, network: &NetworkConfig) -> Option<u64> {
+        match self {
+            BlockchainRequest::TransactionCount { block, .. }
+            | BlockchainRequest::Code { block, .. }
+            | BlockchainRequest::ContractCall { block, .. }
+            | BlockchainRequest::HistoricalBalance { block, .. } => block.as_deref().and_then(block_number_of),
+            // Explorer log pages are newest first, so even a closed range can change
+            BlockchainRequest::Logs(query) if network.source == ChainSource::Rpc => {
+                query.to_block.as_deref().and_then(block_number_of)
+            }
+            _ => None,
+        }
+    }
+
+    /// Transactions and receipts, which stop changing once mined deep enough.
+    fn is_transaction(&self) -> bool {
+        matches!(self, BlockchainRequest::Transaction { .. } | BlockchainRequest::TransactionReceipt { .. })
+    }
+
+    /// How long the response may be served from the cache. Reads pinned to a block at
+    /// least `confirmations` below `head` never change, nor does a transaction mined that deep.
+    fn cache_policy(&self, network: &NetworkConfig, head: Option<u64>, confirmations: u64) -> CachePolicy {
+        if self.is_uncached() {
+            return CachePolicy::Skip;
+        }
+        if self.is_transaction() {
+            return CachePolicy::Mined { head, confirmations };
+        }
+        // Without a known head, a pinned block may still be ahead of the chain
+        match (self.pinned_block(network), head) {
+            (Some(block), Some(head)) if block.saturating_add(confirmations) <= head => CachePolicy::Historical,
+            _ => CachePolicy::Latest,
+        }
+    }
+}
+
+fn block_number_of(block: &str) -> Option<u64> {
+    match block.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => block.parse().ok(),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct ExplorerResponse {
     pub block_number_balance_updated_at: u64,
     pub coin_balance: String,
+    /// `coin_balance` as an exact decimal in the network's native unit, filled in by the host.
+    #[serde(default)]
+    pub coin_balance_formatted: Option<String>,
     pub creation_transaction_hash: Option<String>,
     pub creator_address_hash: Option<String>,
     pub ens_domain_name: Option<String>,
@@ -45,38 +405,618 @@ pub struct ExplorerResponse {
     pub watchlist_names: Vec<String>,
 }
 
+/// What `get_network` returns: enough for the agent to display native amounts.
+#[derive(Debug, Serialize)]
+pub struct NetworkInfo {
+    pub network: String,
+    pub chain_id: u64,
+    pub native_symbol: String,
+    pub decimals: u8,
+}
 
+/// A page of explorer results, as returned by Blockscout's paginated endpoints.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerPage<T> {
+    pub items: Vec<T>,
+    next_page_params: Option<serde_json::Map<String, serde_json::Value>>,
+}
 
-pub async fn get_wallet_balance(address: &str) -> Result<ExplorerResponse, BlockchainError> {
-    // Validate address format
-    if !address.starts_with("0x") || address.len() != 42 {
-        return Err(BlockchainError::InvalidAddress(
-            "Invalid Ethereum address format".to_string(),
-        ));
+impl<T> ExplorerPage<T> {
+    /// Encode `next_page_params` as the cursor handed to the agent.
+    pub fn next_cursor(&self) -> Option<String> {
+        let params = self.next_page_params.as_ref()?;
+        Some(
+            params.iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        serde_json::Value::String(value) => value.clone(),
+                        value => value.to_string(),
+                    };
+                    format!("{}={}", urlencoding::encode(key), urlencoding::encode(&value))
+                })
+                .collect::<Vec<_>>()
+                .join("&"),
+        )
     }
+}
+
+/// `/smart-contracts/{address}`: verified source and ABI. Unverified contracts come back
+/// without either.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerSmartContract {
+    pub name: Option<String>,
+    pub language: Option<String>,
+    pub compiler_version: Option<String>,
+    pub evm_version: Option<String>,
+    pub optimization_enabled: Option<bool>,
+    pub optimization_runs: Option<u64>,
+    pub license_type: Option<String>,
+    pub verified_at: Option<String>,
+    pub is_fully_verified: Option<bool>,
+    pub file_path: Option<String>,
+    pub source_code: Option<String>,
+    #[serde(default)]
+    pub additional_sources: Vec<ExplorerSourceFile>,
+    pub abi: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerSourceFile {
+    pub source_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerToken {
+    #[serde(alias = "address_hash")]
+    pub address: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<String>,
+    #[serde(rename = "type")]
+    pub token_type: String,
+    pub exchange_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerTokenBalance {
+    token: ExplorerToken,
+    token_id: Option<String>,
+    value: String,
+}
+
+/// A token holding. `balance` is the raw integer amount and `balance_formatted`
+/// the same amount as an exact decimal, shifted by `decimals` when they are known.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub contract_address: String,
+    pub name: String,
+    pub symbol: String,
+    /// `None` when the explorer doesn't know the token's decimals.
+    pub decimals: Option<u8>,
+    pub type_token: String,
+    pub balance: String,
+    /// `None` when the decimals are unknown, so a raw balance is never shown as whole units.
+    pub balance_formatted: Option<String>,
+    pub token_id: Option<String>,
+    pub exchange_rate: Option<f64>,
+}
+
+impl From<ExplorerTokenBalance> for TokenBalance {
+    fn from(balance: ExplorerTokenBalance) -> Self {
+        let token = balance.token;
+        let decimals = token.decimals.and_then(|d| d.parse().ok());
+        TokenBalance {
+            contract_address: token.address,
+            name: token.name.unwrap_or_default(),
+            symbol: token.symbol.unwrap_or_default(),
+            decimals,
+            type_token: token.token_type,
+            balance_formatted: decimals.and_then(|decimals| format_units(&balance.value, decimals)),
+            balance: balance.value,
+            token_id: balance.token_id,
+            exchange_rate: token.exchange_rate.and_then(|rate| rate.parse().ok()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub items: Vec<TokenBalance>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransactionFilter {
+    /// Incoming transactions only.
+    To,
+    /// Outgoing transactions only.
+    From,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerAddressRef {
+    pub hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ExplorerFee {
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerTransaction {
+    hash: String,
+    from: ExplorerAddressRef,
+    to: Option<ExplorerAddressRef>,
+    value: String,
+    method: Option<String>,
+    status: Option<String>,
+    timestamp: Option<String>,
+    fee: Option<ExplorerFee>,
+    #[serde(alias = "block")]
+    block_number: Option<u64>,
+}
+
+/// A transaction as seen from an address. `value` and `fee` are in wei, the
+/// `_formatted` fields in the network's native unit.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionSummary {
+    pub hash: String,
+    pub from: String,
+    pub to: Option<String>,
+    pub value: String,
+    pub value_formatted: Option<String>,
+    pub method: Option<String>,
+    pub status: Option<String>,
+    pub timestamp: Option<String>,
+    pub fee: Option<String>,
+    pub fee_formatted: Option<String>,
+    pub block_number: Option<u64>,
+}
+
+impl TransactionSummary {
+    fn from_explorer(tx: ExplorerTransaction, decimals: u8) -> Self {
+        let fee = tx.fee.and_then(|fee| fee.value);
+        TransactionSummary {
+            hash: tx.hash,
+            from: tx.from.hash,
+            to: tx.to.map(|to| to.hash),
+            value_formatted: format_units(&tx.value, decimals),
+            value: tx.value,
+            method: tx.method,
+            status: tx.status,
+            timestamp: tx.timestamp,
+            fee_formatted: fee.as_deref().and_then(|fee| format_units(fee, decimals)),
+            fee,
+            block_number: tx.block_number,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub items: Vec<TransactionSummary>,
+    pub next_cursor: Option<String>,
+}
+
+/// Run a blockchain request on its network and return the JSON handed back to the agent.
+/// Responses are served from the cache while they are still valid.
+pub async fn dispatch(call: BlockchainCall) -> Result<serde_json::Value, BlockchainError> {
+    let config = get_config().map_err(|e| BlockchainError::Config(e.to_string()))?;
+    let network_name = call.network.clone().unwrap_or_else(|| config.blockchain.default_network.clone());
+    let network = config.blockchain.network(Some(&network_name))
+        .ok_or_else(|| BlockchainError::UnknownNetwork(network_name.clone()))?;
+
+    let cache_config = &config.blockchain.cache;
+    if call.request.is_uncached() || !cache_config.enabled {
+        return execute(&config, network, call).await;
+    }
+
+    let mut call = call;
+    if let Some(timestamp) = call.unpinned_timestamp() {
+        let timestamp = history::parse_timestamp(timestamp)?;
+        call.pin_to_block(block_at_timestamp(cache_config, &network_name, network, timestamp).await?);
+    }
+
+    // The head is only read upstream when it decides whether an entry is valid: a latest
+    // entry is dropped once a newer block is seen, and a pinned read or a mined transaction
+    // is only cached for good once it is deep enough below the head
+    let key = cache::key(&network_name, &call.params);
+    let mut head = cache::head(cache_config, &network_name);
+    if head.is_none() && cache::needs_head(cache_config, &key) {
+        head = head_block(cache_config, &network_name, network).await;
+    }
+    if let Some(value) = cache::get(cache_config, &key, head) {
+        return Ok(value);
+    }
+    if head.is_none() && (call.request.pinned_block(network).is_some() || call.request.is_transaction()) {
+        head = head_block(cache_config, &network_name, network).await;
+    }
+    let policy = call.request.cache_policy(network, head, cache_config.confirmations);
+    let value = execute(&config, network, call).await?;
+    cache::put(cache_config, key, policy, &value, head);
+    Ok(value)
+}
+
+/// The last block mined at or before `timestamp`, cached so a query about a past date
+/// doesn't search block headers again. The answer only changes while that block is near
+/// the head, so it is kept for good once `confirmations` deep.
+async fn block_at_timestamp(config: &CacheConfig, name: &str, network: &NetworkConfig, timestamp: u64) -> Result<u64, BlockchainError> {
+    // Not an action, so this can't collide with the key of a request
+    let key = cache::key(name, &serde_json::json!({"block_at_timestamp": timestamp}));
+    let mut head = cache::head(config, name);
+    if head.is_none() && cache::needs_head(config, &key) {
+        head = head_block(config, name, network).await;
+    }
+    if let Some(block) = cache::get(config, &key, head).and_then(|block| block.as_u64()) {
+        return Ok(block);
+    }
+
+    let block = history::block_at(network, timestamp).await?;
+    if head.is_none() {
+        head = head_block(config, name, network).await;
+    }
+    let policy = match head {
+        Some(head) if block.saturating_add(config.confirmations) <= head => CachePolicy::Historical,
+        _ => CachePolicy::Latest,
+    };
+    cache::put(config, key, policy, &serde_json::json!(block), head);
+    Ok(block)
+}
+
+/// Read the network's latest block and remember it for `head_ttl_ms`. `None` if it can't be read.
+async fn head_block(config: &CacheConfig, name: &str, network: &NetworkConfig) -> Option<u64> {
+    match block_number(network).await {
+        Ok(head) => {
+            cache::record_head(config, name, head);
+            Some(head)
+        }
+        Err(e) => {
+            println!("Could not read the latest block of {}: {}", name, e);
+            None
+        }
+    }
+}
+
+async fn execute(
+    config: &UomiConfig,
+    network: &NetworkConfig,
+    call: BlockchainCall,
+) -> Result<serde_json::Value, BlockchainError> {
+    match call.request {
+        BlockchainRequest::Balance { address } => {
+            Ok(serde_json::to_value(get_wallet_balance(network, &address).await?)?)
+        }
+        BlockchainRequest::Tokens { address, paginated, cursor } => {
+            let tokens = if paginated || cursor.is_some() {
+                get_tokens_page(network, &address, cursor.as_deref()).await?
+            } else {
+                get_token_balances(network, &address).await?
+            };
+            Ok(serde_json::to_value(tokens)?)
+        }
+        BlockchainRequest::Transactions { address, filter, cursor } => {
+            Ok(serde_json::to_value(get_transactions(network, &address, filter, cursor.as_deref()).await?)?)
+        }
+        BlockchainRequest::BlockNumber => {
+            Ok(serde_json::json!({ "block_number": block_number(network).await? }))
+        }
+        BlockchainRequest::Network => {
+            let name = call.network.as_deref().unwrap_or(&config.blockchain.default_network);
+            Ok(serde_json::to_value(NetworkInfo {
+                network: name.to_string(),
+                chain_id: network.chain_id,
+                native_symbol: network.native_symbol.clone(),
+                decimals: network.decimals,
+            })?)
+        }
+        BlockchainRequest::TransactionCount { address, block } => {
+            let address = validate_address(&address)?;
+            let count = RpcClient::new(network)?
+                .get_transaction_count(&address.to_string(), &block_tag(block.as_deref()))
+                .await?;
+            Ok(serde_json::json!({ "address": address, "transaction_count": count }))
+        }
+        BlockchainRequest::Code { address, block } => {
+            let address = validate_address(&address)?;
+            let code = RpcClient::new(network)?.get_code(&address.to_string(), &block_tag(block.as_deref())).await?;
+            Ok(serde_json::json!({ "address": address, "is_contract": code != "0x", "code": code }))
+        }
+        BlockchainRequest::TransactionReceipt { hash } => {
+            let hash = transaction::validate_hash(&hash)?;
+            let receipt = RpcClient::new(network)?.get_transaction_receipt(&hash).await?
+                .ok_or_else(|| BlockchainError::NotFound(format!("transaction receipt {}", hash)))?;
+            Ok(serde_json::to_value(receipt)?)
+        }
+        BlockchainRequest::ContractCall { contract, signature, abi, function, args, block } => {
+            let function = match (signature, abi) {
+                (Some(signature), _) => Function::parse(&signature)?,
+                (None, Some(abi)) => Function::from_abi(&abi, function.as_deref())?,
+                (None, None) => {
+                    return Err(BlockchainError::InvalidRequest("contract_call needs a signature or an abi".to_string()))
+                }
+            };
+            contract_call(network, &contract, &function, &args, block.as_deref()).await
+        }
+        BlockchainRequest::Logs(query) => Ok(serde_json::to_value(logs::get_logs(network, &query).await?)?),
+        BlockchainRequest::Transaction { hash } => {
+            Ok(serde_json::to_value(transaction::get_transaction(network, &hash).await?)?)
+        }
+        BlockchainRequest::HistoricalBalance { address, token, block, timestamp } => {
+            let balance = history::get_historical_balance(
+                network,
+                &address,
+                token.as_deref(),
+                block.as_deref(),
+                timestamp.as_ref(),
+            )
+            .await?;
+            Ok(serde_json::to_value(balance)?)
+        }
+        BlockchainRequest::Approvals { address, from_block, to_block, cursor } => {
+            let network_name = call.network.as_deref().unwrap_or(&config.blockchain.default_network);
+            let report = approvals::get_approvals(network_name, network, &address, from_block, to_block, cursor).await?;
+            Ok(serde_json::to_value(report)?)
+        }
+        BlockchainRequest::Nfts { address, token_type, cursor } => {
+            let nfts = nft::get_nfts(network, &config.ipfs.gateway, &address, token_type.as_deref(), cursor.as_deref()).await?;
+            Ok(serde_json::to_value(nfts)?)
+        }
+        BlockchainRequest::Contract { address, include_abi } => {
+            Ok(serde_json::to_value(contract::get_contract(network, &address, include_abi).await?)?)
+        }
+        BlockchainRequest::BalanceHistory { address, cursor } => {
+            Ok(serde_json::to_value(history::get_balance_history(network, &address, cursor.as_deref()).await?)?)
+        }
+        BlockchainRequest::Gas { blocks } => Ok(serde_json::to_value(gas::get_gas(network, blocks).await?)?),
+        BlockchainRequest::EstimateGas(call) => Ok(serde_json::to_value(gas::estimate_gas(network, &call).await?)?),
+        BlockchainRequest::PrepareTransaction(request) => {
+            Ok(serde_json::to_value(prepare::prepare_transaction(network, &request).await?)?)
+        }
+        BlockchainRequest::SendTransaction(request) => {
+            let network_name = call.network.as_deref().unwrap_or(&config.blockchain.default_network);
+            let sent = signer::send_transaction(&config.blockchain.signer, network_name, network, &request).await?;
+            Ok(serde_json::to_value(sent)?)
+        }
+        BlockchainRequest::Batch { requests, concurrency } => {
+            let results = batch::run(&config.blockchain.batch, call.network, requests, concurrency).await?;
+            Ok(serde_json::to_value(results)?)
+        }
+    }
+}
+
+pub async fn get_wallet_balance(network: &NetworkConfig, address: &str) -> Result<ExplorerResponse, BlockchainError> {
+    let address = validate_address(address)?;
+
+    let mut balance: ExplorerResponse = match network.source {
+        ChainSource::Explorer => explorer_get(network, &format!("/addresses/{}", address)).await?,
+        ChainSource::Rpc => get_wallet_balance_rpc(network, &address).await?,
+    };
+    balance.coin_balance_formatted = format_units(&balance.coin_balance, network.decimals);
+    Ok(balance)
+}
+
+/// Balance from a JSON-RPC node, in the explorer's shape. Only the balance, block
+/// and contract fields are filled in; explorer-only metadata is left empty.
+async fn get_wallet_balance_rpc(network: &NetworkConfig, address: &Address) -> Result<ExplorerResponse, BlockchainError> {
+    let address = address.to_string();
+    let rpc = RpcClient::new(network)?;
+    let block_number = rpc.block_number().await?;
+    let block = format!("0x{:x}", block_number);
+    let balance = rpc.get_balance(&address, &block).await?;
+    let code = rpc.get_code(&address, &block).await?;
+
+    Ok(ExplorerResponse {
+        block_number_balance_updated_at: block_number,
+        coin_balance: balance.to_string(),
+        hash: address,
+        is_contract: code != "0x",
+        ..Default::default()
+    })
+}
+
+/// Call a view function with `eth_call` and decode its outputs.
+pub async fn contract_call(
+    network: &NetworkConfig,
+    contract: &str,
+    function: &Function,
+    args: &[serde_json::Value],
+    block: Option<&str>,
+) -> Result<serde_json::Value, BlockchainError> {
+    let contract = validate_address(contract)?.to_string();
+
+    let data = function.encode_call(args)?;
+    let output = RpcClient::new(network)?.eth_call(&contract, &data, &block_tag(block)).await?;
+    Ok(serde_json::json!({
+        "contract": contract,
+        "function": function.signature(),
+        "outputs": function.decode_output(&output)?,
+        "raw": format!("0x{}", to_hex(&output)),
+    }))
+}
+
+async fn block_number(network: &NetworkConfig) -> Result<u64, BlockchainError> {
+    match network.source {
+        ChainSource::Rpc => RpcClient::new(network)?.block_number().await,
+        ChainSource::Explorer => get_explorer_block_number(network).await,
+    }
+}
+
+async fn get_explorer_block_number(network: &NetworkConfig) -> Result<u64, BlockchainError> {
+    #[derive(Deserialize)]
+    struct Block {
+        height: u64,
+    }
+
+    let blocks: Vec<Block> = explorer_get(network, "/main-page/blocks").await?;
+    blocks.first()
+        .map(|block| block.height)
+        .ok_or_else(|| BlockchainError::NotFound("latest block".to_string()))
+}
+
+/// Every ERC-20 balance held by `address`, from the explorer's unpaginated `/token-balances`.
+pub async fn get_token_balances(network: &NetworkConfig, address: &str) -> Result<TokenResponse, BlockchainError> {
+    let address = validate_address(address)?;
+
+    let path = format!("/addresses/{}/token-balances", address);
+    let balances: Vec<ExplorerTokenBalance> = explorer_get(network, &path).await?;
+    Ok(TokenResponse {
+        items: balances.into_iter()
+            .filter(|balance| balance.token.token_type == ERC20)
+            .map(TokenBalance::from)
+            .collect(),
+        next_cursor: None,
+    })
+}
+
+/// One page of ERC-20 balances from `/tokens`. Pass the returned `next_cursor` to get the next page.
+pub async fn get_tokens_page(
+    network: &NetworkConfig,
+    address: &str,
+    cursor: Option<&str>,
+) -> Result<TokenResponse, BlockchainError> {
+    let address = validate_address(address)?;
+
+    let path = with_cursor(format!("/addresses/{}/tokens?type={}", address, ERC20), cursor);
+    let page: ExplorerPage<ExplorerTokenBalance> = explorer_get(network, &path).await?;
+    Ok(TokenResponse {
+        next_cursor: page.next_cursor(),
+        items: page.items.into_iter().map(TokenBalance::from).collect(),
+    })
+}
+
+/// One page of an address's transactions, newest first. Pass the returned
+/// `next_cursor` back to get the next page.
+pub async fn get_transactions(
+    network: &NetworkConfig,
+    address: &str,
+    filter: Option<TransactionFilter>,
+    cursor: Option<&str>,
+) -> Result<TransactionPage, BlockchainError> {
+    let address = validate_address(address)?;
+
+    let mut path = format!("/addresses/{}/transactions", address);
+    if let Some(filter) = filter {
+        path.push_str(match filter {
+            TransactionFilter::To => "?filter=to",
+            TransactionFilter::From => "?filter=from",
+        });
+    }
+    let page: ExplorerPage<ExplorerTransaction> = explorer_get(network, &with_cursor(path, cursor)).await?;
+    Ok(TransactionPage {
+        next_cursor: page.next_cursor(),
+        items: page.items.into_iter()
+            .map(|tx| TransactionSummary::from_explorer(tx, network.decimals))
+            .collect(),
+    })
+}
+
+/// Parse an address strictly; mixed-case input must carry a valid EIP-55 checksum.
+/// Paths and responses use the returned address, so output is always checksummed.
+pub(crate) fn validate_address(address: &str) -> Result<Address, BlockchainError> {
+    Ok(address.trim().parse::<Address>()?)
+}
+
+/// A raw integer amount as an exact decimal string, or `None` if it isn't a valid integer.
+pub(crate) fn format_units(raw: &str, decimals: u8) -> Option<String> {
+    Amount::from_raw(raw, decimals).ok().map(|amount| amount.to_string())
+}
+
+/// A token's `decimals()` result. More than 77 is rejected rather than truncated to a
+/// `u8`, since no U256 amount can have that many decimal places.
+pub(crate) fn token_decimals(token: &str, decimals: U256) -> Result<u8, BlockchainError> {
+    if decimals > U256::from(MAX_TOKEN_DECIMALS) {
+        return Err(BlockchainError::InvalidRequest(format!(
+            "{} reports {} decimals, more than the {} an amount can have",
+            token, decimals, MAX_TOKEN_DECIMALS
+        )));
+    }
+    Ok(decimals.low_u32() as u8)
+}
+
+/// An address from a node or explorer in checksummed form; left unchanged if it doesn't parse.
+pub(crate) fn checksummed(address: String) -> String {
+    address.parse::<Address>().map(|address| address.to_string()).unwrap_or(address)
+}
+
+/// Append an opaque page cursor (the explorer's `next_page_params` as a query string) to a URL.
+pub(crate) fn with_cursor(url: String, cursor: Option<&str>) -> String {
+    match cursor {
+        Some(cursor) if !cursor.is_empty() => {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            format!("{}{}{}", url, separator, cursor)
+        }
+        _ => url,
+    }
+}
+
+/// GET an explorer API path on `network` and parse the JSON response.
+pub(crate) async fn explorer_get<T: DeserializeOwned>(network: &NetworkConfig, path: &str) -> Result<T, BlockchainError> {
+    let api_url = format!("{}{}", network.explorer_url.trim_end_matches('/'), path);
+    let response_text = send_request("explorer", network, &api_url, None).await?;
+    match serde_json::from_str::<T>(&response_text) {
+        Ok(response) => Ok(response),
+        Err(e) => {
+            println!("Failed to parse response: {}", e);
+            Err(BlockchainError::Json(e))
+        }
+    }
+}
+
+/// Send a request to a chain endpoint (a POST when there is a body, a GET otherwise),
+/// going through the rate limiter, the audit log and the endpoint's circuit breaker.
+pub(crate) async fn send_request(
+    service: &str,
+    network: &NetworkConfig,
+    api_url: &str,
+    body: Option<String>,
+) -> Result<String, BlockchainError> {
+    let endpoint = circuit_breaker::endpoint_key(api_url);
+    // A node has its own limit and bucket, even when it shares the explorer's origin
+    if service == "rpc" {
+        rate_limiter::acquire(&format!("rpc {}", endpoint), &network.rpc_rate_limit, 0).await;
+    } else if let Ok(config) = get_config() {
+        rate_limiter::acquire(&endpoint, &config.blockchain.rate_limit, 0).await;
+    }
+    circuit_breaker::acquire(&endpoint)?;
 
     let client = Client::builder()
-        .timeout(Duration::from_secs(10))
+        .timeout(Duration::from_millis(network.timeout_ms))
         .build()?;
 
-    // Use UOMI Explorer API endpoint
-    let api_url = format!("https://explorer.uomi.ai/api/v2/addresses/{}", address);
-    
     let mut headers = HeaderMap::new();
     headers.insert("accept", "*/*".parse().unwrap());
     headers.insert("user-agent", "uomi-agent/1.0".parse().unwrap());
-    
-    // API call in progress
-    
-    let response = client
-        .get(&api_url)
-        .headers(headers)
-        .send()
-        .await?;
+
+    let request = match &body {
+        Some(body) => {
+            headers.insert("content-type", "application/json".parse().unwrap());
+            client.post(api_url).body(body.clone())
+        }
+        None => client.get(api_url),
+    };
+    let method = if body.is_some() { "POST" } else { "GET" };
+
+    let audit = AuditRecord::request(service, method, api_url, body.as_deref().map(str::as_bytes));
+    let response = match request.headers(headers).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            circuit_breaker::record_failure(&endpoint, &e.to_string());
+            audit.error(&e).log();
+            return Err(e.into());
+        }
+    };
 
     let status = response.status();
     if !status.is_success() {
         let error_text = response.text().await.unwrap_or_default();
+        audit.response(status.as_u16(), error_text.as_bytes()).log();
+        if status.is_server_error() {
+            circuit_breaker::record_failure(&endpoint, &status.to_string());
+        } else {
+            circuit_breaker::record_success(&endpoint);
+        }
         println!("API Error: {} - {}", status, error_text);
         return Err(BlockchainError::ApiError {
             status: status.as_u16(),
@@ -85,11 +1025,167 @@ pub async fn get_wallet_balance(address: &str) -> Result<ExplorerResponse, Block
     }
 
     let response_text = response.text().await?;
-    match serde_json::from_str::<ExplorerResponse>(&response_text) {
-        Ok(response) => Ok(response),
-        Err(e) => {
-            println!("Failed to parse response: {}", e);
-            Err(BlockchainError::Json(e))
+    audit.response(status.as_u16(), response_text.as_bytes()).log();
+    circuit_breaker::record_success(&endpoint);
+    Ok(response_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn network(source: &str) -> NetworkConfig {
+        serde_json::from_value(json!({"explorer_url": "http://localhost", "chain_id": 1, "source": source})).unwrap()
+    }
+
+    fn request(value: serde_json::Value) -> BlockchainRequest {
+        BlockchainCall::parse(value.to_string().as_bytes()).unwrap().request
+    }
+
+    #[test]
+    fn pinned_reads_are_historical_once_confirmed() {
+        let network = network("explorer");
+        let call = request(json!({"action": "get_code", "address": "0x00", "block": "0x64"}));
+        assert_eq!(call.cache_policy(&network, Some(112), 12), CachePolicy::Historical);
+        assert_eq!(call.cache_policy(&network, Some(111), 12), CachePolicy::Latest);
+        assert_eq!(call.cache_policy(&network, None, 12), CachePolicy::Latest);
+
+        let call = request(json!({"action": "get_code", "address": "0x00", "block": "latest"}));
+        assert_eq!(call.pinned_block(&network), None);
+        assert_eq!(call.cache_policy(&network, Some(1000), 12), CachePolicy::Latest);
+    }
+
+    #[test]
+    fn only_rpc_log_ranges_are_pinned() {
+        let logs = request(json!({"action": "get_logs", "from_block": "1", "to_block": "100"}));
+        assert_eq!(logs.pinned_block(&network("rpc")), Some(100));
+        assert_eq!(logs.pinned_block(&network("explorer")), None);
+        assert_eq!(logs.cache_policy(&network("explorer"), Some(1000), 12), CachePolicy::Latest);
+    }
+
+    #[test]
+    fn timestamp_queries_are_pinned_to_their_block() {
+        let network = network("rpc");
+        let mut call = BlockchainCall::from_value(json!({
+            "action": "get_historical_balance",
+            "address": "0x00",
+            "timestamp": "2024-01-01",
+            "network": "uomi",
+        }))
+        .unwrap();
+        assert_eq!(call.unpinned_timestamp(), Some(&json!("2024-01-01")));
+        assert_eq!(call.request.pinned_block(&network), None);
+
+        call.pin_to_block(100);
+        assert_eq!(call.unpinned_timestamp(), None);
+        assert_eq!(call.params, json!({"action": "get_historical_balance", "address": "0x00", "block": "0x64"}));
+        assert_eq!(call.request.pinned_block(&network), Some(100));
+        assert_eq!(call.request.cache_policy(&network, Some(112), 12), CachePolicy::Historical);
+
+        // A query with both is left for the request to reject
+        let both = json!({"action": "get_historical_balance", "address": "0x00", "block": "0x1", "timestamp": 1});
+        assert_eq!(BlockchainCall::from_value(both).unwrap().unpinned_timestamp(), None);
+    }
+
+    #[test]
+    fn transactions_are_cached_once_mined() {
+        let network = network("explorer");
+        let call = request(json!({"action": "get_transaction", "hash": "0x00"}));
+        assert_eq!(call.cache_policy(&network, None, 12), CachePolicy::Mined { head: None, confirmations: 12 });
+        let call = request(json!({"action": "get_transaction_receipt", "hash": "0x00"}));
+        assert_eq!(call.cache_policy(&network, Some(7), 12), CachePolicy::Mined { head: Some(7), confirmations: 12 });
+    }
+
+    #[test]
+    fn state_changing_and_head_reads_are_not_cached() {
+        let network = network("explorer");
+        let call = request(json!({"action": "get_block_number"}));
+        assert_eq!(call.cache_policy(&network, Some(1), 12), CachePolicy::Skip);
+        let call = request(json!({"action": "batch", "requests": []}));
+        assert_eq!(call.cache_policy(&network, Some(1), 12), CachePolicy::Skip);
+    }
+
+    #[test]
+    fn actions_match_the_request_variants() {
+        // serde lists every variant it accepts when it meets an unknown one
+        let error = serde_json::from_value::<BlockchainRequest>(json!({"action": "no_such_action"})).unwrap_err();
+        let message = error.to_string();
+        let (_, expected) = message.split_once("expected one of ").unwrap();
+        let mut variants: Vec<&str> = expected.split(", ").map(|variant| variant.trim_matches('`')).collect();
+        let mut actions = BlockchainRequest::ACTIONS.to_vec();
+        variants.sort_unstable();
+        actions.sort_unstable();
+        assert_eq!(actions, variants);
+    }
+
+    #[test]
+    fn unknown_actions_are_told_apart_from_bad_parameters() {
+        let error = BlockchainCall::parse(br#"{"action": "get_balances"}"#).unwrap_err();
+        assert!(matches!(error, BlockchainError::UnknownAction(action) if action == "get_balances"));
+        let error = BlockchainCall::parse(br#"{"action": "get_balance"}"#).unwrap_err();
+        assert!(matches!(error, BlockchainError::InvalidRequest(_)));
+    }
+
+    #[test]
+    fn errors_map_to_specific_kinds() {
+        let json = serde_json::from_str::<serde_json::Value>("<html>").unwrap_err();
+        assert_eq!(BlockchainError::Json(json).kind(), ErrorKind::InvalidResponse);
+        assert_eq!(BlockchainError::Config("no rpc_url".to_string()).kind(), ErrorKind::ConfigError);
+        assert_eq!(BlockchainError::Signer("wrong keystore password".to_string()).kind(), ErrorKind::SignerError);
+        let status = BlockchainError::ApiError { status: 429, message: String::new() };
+        assert_eq!(status.kind(), ErrorKind::RateLimited);
+        assert_eq!(status.to_chain_error().status, Some(429));
+    }
+
+    fn token_balance(decimals: Option<&str>) -> TokenBalance {
+        let balance: ExplorerTokenBalance = serde_json::from_value(json!({
+            "token": {"address": "0x00", "symbol": "TKN", "decimals": decimals, "type": "ERC-20"},
+            "token_id": null,
+            "value": "1500000000000000000",
+        }))
+        .unwrap();
+        TokenBalance::from(balance)
+    }
+
+    #[test]
+    fn token_balances_are_formatted_only_with_known_decimals() {
+        let known = token_balance(Some("18"));
+        assert_eq!(known.decimals, Some(18));
+        assert_eq!(known.balance_formatted.as_deref(), Some("1.5"));
+        for decimals in [None, Some(""), Some("eighteen"), Some("300")] {
+            let unknown = token_balance(decimals);
+            assert_eq!(unknown.decimals, None, "{:?}", decimals);
+            assert_eq!(unknown.balance_formatted, None, "{:?}", decimals);
+            assert_eq!(unknown.balance, "1500000000000000000");
         }
     }
+
+    #[test]
+    fn token_decimals_above_77_are_rejected() {
+        assert_eq!(token_decimals("0x00", U256::from(18)).unwrap(), 18);
+        assert_eq!(token_decimals("0x00", U256::from(77)).unwrap(), 77);
+        for decimals in [78u64, 256, 300] {
+            assert!(matches!(token_decimals("0x00", U256::from(decimals)), Err(BlockchainError::InvalidRequest(_))));
+        }
+        assert!(token_decimals("0x00", U256::MAX).is_err());
+    }
+
+    #[tokio::test]
+    async fn malformed_receipt_hashes_are_invalid_requests() {
+        let config = get_config().unwrap();
+        let network = config.blockchain.network(None).unwrap();
+        let call = BlockchainCall::parse(br#"{"action": "get_transaction_receipt", "hash": "0x1234"}"#).unwrap();
+        let error = execute(&config, network, call).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidRequest);
+    }
+
+    #[test]
+    fn explorer_404_is_not_found() {
+        let error = BlockchainError::ApiError { status: 404, message: "Not found".to_string() };
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert_eq!(error.to_chain_error().status, Some(404));
+        let error = BlockchainError::ApiError { status: 500, message: String::new() };
+        assert_eq!(error.kind(), ErrorKind::UpstreamError);
+    }
 }
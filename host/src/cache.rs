@@ -0,0 +1,385 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::api_service::CacheConfig;
+
+/// Shortest time between two writes of the cache file while a run is storing entries.
+/// Whatever is left is written by `flush` when the run ends.
+const SAVE_INTERVAL_MS: u64 = 5000;
+
+/// How long a response stays valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Never cached.
+    Skip,
+    /// Valid for `ttl_ms`, and only until a new block is seen.
+    Latest,
+    /// Pinned to a block deep enough below the head, valid until evicted.
+    Historical,
+    /// A transaction or receipt: historical once its block is `confirmations` below `head`,
+    /// latest while pending or too recent to be safe from a reorg.
+    Mined { head: Option<u64>, confirmations: u64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    value: serde_json::Value,
+    stored_at_ms: u64,
+    /// Head block when the entry was stored; `None` for historical entries.
+    block: Option<u64>,
+    historical: bool,
+}
+
+/// Cached responses, along with the latest block read per network so a fresh run doesn't
+/// drop every latest entry.
+#[derive(Debug, Default)]
+struct Cache {
+    entries: BTreeMap<String, CacheEntry>,
+    /// Entries changed since the file was last written.
+    dirty: bool,
+    saved_at_ms: u64,
+}
+
+/// Responses shared by every request of this process and persisted between runs.
+static CACHE: Mutex<Option<Cache>> = Mutex::new(None);
+
+/// Cache key for a request: its network and its parameters as canonical JSON.
+pub fn key(network: &str, params: &serde_json::Value) -> String {
+    format!("{}:{}", network, params)
+}
+
+/// A cached response for `key`, if it has neither expired nor been passed by `head`.
+/// With no known head, latest entries fall back to their TTL alone.
+pub fn get(config: &CacheConfig, key: &str, head: Option<u64>) -> Option<serde_json::Value> {
+    if !config.enabled {
+        return None;
+    }
+    with_cache(config, |cache| cache.get(config, key, head, now_ms()))
+}
+
+/// Whether the entry cached for `key` is only valid as long as no newer block has been
+/// seen, so the head must be known to serve it.
+pub fn needs_head(config: &CacheConfig, key: &str) -> bool {
+    config.enabled && with_cache(config, |cache| cache.needs_head(config, key, now_ms()))
+}
+
+/// Store a response under `policy`. `head` is the latest block when it was fetched.
+pub fn put(config: &CacheConfig, key: String, policy: CachePolicy, value: &serde_json::Value, head: Option<u64>) {
+    let Some(historical) = is_historical(policy, value) else { return };
+    if !config.enabled {
+        return;
+    }
+
+    store(config, key, CacheEntry {
+        value: value.clone(),
+        stored_at_ms: now_ms(),
+        block: if historical { None } else { head },
+        historical,
+    });
+}
+
+/// The latest block of `network` if it was read within `head_ttl_ms`, by this run or an earlier one.
+pub fn head(config: &CacheConfig, network: &str) -> Option<u64> {
+    if !config.enabled {
+        return None;
+    }
+    with_cache(config, |cache| cache.head(config, network, now_ms()))
+}
+
+pub fn record_head(config: &CacheConfig, network: &str, block: u64) {
+    if !config.enabled {
+        return;
+    }
+    store(config, head_key(network), CacheEntry {
+        value: serde_json::json!(block),
+        stored_at_ms: now_ms(),
+        block: Some(block),
+        historical: false,
+    });
+}
+
+/// Write entries stored since the last save. Called when the run ends.
+pub fn flush(config: &CacheConfig) {
+    if !config.enabled {
+        return;
+    }
+    let mut guard = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(cache) = guard.as_mut() {
+        cache.save(&config.path, now_ms());
+    }
+}
+
+/// Request keys end in their JSON parameters, so this can't collide with one.
+fn head_key(network: &str) -> String {
+    format!("{}:head", network)
+}
+
+fn with_cache<T>(config: &CacheConfig, f: impl FnOnce(&mut Cache) -> T) -> T {
+    let mut guard = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(|| Cache { entries: load(&config.path), ..Default::default() }))
+}
+
+/// Insert `entry` and save if the file hasn't been written for `SAVE_INTERVAL_MS`.
+fn store(config: &CacheConfig, key: String, entry: CacheEntry) {
+    with_cache(config, |cache| {
+        let now = now_ms();
+        cache.insert(config, key, entry, now);
+        if now.saturating_sub(cache.saved_at_ms) >= SAVE_INTERVAL_MS {
+            cache.save(&config.path, now);
+        }
+    })
+}
+
+impl Cache {
+    fn get(&self, config: &CacheConfig, key: &str, head: Option<u64>, now: u64) -> Option<serde_json::Value> {
+        let entry = self.entries.get(key)?;
+        if entry.historical {
+            return Some(entry.value.clone());
+        }
+
+        let expired = now.saturating_sub(entry.stored_at_ms) >= config.ttl_ms;
+        let new_block = matches!((head, entry.block), (Some(head), Some(block)) if head > block);
+        if expired || new_block {
+            return None;
+        }
+        Some(entry.value.clone())
+    }
+
+    fn needs_head(&self, config: &CacheConfig, key: &str, now: u64) -> bool {
+        self.entries.get(key).is_some_and(|entry| {
+            !entry.historical && entry.block.is_some() && now.saturating_sub(entry.stored_at_ms) < config.ttl_ms
+        })
+    }
+
+    fn head(&self, config: &CacheConfig, network: &str, now: u64) -> Option<u64> {
+        let entry = self.entries.get(&head_key(network))?;
+        entry.block.filter(|_| now.saturating_sub(entry.stored_at_ms) < config.head_ttl_ms)
+    }
+
+    /// Insert `entry` and evict down to `max_entries`: expired latest entries first, then
+    /// the oldest ones.
+    fn insert(&mut self, config: &CacheConfig, key: String, entry: CacheEntry, now: u64) {
+        self.entries.insert(key, entry);
+        self.dirty = true;
+
+        if self.entries.len() > config.max_entries {
+            self.entries.retain(|_, entry| entry.historical || now.saturating_sub(entry.stored_at_ms) < config.ttl_ms);
+        }
+        while self.entries.len() > config.max_entries {
+            let oldest = self.entries.iter()
+                .min_by_key(|(_, entry)| entry.stored_at_ms)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => self.entries.remove(&oldest),
+                None => break,
+            };
+        }
+    }
+
+    fn save(&mut self, path: &str, now: u64) {
+        if !self.dirty {
+            return;
+        }
+        match save(path, &self.entries) {
+            Ok(()) => {
+                self.dirty = false;
+                self.saved_at_ms = now;
+            }
+            Err(e) => eprintln!("Failed to save blockchain cache: {}", e),
+        }
+    }
+}
+
+/// Whether a response stored under `policy` is historical; `None` if it isn't stored at all.
+fn is_historical(policy: CachePolicy, value: &serde_json::Value) -> Option<bool> {
+    match policy {
+        CachePolicy::Skip => None,
+        CachePolicy::Latest => Some(false),
+        CachePolicy::Historical => Some(true),
+        CachePolicy::Mined { head, confirmations } => Some(matches!(
+            (mined_block(value), head),
+            (Some(block), Some(head)) if block.saturating_add(confirmations) <= head
+        )),
+    }
+}
+
+/// Block of a transaction or receipt: the explorer's `block_number` or the node's hex `blockNumber`.
+fn mined_block(value: &serde_json::Value) -> Option<u64> {
+    if let Some(block) = value.get("block_number").and_then(|block| block.as_u64()) {
+        return Some(block);
+    }
+    let block = value.get("blockNumber")?.as_str()?;
+    u64::from_str_radix(block.strip_prefix("0x")?, 16).ok()
+}
+
+fn load(path: &str) -> BTreeMap<String, CacheEntry> {
+    if !Path::new(path).exists() {
+        return BTreeMap::new();
+    }
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+/// Write to a temporary file and rename it into place, so a crash never leaves a
+/// truncated cache behind.
+fn save(path: &str, entries: &BTreeMap<String, CacheEntry>) -> std::io::Result<()> {
+    let temp = format!("{}.tmp", path);
+    let mut file = std::fs::File::create(&temp)?;
+    file.write_all(&serde_json::to_vec(entries)?)?;
+    file.sync_all()?;
+    std::fs::rename(&temp, path)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config() -> CacheConfig {
+        CacheConfig { ttl_ms: 1000, head_ttl_ms: 100, max_entries: 3, ..Default::default() }
+    }
+
+    fn latest(value: u64, stored_at_ms: u64, block: Option<u64>) -> CacheEntry {
+        CacheEntry { value: json!(value), stored_at_ms, block, historical: false }
+    }
+
+    fn historical(value: u64, stored_at_ms: u64) -> CacheEntry {
+        CacheEntry { value: json!(value), stored_at_ms, block: None, historical: true }
+    }
+
+    #[test]
+    fn latest_entries_expire_after_the_ttl() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "a".to_string(), latest(1, 0, None), 0);
+        assert_eq!(cache.get(&config, "a", None, 999), Some(json!(1)));
+        assert_eq!(cache.get(&config, "a", None, 1000), None);
+        assert_eq!(cache.get(&config, "missing", None, 0), None);
+    }
+
+    #[test]
+    fn latest_entries_are_dropped_by_a_newer_head() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "a".to_string(), latest(1, 0, Some(100)), 0);
+        assert_eq!(cache.get(&config, "a", Some(100), 10), Some(json!(1)));
+        assert_eq!(cache.get(&config, "a", Some(101), 10), None);
+        // An unknown head falls back to the TTL
+        assert_eq!(cache.get(&config, "a", None, 10), Some(json!(1)));
+    }
+
+    #[test]
+    fn historical_entries_ignore_ttl_and_head() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "a".to_string(), historical(1, 0), 0);
+        assert_eq!(cache.get(&config, "a", Some(u64::MAX), u64::MAX), Some(json!(1)));
+        assert!(!cache.needs_head(&config, "a", 0));
+    }
+
+    #[test]
+    fn only_live_entries_stored_at_a_block_need_the_head() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "at_block".to_string(), latest(1, 0, Some(100)), 0);
+        cache.insert(&config, "no_block".to_string(), latest(2, 0, None), 0);
+        assert!(cache.needs_head(&config, "at_block", 10));
+        assert!(!cache.needs_head(&config, "at_block", 1000));
+        assert!(!cache.needs_head(&config, "no_block", 10));
+        assert!(!cache.needs_head(&config, "missing", 10));
+    }
+
+    #[test]
+    fn head_is_trusted_for_head_ttl() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, head_key("uomi"), latest(42, 0, Some(42)), 0);
+        assert_eq!(cache.head(&config, "uomi", 99), Some(42));
+        assert_eq!(cache.head(&config, "uomi", 100), None);
+        assert_eq!(cache.head(&config, "other", 0), None);
+    }
+
+    #[test]
+    fn evicts_expired_then_oldest_entries() {
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "expired".to_string(), latest(1, 0, None), 0);
+        cache.insert(&config, "old".to_string(), historical(2, 10), 10);
+        cache.insert(&config, "newer".to_string(), latest(3, 1500, None), 1500);
+        cache.insert(&config, "newest".to_string(), latest(4, 1600, None), 1600);
+        assert_eq!(cache.entries.keys().collect::<Vec<_>>(), ["newer", "newest", "old"]);
+
+        cache.insert(&config, "latest".to_string(), latest(5, 1700, None), 1700);
+        assert_eq!(cache.entries.keys().collect::<Vec<_>>(), ["latest", "newer", "newest"]);
+    }
+
+    #[test]
+    fn mined_block_reads_explorer_and_node_fields() {
+        assert_eq!(mined_block(&json!({"block_number": 5})), Some(5));
+        assert_eq!(mined_block(&json!({"blockNumber": "0x5"})), Some(5));
+        assert_eq!(mined_block(&json!({"block_number": null})), None);
+        assert_eq!(mined_block(&json!({"hash": "0x01"})), None);
+    }
+
+    #[test]
+    fn mined_entries_are_historical_once_confirmed() {
+        let mined = |head| CachePolicy::Mined { head, confirmations: 12 };
+        let receipt = json!({"blockNumber": "0x64", "status": "0x1"});
+        assert_eq!(is_historical(mined(Some(112)), &receipt), Some(true));
+        // Just mined: could still be reorged out
+        assert_eq!(is_historical(mined(Some(101)), &receipt), Some(false));
+        assert_eq!(is_historical(mined(None), &receipt), Some(false));
+        // Pending
+        assert_eq!(is_historical(mined(Some(1000)), &json!({"blockNumber": null})), Some(false));
+        assert_eq!(is_historical(CachePolicy::Skip, &receipt), None);
+    }
+
+    #[test]
+    fn just_mined_receipts_are_dropped_by_the_next_block() {
+        let config = config();
+        let mut cache = Cache::default();
+        let receipt = json!({"blockNumber": "0x64", "status": "0x1"});
+        let policy = CachePolicy::Mined { head: Some(101), confirmations: 12 };
+        let historical = is_historical(policy, &receipt).unwrap();
+        let entry = CacheEntry { value: receipt.clone(), stored_at_ms: 0, block: Some(101), historical };
+        cache.insert(&config, "receipt".to_string(), entry, 0);
+        assert_eq!(cache.get(&config, "receipt", Some(101), 10), Some(receipt));
+        assert_eq!(cache.get(&config, "receipt", Some(102), 10), None);
+    }
+
+    #[test]
+    fn saves_only_when_dirty_through_a_temporary_file() {
+        let dir = std::env::temp_dir().join(format!("cache-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cache.json").to_string_lossy().into_owned();
+
+        let config = config();
+        let mut cache = Cache::default();
+        cache.insert(&config, "a".to_string(), historical(1, 0), 0);
+        cache.save(&path, 10);
+        assert!(!cache.dirty);
+        assert_eq!(cache.saved_at_ms, 10);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        assert_eq!(load(&path).len(), 1);
+
+        std::fs::remove_file(&path).unwrap();
+        cache.save(&path, 20);
+        assert!(!Path::new(&path).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Mutex, OnceLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+use crate::api_service::{get_config, CircuitBreakerConfig};
+
+#[derive(Error, Debug)]
+#[error("Circuit open for {endpoint}, retry in {retry_in_ms} ms")]
+pub struct CircuitOpen {
+    pub endpoint: String,
+    pub retry_in_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    #[default]
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct EndpointBreaker {
+    pub state: BreakerState,
+    pub consecutive_failures: u32,
+    /// When the breaker last opened, or when the current half-open probe started.
+    pub changed_at_ms: u64,
+    pub last_error: Option<String>,
+}
+
+/// Breaker state shared by every request of this process and persisted between runs.
+static BREAKERS: Mutex<Option<BTreeMap<String, EndpointBreaker>>> = Mutex::new(None);
+
+static CONFIG: OnceLock<CircuitBreakerConfig> = OnceLock::new();
+
+impl EndpointBreaker {
+    /// Let a request through unless the breaker is open. Once the open interval has
+    /// elapsed a single probe goes through half-open. `Ok(true)` when the state changed;
+    /// `Err` holds the time left until the next probe.
+    fn acquire(&mut self, config: &CircuitBreakerConfig, now: u64) -> Result<bool, u64> {
+        let elapsed = now.saturating_sub(self.changed_at_ms);
+        match self.state {
+            BreakerState::Closed => Ok(false),
+            // Also re-probes if a previous probe never reported back.
+            BreakerState::Open | BreakerState::HalfOpen if elapsed >= config.open_ms => {
+                self.state = BreakerState::HalfOpen;
+                self.changed_at_ms = now;
+                Ok(true)
+            }
+            BreakerState::Open | BreakerState::HalfOpen => Err(config.open_ms - elapsed),
+        }
+    }
+
+    /// Close the breaker. `true` when it was not closed before.
+    fn record_success(&mut self) -> bool {
+        let changed = self.state != BreakerState::Closed;
+        *self = EndpointBreaker::default();
+        changed
+    }
+
+    /// Count a failure and open the breaker once `failure_threshold` failures follow each
+    /// other, or straight away if the half-open probe failed. `true` when it just opened.
+    fn record_failure(&mut self, config: &CircuitBreakerConfig, error: &str, now: u64) -> bool {
+        self.consecutive_failures += 1;
+        self.last_error = Some(error.to_string());
+
+        let reopen = self.state == BreakerState::HalfOpen;
+        if !reopen && self.consecutive_failures < config.failure_threshold {
+            return false;
+        }
+        let changed = self.state != BreakerState::Open;
+        self.state = BreakerState::Open;
+        self.changed_at_ms = now;
+        changed
+    }
+}
+
+/// Breakers are keyed by origin, so all models behind one server share a breaker.
+pub fn endpoint_key(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => url.origin().ascii_serialization(),
+        Err(_) => url.to_string(),
+    }
+}
+
+/// Ask to send a request to `endpoint`. Fails fast while the breaker is open and
+/// lets a single probe through once the open interval has elapsed.
+pub fn acquire(endpoint: &str) -> Result<(), CircuitOpen> {
+    let config = breaker_config();
+    if !config.enabled {
+        return Ok(());
+    }
+
+    with_breakers(config, |breakers| {
+        match breakers.entry(endpoint.to_string()).or_default().acquire(config, now_ms()) {
+            Ok(changed) => {
+                if changed {
+                    println!("Circuit breaker for {} half-open, probing", endpoint);
+                }
+                (changed, Ok(()))
+            }
+            Err(retry_in_ms) => (false, Err(CircuitOpen { endpoint: endpoint.to_string(), retry_in_ms })),
+        }
+    })
+}
+
+pub fn record_success(endpoint: &str) {
+    let config = breaker_config();
+    if !config.enabled {
+        return;
+    }
+
+    with_breakers(config, |breakers| {
+        let changed = breakers.entry(endpoint.to_string()).or_default().record_success();
+        if changed {
+            println!("Circuit breaker for {} closed", endpoint);
+        }
+        (changed, ())
+    })
+}
+
+/// Record a failure that says the endpoint is unhealthy (transport error, timeout, 5xx).
+pub fn record_failure(endpoint: &str, error: &str) {
+    let config = breaker_config();
+    if !config.enabled {
+        return;
+    }
+
+    with_breakers(config, |breakers| {
+        let breaker = breakers.entry(endpoint.to_string()).or_default();
+        let changed = breaker.record_failure(config, error, now_ms());
+        if changed {
+            println!(
+                "Circuit breaker for {} opened after {} consecutive failures",
+                endpoint, breaker.consecutive_failures
+            );
+        }
+        (changed, ())
+    })
+}
+
+/// Print the state of every known breaker.
+pub fn print_diagnostics() {
+    let config = breaker_config();
+    let breakers = with_breakers(config, |breakers| (false, breakers.clone()));
+    if breakers.is_empty() {
+        println!("No circuit breakers recorded in {}", config.state_path);
+        return;
+    }
+
+    let now = now_ms();
+    for (endpoint, breaker) in &breakers {
+        let since = now.saturating_sub(breaker.changed_at_ms) / 1000;
+        println!(
+            "{:<40} {:<10} failures: {:<4} {}",
+            endpoint,
+            format!("{:?}", breaker.state).to_lowercase(),
+            breaker.consecutive_failures,
+            match (&breaker.state, &breaker.last_error) {
+                (BreakerState::Closed, _) => String::new(),
+                (_, Some(error)) => format!("since {}s ago, last error: {}", since, error),
+                (_, None) => format!("since {}s ago", since),
+            }
+        );
+    }
+}
+
+/// The configuration, read once per process.
+fn breaker_config() -> &'static CircuitBreakerConfig {
+    CONFIG.get_or_init(|| get_config().map(|config| config.circuit_breaker).unwrap_or_default())
+}
+
+/// Run `f` on the breakers. It returns whether a breaker changed state along with its
+/// result; only state changes are saved, not every failure count.
+fn with_breakers<T>(
+    config: &CircuitBreakerConfig,
+    f: impl FnOnce(&mut BTreeMap<String, EndpointBreaker>) -> (bool, T),
+) -> T {
+    let mut guard = BREAKERS.lock().unwrap_or_else(|e| e.into_inner());
+    let breakers = guard.get_or_insert_with(|| load(&config.state_path));
+    let (changed, result) = f(breakers);
+    if changed {
+        if let Err(e) = save(&config.state_path, breakers) {
+            eprintln!("Failed to save circuit breaker state: {}", e);
+        }
+    }
+    result
+}
+
+fn load(path: &str) -> BTreeMap<String, EndpointBreaker> {
+    if !Path::new(path).exists() {
+        return BTreeMap::new();
+    }
+    std::fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn save(path: &str, breakers: &BTreeMap<String, EndpointBreaker>) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_vec_pretty(breakers)?)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitBreakerConfig {
+        CircuitBreakerConfig { failure_threshold: 3, open_ms: 1000, ..Default::default() }
+    }
+
+    /// A breaker opened at `at` by `failure_threshold` failures.
+    fn opened(config: &CircuitBreakerConfig, at: u64) -> EndpointBreaker {
+        let mut breaker = EndpointBreaker::default();
+        for _ in 1..config.failure_threshold {
+            assert!(!breaker.record_failure(config, "502 Bad Gateway", at));
+        }
+        assert!(breaker.record_failure(config, "502 Bad Gateway", at));
+        breaker
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let config = config();
+        let mut breaker = EndpointBreaker::default();
+        assert!(!breaker.record_failure(&config, "timeout", 0));
+        assert!(!breaker.record_failure(&config, "timeout", 0));
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.acquire(&config, 0), Ok(false));
+
+        assert!(breaker.record_failure(&config, "timeout", 5));
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.last_error.as_deref(), Some("timeout"));
+        assert_eq!(breaker.acquire(&config, 405), Err(600));
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let config = config();
+        let mut breaker = EndpointBreaker::default();
+        breaker.record_failure(&config, "timeout", 0);
+        breaker.record_failure(&config, "timeout", 0);
+        assert!(!breaker.record_success());
+        assert!(!breaker.record_failure(&config, "timeout", 0));
+        assert_eq!(breaker.state, BreakerState::Closed);
+    }
+
+    #[test]
+    fn half_open_probe_closes_on_success() {
+        let config = config();
+        let mut breaker = opened(&config, 0);
+        assert_eq!(breaker.acquire(&config, 1000), Ok(true));
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        // Only the probe goes through until it reports back
+        assert_eq!(breaker.acquire(&config, 1001), Err(999));
+
+        assert!(breaker.record_success());
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.consecutive_failures, 0);
+        assert_eq!(breaker.acquire(&config, 1002), Ok(false));
+    }
+
+    #[test]
+    fn half_open_probe_reopens_on_failure() {
+        let config = config();
+        let mut breaker = opened(&config, 0);
+        breaker.acquire(&config, 1500).unwrap();
+        assert!(breaker.record_failure(&config, "503 Service Unavailable", 1600));
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(breaker.acquire(&config, 2000), Err(600));
+        assert_eq!(breaker.acquire(&config, 2600), Ok(true));
+    }
+
+    #[test]
+    fn reprobes_when_a_probe_never_reports_back() {
+        let config = config();
+        let mut breaker = opened(&config, 0);
+        breaker.acquire(&config, 1000).unwrap();
+        assert_eq!(breaker.acquire(&config, 2000), Ok(true));
+        assert_eq!(breaker.changed_at_ms, 2000);
+    }
+
+    #[test]
+    fn failures_while_open_are_not_state_changes() {
+        let config = config();
+        let mut breaker = opened(&config, 0);
+        assert!(!breaker.record_failure(&config, "timeout", 10));
+        assert_eq!(breaker.changed_at_ms, 10);
+    }
+
+    #[test]
+    fn keys_breakers_by_origin() {
+        assert_eq!(endpoint_key("https://api.example.com/v1/chat?model=x"), "https://api.example.com");
+        assert_eq!(endpoint_key("http://localhost:11434/api/chat"), "http://localhost:11434");
+        assert_eq!(endpoint_key("not a url"), "not a url");
+    }
+}
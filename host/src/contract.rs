@@ -0,0 +1,503 @@
+use futures::join;
+use serde::{Deserialize, Serialize};
+
+use uomi_common::Address;
+
+use crate::abi::{AbiEntry, Event, Function};
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{checksummed, explorer_get, validate_address, BlockchainError, ExplorerSmartContract};
+use crate::rpc::RpcClient;
+
+/// EIP-1967 storage slots: `keccak256("eip1967.proxy.<name>") - 1`.
+const IMPLEMENTATION_SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+const BEACON_SLOT: &str = "0xa3f0ad74e5423aebfd80d3ef4346578335a9a72aeaee59ff6cb3582b35133d50";
+const ADMIN_SLOT: &str = "0xb53127684a568b3173ae13b9f8a6016e243e63b6e8ee1178d6a717850b5d6103";
+
+/// What a contract is: its proxy setup and the verified source behind it.
+#[derive(Debug, Serialize)]
+pub struct ContractSummary {
+    pub address: String,
+    pub is_contract: bool,
+    /// Explorer name of the contract or its token.
+    pub name: Option<String>,
+    pub creator: Option<String>,
+    pub creation_transaction: Option<String>,
+    /// `None` when the address is not a proxy.
+    pub proxy: Option<ProxyInfo>,
+    /// Verified source of the address itself.
+    pub source: Option<VerifiedSource>,
+    /// Verified source of the current implementation, for proxies.
+    pub implementation_source: Option<VerifiedSource>,
+    /// Things worth telling the user, e.g. an unverified implementation.
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProxyInfo {
+    /// The explorer's proxy type (e.g. `eip1967`, `eip1167`, `eip1822`), or `eip1967` /
+    /// `eip1967_beacon` when only the storage slots identify it.
+    pub proxy_type: Option<String>,
+    /// The implementation calls are delegated to now: the EIP-1967 slot when set,
+    /// otherwise the explorer's first implementation.
+    pub implementation: Option<String>,
+    pub implementations: Vec<Implementation>,
+    /// EIP-1967 admin slot.
+    pub admin: Option<String>,
+    /// EIP-1967 beacon slot; the implementation is read from the beacon.
+    pub beacon: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Implementation {
+    pub address: String,
+    pub name: Option<String>,
+    /// Where the implementation was found: `storage`, `explorer` or both.
+    pub sources: Vec<&'static str>,
+}
+
+/// Metadata of verified source code, with the ABI reduced to signatures.
+#[derive(Debug, Serialize)]
+pub struct VerifiedSource {
+    pub address: String,
+    pub name: Option<String>,
+    pub language: Option<String>,
+    pub compiler_version: Option<String>,
+    pub evm_version: Option<String>,
+    pub optimization_enabled: Option<bool>,
+    pub optimization_runs: Option<u64>,
+    pub license_type: Option<String>,
+    pub verified_at: Option<String>,
+    pub is_fully_verified: Option<bool>,
+    /// Main source file.
+    pub file_path: Option<String>,
+    pub source_files: usize,
+    pub source_lines: usize,
+    /// `view` and `pure` functions as `name(inputs)(outputs)`, ready for `contract_call`.
+    pub read_functions: Vec<String>,
+    /// State-changing functions; payable ones end in ` payable`.
+    pub write_functions: Vec<String>,
+    pub events: Vec<String>,
+    /// The full JSON ABI, when requested with `include_abi`.
+    pub abi: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerContractAddress {
+    #[serde(default)]
+    is_contract: bool,
+    name: Option<String>,
+    creator_address_hash: Option<String>,
+    #[serde(alias = "creation_tx_hash")]
+    creation_transaction_hash: Option<String>,
+    proxy_type: Option<String>,
+    #[serde(default)]
+    implementations: Vec<ExplorerImplementation>,
+}
+
+/// Older explorers list implementation addresses, newer ones `{address, name}` objects.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ExplorerImplementation {
+    Address(String),
+    Named {
+        #[serde(alias = "address_hash")]
+        address: String,
+        name: Option<String>,
+    },
+}
+
+/// EIP-1967 slots of a contract, as read from storage.
+#[derive(Debug, Default)]
+struct ProxySlots {
+    implementation: Option<Address>,
+    beacon: Option<Address>,
+    admin: Option<Address>,
+}
+
+/// Summarize a contract: whether it is a proxy and what it points to (EIP-1967 slots read
+/// over `rpc_url`, plus the explorer's proxy detection), and the verified source and ABI of
+/// the contract and its implementation.
+pub async fn get_contract(
+    network: &NetworkConfig,
+    address: &str,
+    include_abi: bool,
+) -> Result<ContractSummary, BlockchainError> {
+    let address = validate_address(address)?;
+    let rpc = network.rpc_url.as_ref().map(|_| RpcClient::new(network)).transpose()?;
+    let mut notes = Vec::new();
+
+    let explorer = match explorer_get::<ExplorerContractAddress>(network, &format!("/addresses/{}", address)).await {
+        Ok(explorer) => Some(explorer),
+        Err(BlockchainError::ApiError { status: 404, .. }) => None,
+        // Storage alone still answers the proxy question
+        Err(e) if rpc.is_some() => {
+            println!("No explorer data for {}: {}", address, e);
+            notes.push(format!("The explorer could not be reached ({}), so names and proxy detection are incomplete", e));
+            None
+        }
+        Err(e) => return Err(e),
+    };
+
+    let is_contract = match &rpc {
+        Some(rpc) => match rpc.get_code(&address.to_string(), "latest").await {
+            Ok(code) => code != "0x",
+            // Like the proxy slots below, the explorer can stand in for storage
+            Err(e) => match &explorer {
+                Some(explorer) => {
+                    println!("Could not read the code of {}: {}", address, e);
+                    notes.push(format!("The contract code could not be read ({}), so the explorer decides whether this is a contract", e));
+                    explorer.is_contract
+                }
+                None => return Err(e),
+            },
+        },
+        None => explorer.as_ref().is_some_and(|explorer| explorer.is_contract),
+    };
+    let (name, creator, creation_transaction) = match &explorer {
+        Some(explorer) => (
+            explorer.name.clone(),
+            explorer.creator_address_hash.clone().map(checksummed),
+            explorer.creation_transaction_hash.clone(),
+        ),
+        None => (None, None, None),
+    };
+    if !is_contract {
+        notes.push("The address has no contract code".to_string());
+        return Ok(ContractSummary {
+            address: address.to_string(),
+            is_contract,
+            name,
+            creator,
+            creation_transaction,
+            proxy: None,
+            source: None,
+            implementation_source: None,
+            notes,
+        });
+    }
+
+    let slots = match &rpc {
+        Some(rpc) => match proxy_slots(rpc, &address).await {
+            Ok(slots) => slots,
+            Err(e) => {
+                println!("Could not read proxy slots of {}: {}", address, e);
+                notes.push(format!("The proxy storage slots could not be read ({}), so proxy implementations come from the explorer alone", e));
+                ProxySlots::default()
+            }
+        },
+        None => {
+            notes.push("No rpc_url is configured, so proxy implementations come from the explorer alone".to_string());
+            ProxySlots::default()
+        }
+    };
+    let proxy = proxy_info(explorer.as_ref(), &slots, &mut notes);
+
+    let implementation = proxy.as_ref().and_then(|proxy| proxy.implementation.clone());
+    let contract = address.to_string();
+    let (source, implementation_source) = join!(
+        verified_source(network, &contract, include_abi),
+        async {
+            match &implementation {
+                Some(implementation) => verified_source(network, implementation, include_abi).await,
+                None => None,
+            }
+        }
+    );
+
+    if source.is_none() {
+        notes.push("The contract source is not verified on the explorer".to_string());
+    }
+    if let Some(implementation) = &implementation {
+        if implementation_source.is_none() {
+            notes.push(format!("The implementation {} is not verified on the explorer", implementation));
+        }
+    }
+
+    Ok(ContractSummary {
+        address: address.to_string(),
+        is_contract,
+        name,
+        creator,
+        creation_transaction,
+        proxy,
+        source,
+        implementation_source,
+        notes,
+    })
+}
+
+/// Read the EIP-1967 slots. A beacon proxy's implementation comes from `implementation()`
+/// on the beacon.
+async fn proxy_slots(rpc: &RpcClient<'_>, address: &Address) -> Result<ProxySlots, BlockchainError> {
+    let address = address.to_string();
+    let (implementation, beacon, admin) = join!(
+        rpc.get_storage_at(&address, IMPLEMENTATION_SLOT, "latest"),
+        rpc.get_storage_at(&address, BEACON_SLOT, "latest"),
+        rpc.get_storage_at(&address, ADMIN_SLOT, "latest"),
+    );
+    let mut slots = ProxySlots {
+        implementation: slot_address(&implementation?),
+        beacon: slot_address(&beacon?),
+        admin: slot_address(&admin?),
+    };
+
+    if let (None, Some(beacon)) = (slots.implementation, slots.beacon) {
+        let function = Function::parse("implementation()(address)")?;
+        match rpc.eth_call(&beacon.to_string(), &function.encode_call(&[])?, "latest").await {
+            Ok(output) => slots.implementation = slot_address(&output),
+            Err(e) => println!("Could not read the implementation of beacon {}: {}", beacon, e),
+        }
+    }
+    Ok(slots)
+}
+
+/// An address stored right-aligned in a 32-byte word, or `None` for an empty slot.
+fn slot_address(word: &[u8]) -> Option<Address> {
+    if word.len() < 32 || word.iter().all(|byte| *byte == 0) {
+        return None;
+    }
+    let mut address = [0u8; 20];
+    address.copy_from_slice(&word[12..32]);
+    Some(Address::from_bytes(address))
+}
+
+/// Merge what storage and the explorer say about the proxy. Storage wins when they disagree,
+/// since it is what the proxy actually delegates to.
+fn proxy_info(
+    explorer: Option<&ExplorerContractAddress>,
+    slots: &ProxySlots,
+    notes: &mut Vec<String>,
+) -> Option<ProxyInfo> {
+    let mut implementations: Vec<Implementation> = Vec::new();
+    if let Some(address) = slots.implementation {
+        implementations.push(Implementation { address: address.to_string(), name: None, sources: vec!["storage"] });
+    }
+
+    for listed in explorer.map(|explorer| explorer.implementations.as_slice()).unwrap_or_default() {
+        let (address, name) = match listed {
+            ExplorerImplementation::Address(address) => (address, None),
+            ExplorerImplementation::Named { address, name } => (address, name.clone()),
+        };
+        let Ok(address) = address.parse::<Address>() else {
+            continue;
+        };
+        match implementations.iter_mut().find(|known| known.address == address.to_string()) {
+            Some(known) => {
+                known.name = known.name.take().or(name);
+                known.sources.push("explorer");
+            }
+            None => implementations.push(Implementation { address: address.to_string(), name, sources: vec!["explorer"] }),
+        }
+    }
+
+    let proxy_type = explorer.and_then(|explorer| explorer.proxy_type.clone())
+        .or_else(|| match (slots.implementation, slots.beacon) {
+            (_, Some(_)) => Some("eip1967_beacon".to_string()),
+            (Some(_), None) => Some("eip1967".to_string()),
+            (None, None) => None,
+        });
+    if implementations.is_empty() && proxy_type.is_none() && slots.admin.is_none() {
+        return None;
+    }
+
+    if let Some(current) = slots.implementation {
+        let stale: Vec<&str> = implementations.iter()
+            .filter(|implementation| implementation.sources == ["explorer"])
+            .map(|implementation| implementation.address.as_str())
+            .collect();
+        if !stale.is_empty() {
+            notes.push(format!(
+                "The explorer lists {} as the implementation, but the EIP-1967 slot points to {}",
+                stale.join(", "),
+                current
+            ));
+        }
+    }
+
+    Some(ProxyInfo {
+        proxy_type,
+        implementation: implementations.first().map(|implementation| implementation.address.clone()),
+        implementations,
+        admin: slots.admin.map(|admin| admin.to_string()),
+        beacon: slots.beacon.map(|beacon| beacon.to_string()),
+    })
+}
+
+/// The verified source of `address`, or `None` when the explorer has none or can't be reached.
+async fn verified_source(network: &NetworkConfig, address: &str, include_abi: bool) -> Option<VerifiedSource> {
+    let contract = match explorer_get::<ExplorerSmartContract>(network, &format!("/smart-contracts/{}", address)).await {
+        Ok(contract) => contract,
+        Err(e) => {
+            println!("No verified source for {}: {}", address, e);
+            return None;
+        }
+    };
+    // Unverified contracts come back without source or ABI
+    if contract.source_code.is_none() && contract.abi.is_none() {
+        return None;
+    }
+
+    let sources: Vec<&str> = contract.source_code.as_deref().into_iter()
+        .chain(contract.additional_sources.iter().filter_map(|file| file.source_code.as_deref()))
+        .collect();
+    let entries: Vec<AbiEntry> = contract.abi.as_ref()
+        .and_then(|abi| serde_json::from_value(abi.clone()).ok())
+        .unwrap_or_default();
+    let (read_functions, write_functions, events) = abi_signatures(&entries);
+
+    Some(VerifiedSource {
+        address: address.to_string(),
+        name: contract.name,
+        language: contract.language,
+        compiler_version: contract.compiler_version,
+        evm_version: contract.evm_version,
+        optimization_enabled: contract.optimization_enabled,
+        optimization_runs: contract.optimization_runs,
+        license_type: contract.license_type,
+        verified_at: contract.verified_at,
+        is_fully_verified: contract.is_fully_verified,
+        file_path: contract.file_path,
+        source_files: sources.len(),
+        source_lines: sources.iter().map(|source| source.lines().count()).sum(),
+        read_functions,
+        write_functions,
+        events,
+        abi: contract.abi.filter(|_| include_abi),
+    })
+}
+
+/// Read functions, write functions and events of an ABI, as signatures.
+fn abi_signatures(entries: &[AbiEntry]) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let mut read = Vec::new();
+    let mut write = Vec::new();
+    let mut events = Vec::new();
+    for entry in entries {
+        match entry.entry_type.as_str() {
+            "function" => {
+                let Ok(function) = Function::from_entry(entry) else {
+                    continue;
+                };
+                let readonly = entry.constant || matches!(entry.state_mutability.as_str(), "view" | "pure");
+                if readonly {
+                    let outputs: Vec<String> = function.outputs.iter().map(|param| param.kind.canonical()).collect();
+                    read.push(format!("{}({})", function.signature(), outputs.join(",")));
+                } else if entry.state_mutability == "payable" {
+                    write.push(format!("{} payable", function.signature()));
+                } else {
+                    write.push(function.signature());
+                }
+            }
+            "event" => {
+                if let Ok(event) = Event::from_entry(entry) {
+                    events.push(event.signature());
+                }
+            }
+            _ => {}
+        }
+    }
+    (read, write, events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const IMPLEMENTATION: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const OLD_IMPLEMENTATION: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn word(address: &str) -> Vec<u8> {
+        let mut word = vec![0u8; 12];
+        word.extend_from_slice(address.parse::<Address>().unwrap().as_bytes());
+        word
+    }
+
+    fn explorer(value: serde_json::Value) -> ExplorerContractAddress {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn reads_right_aligned_slot_addresses() {
+        assert_eq!(slot_address(&word(IMPLEMENTATION)).unwrap().to_string(), IMPLEMENTATION);
+        assert_eq!(slot_address(&[0u8; 32]), None);
+        assert_eq!(slot_address(&[1u8; 20]), None);
+    }
+
+    #[test]
+    fn plain_contract_is_not_a_proxy() {
+        let mut notes = Vec::new();
+        let explorer = explorer(json!({"is_contract": true, "name": "Token"}));
+        assert!(proxy_info(Some(&explorer), &ProxySlots::default(), &mut notes).is_none());
+        assert!(proxy_info(None, &ProxySlots::default(), &mut notes).is_none());
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn merges_storage_and_explorer_implementations() {
+        let mut notes = Vec::new();
+        let explorer = explorer(json!({
+            "is_contract": true,
+            "proxy_type": "eip1967",
+            "implementations": [{"address": IMPLEMENTATION.to_lowercase(), "name": "TokenV2"}],
+        }));
+        let slots = ProxySlots { implementation: IMPLEMENTATION.parse().ok(), ..Default::default() };
+
+        let proxy = proxy_info(Some(&explorer), &slots, &mut notes).unwrap();
+        assert_eq!(proxy.proxy_type.as_deref(), Some("eip1967"));
+        assert_eq!(proxy.implementation.as_deref(), Some(IMPLEMENTATION));
+        assert_eq!(proxy.implementations.len(), 1);
+        assert_eq!(proxy.implementations[0].name.as_deref(), Some("TokenV2"));
+        assert_eq!(proxy.implementations[0].sources, ["storage", "explorer"]);
+        assert!(notes.is_empty());
+    }
+
+    #[test]
+    fn storage_wins_over_a_stale_explorer_implementation() {
+        let mut notes = Vec::new();
+        let explorer = explorer(json!({"implementations": [OLD_IMPLEMENTATION]}));
+        let slots = ProxySlots { implementation: IMPLEMENTATION.parse().ok(), ..Default::default() };
+
+        let proxy = proxy_info(Some(&explorer), &slots, &mut notes).unwrap();
+        assert_eq!(proxy.implementation.as_deref(), Some(IMPLEMENTATION));
+        assert_eq!(proxy.implementations.len(), 2);
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].contains(OLD_IMPLEMENTATION));
+    }
+
+    #[test]
+    fn slots_alone_identify_the_proxy_type() {
+        let mut notes = Vec::new();
+        let slots = ProxySlots { implementation: IMPLEMENTATION.parse().ok(), ..Default::default() };
+        assert_eq!(proxy_info(None, &slots, &mut notes).unwrap().proxy_type.as_deref(), Some("eip1967"));
+
+        let slots = ProxySlots { beacon: OLD_IMPLEMENTATION.parse().ok(), ..Default::default() };
+        let proxy = proxy_info(None, &slots, &mut notes).unwrap();
+        assert_eq!(proxy.proxy_type.as_deref(), Some("eip1967_beacon"));
+        assert_eq!(proxy.beacon.as_deref(), Some(OLD_IMPLEMENTATION));
+        assert_eq!(proxy.implementation, None);
+    }
+
+    #[test]
+    fn sorts_abi_entries_into_signatures() {
+        let entries: Vec<AbiEntry> = serde_json::from_value(json!([
+            {"type": "constructor", "inputs": [{"name": "supply", "type": "uint256"}]},
+            {"type": "function", "name": "balanceOf", "stateMutability": "view",
+                "inputs": [{"name": "owner", "type": "address"}], "outputs": [{"type": "uint256"}]},
+            {"type": "function", "name": "decimals", "constant": true, "outputs": [{"type": "uint8"}]},
+            {"type": "function", "name": "transfer", "stateMutability": "nonpayable",
+                "inputs": [{"type": "address"}, {"type": "uint256"}], "outputs": [{"type": "bool"}]},
+            {"type": "function", "name": "deposit", "stateMutability": "payable"},
+            {"type": "event", "name": "Transfer", "inputs": [
+                {"name": "from", "type": "address", "indexed": true},
+                {"name": "to", "type": "address", "indexed": true},
+                {"name": "value", "type": "uint256"}]},
+            {"type": "function", "name": "broken", "inputs": [{"type": "notatype"}]},
+        ]))
+        .unwrap();
+
+        let (read, write, events) = abi_signatures(&entries);
+        assert_eq!(read, ["balanceOf(address)(uint256)", "decimals()(uint8)"]);
+        assert_eq!(write, ["transfer(address,uint256)", "deposit() payable"]);
+        assert_eq!(events, ["Transfer(address,address,uint256)"]);
+    }
+}
@@ -0,0 +1,373 @@
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+
+use uomi_common::{Amount, FormatOptions};
+
+use crate::abi::{hex_bytes, to_hex, Function};
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{explorer_get, format_units, validate_address, BlockchainError};
+use crate::rpc::{parse_u256, parse_u64, FeeHistory, RpcClient};
+
+/// Gas used by a plain native transfer.
+pub(crate) const TRANSFER_GAS: u64 = 21_000;
+
+/// Blocks of fee history sampled by default.
+const DEFAULT_FEE_BLOCKS: u64 = 20;
+const MAX_FEE_BLOCKS: u64 = 1024;
+
+/// Priority fee percentiles behind each speed tier.
+const TIERS: [(&str, f64); 3] = [("slow", 10.0), ("standard", 50.0), ("fast", 90.0)];
+
+/// Current fees and what a transaction using `gas_limit` would cost at each speed tier.
+#[derive(Debug, Serialize)]
+pub struct GasReport {
+    pub block_number: Option<u64>,
+    /// Whether the chain prices gas with an EIP-1559 base fee.
+    pub eip1559: bool,
+    /// `eth_gasPrice`, in wei.
+    pub gas_price: String,
+    pub gas_price_gwei: Option<String>,
+    /// Base fee expected for the next block, in wei.
+    pub base_fee_per_gas: Option<String>,
+    pub base_fee_gwei: Option<String>,
+    pub gas_limit: String,
+    /// USD price of the native coin from the explorer.
+    pub exchange_rate: Option<String>,
+    pub tiers: Vec<FeeTier>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeeTier {
+    /// `slow`, `standard` or `fast`.
+    pub name: &'static str,
+    pub max_priority_fee_per_gas: Option<String>,
+    /// Twice the next base fee plus the priority fee, so the transaction stays valid
+    /// through a few blocks of rising base fees. The gas price on legacy chains.
+    pub max_fee_per_gas: String,
+    /// Base fee plus priority fee: what the transaction is expected to pay per gas.
+    pub expected_fee_per_gas: String,
+    /// `gas_limit` times the expected fee, in wei.
+    pub cost: String,
+    pub cost_formatted: Option<String>,
+    pub cost_usd: Option<String>,
+    /// `gas_limit` times the max fee: the most the transaction can pay.
+    pub max_cost: String,
+    pub max_cost_formatted: Option<String>,
+}
+
+/// A proposed call to estimate, given as raw `data` or as a `signature` with `args`.
+#[derive(Debug, Default, Deserialize)]
+pub struct GasCall {
+    pub to: String,
+    #[serde(default)]
+    pub from: Option<String>,
+    /// Native value sent with the call, as a raw decimal or hex amount.
+    #[serde(default)]
+    pub value: Option<String>,
+    #[serde(default)]
+    pub data: Option<String>,
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub args: Vec<serde_json::Value>,
+    /// Blocks of fee history to sample.
+    #[serde(default)]
+    pub blocks: Option<u64>,
+}
+
+/// Fee market at the latest block, as used to price transactions.
+pub(crate) struct FeeMarket {
+    block_number: Option<u64>,
+    gas_price: U256,
+    /// `None` on chains without EIP-1559.
+    next_base_fee: Option<U256>,
+    /// Priority fee of each tier in [`TIERS`] order.
+    priority_fees: [U256; 3],
+}
+
+impl FeeMarket {
+    /// Max fee and priority fee per gas of the named tier. On legacy chains both are the gas price.
+    pub fn fees_for(&self, tier: &str) -> Option<(U256, U256)> {
+        let index = TIERS.iter().position(|(name, _)| *name == tier)?;
+        let (max_fee, priority_fee) = self.tier(index);
+        Some((max_fee, priority_fee.unwrap_or(max_fee)))
+    }
+
+    fn tier(&self, index: usize) -> (U256, Option<U256>) {
+        match self.next_base_fee {
+            Some(base_fee) => {
+                let priority = self.priority_fees[index];
+                (base_fee.saturating_mul(2.into()).saturating_add(priority), Some(priority))
+            }
+            None => (self.gas_price, None),
+        }
+    }
+
+    fn expected_fee(&self, index: usize) -> U256 {
+        match self.next_base_fee {
+            Some(base_fee) => base_fee.saturating_add(self.priority_fees[index]),
+            None => self.gas_price,
+        }
+    }
+}
+
+/// Blockscout returns the coin's `exchange_rate` only on address and token responses;
+/// `/stats` carries the same market price as `coin_price` without needing an address.
+#[derive(Debug, Deserialize)]
+struct ExplorerStats {
+    coin_price: Option<String>,
+}
+
+/// Current gas prices and the cost of a plain native transfer.
+pub async fn get_gas(network: &NetworkConfig, blocks: Option<u64>) -> Result<GasReport, BlockchainError> {
+    let rpc = RpcClient::new(network)?;
+    let market = fee_market(&rpc, blocks).await?;
+    Ok(report(network, &market, U256::from(TRANSFER_GAS)).await)
+}
+
+/// Gas estimate for a proposed call, priced at the current fees.
+pub async fn estimate_gas(network: &NetworkConfig, call: &GasCall) -> Result<GasReport, BlockchainError> {
+    let rpc = RpcClient::new(network)?;
+    let gas_limit = rpc.estimate_gas(&call_object(network, call)?).await?;
+    let market = fee_market(&rpc, call.blocks).await?;
+    Ok(report(network, &market, gas_limit).await)
+}
+
+/// Read the fee market: `eth_gasPrice`, plus base fees and priority fee percentiles over the
+/// last `blocks` blocks from `eth_feeHistory`. Chains without a base fee, or nodes without
+/// `eth_feeHistory`, are priced with the gas price alone.
+pub(crate) async fn fee_market(rpc: &RpcClient<'_>, blocks: Option<u64>) -> Result<FeeMarket, BlockchainError> {
+    let gas_price = rpc.gas_price().await?;
+    let blocks = blocks.unwrap_or(DEFAULT_FEE_BLOCKS).clamp(1, MAX_FEE_BLOCKS);
+    let percentiles: Vec<f64> = TIERS.iter().map(|(_, percentile)| *percentile).collect();
+
+    let history = match rpc.fee_history(blocks, "latest", &percentiles).await {
+        Ok(history) => history,
+        Err(e @ BlockchainError::Rpc { .. }) => {
+            println!("eth_feeHistory unavailable, using eth_gasPrice only: {}", e);
+            return Ok(FeeMarket { block_number: None, gas_price, next_base_fee: None, priority_fees: [gas_price; 3] });
+        }
+        Err(e) => return Err(e),
+    };
+
+    // One base fee per block in the range, plus the next block's
+    let sampled = history.base_fee_per_gas.len().saturating_sub(1) as u64;
+    let block_number = parse_u64(&history.oldest_block).ok()
+        .map(|oldest| oldest + sampled.saturating_sub(1));
+    let next_base_fee = match history.base_fee_per_gas.last() {
+        Some(base_fee) => Some(parse_u256(base_fee)?).filter(|base_fee| !base_fee.is_zero()),
+        None => None,
+    };
+
+    let priority_fees = match next_base_fee {
+        Some(base_fee) => priority_percentiles(&history, gas_price.saturating_sub(base_fee))?,
+        None => [gas_price; 3],
+    };
+    Ok(FeeMarket { block_number, gas_price, next_base_fee, priority_fees })
+}
+
+/// Median across blocks of each tier's priority fee. Without reward data (an empty chain),
+/// every tier gets what the node suggests over the base fee.
+fn priority_percentiles(history: &FeeHistory, suggested: U256) -> Result<[U256; 3], BlockchainError> {
+    let mut fees = [suggested; 3];
+    for (index, fee) in fees.iter_mut().enumerate() {
+        let mut samples = history.reward.iter()
+            .filter_map(|rewards| rewards.get(index))
+            .map(|reward| parse_u256(reward))
+            .collect::<Result<Vec<_>, _>>()?;
+        if samples.is_empty() {
+            continue;
+        }
+        samples.sort();
+        *fee = samples[samples.len() / 2];
+    }
+    Ok(fees)
+}
+
+async fn report(network: &NetworkConfig, market: &FeeMarket, gas_limit: U256) -> GasReport {
+    let exchange_rate = exchange_rate(network).await;
+    let amount = |raw: U256| {
+        let raw = raw.to_string();
+        (format_units(&raw, network.decimals), raw)
+    };
+
+    let tiers = TIERS.iter().enumerate()
+        .map(|(index, (name, _))| {
+            let (max_fee, priority_fee) = market.tier(index);
+            let (cost_formatted, cost) = amount(gas_limit.saturating_mul(market.expected_fee(index)));
+            let (max_cost_formatted, max_cost) = amount(gas_limit.saturating_mul(max_fee));
+            FeeTier {
+                name,
+                max_priority_fee_per_gas: priority_fee.map(|fee| fee.to_string()),
+                max_fee_per_gas: max_fee.to_string(),
+                expected_fee_per_gas: market.expected_fee(index).to_string(),
+                cost_usd: exchange_rate.as_deref().and_then(|rate| usd_value(&cost, network.decimals, rate)),
+                cost,
+                cost_formatted,
+                max_cost,
+                max_cost_formatted,
+            }
+        })
+        .collect();
+
+    let gas_price = market.gas_price.to_string();
+    let base_fee = market.next_base_fee.map(|fee| fee.to_string());
+    GasReport {
+        block_number: market.block_number,
+        eip1559: market.next_base_fee.is_some(),
+        gas_price_gwei: format_units(&gas_price, 9),
+        gas_price,
+        base_fee_gwei: base_fee.as_deref().and_then(|fee| format_units(fee, 9)),
+        base_fee_per_gas: base_fee,
+        gas_limit: gas_limit.to_string(),
+        exchange_rate,
+        tiers,
+    }
+}
+
+/// The JSON-RPC call object for `eth_estimateGas`.
+fn call_object(network: &NetworkConfig, call: &GasCall) -> Result<serde_json::Value, BlockchainError> {
+    let data = match (&call.data, &call.signature) {
+        (Some(_), Some(_)) => {
+            return Err(BlockchainError::InvalidRequest("pass either data or signature, not both".to_string()))
+        }
+        (Some(data), None) => hex_bytes(data)
+            .ok_or_else(|| BlockchainError::InvalidRequest(format!("invalid calldata {}", data)))?,
+        (None, Some(signature)) => Function::parse(signature)?.encode_call(&call.args)?,
+        (None, None) => Vec::new(),
+    };
+
+    let mut object = serde_json::json!({
+        "to": validate_address(&call.to)?,
+        "data": format!("0x{}", to_hex(&data)),
+    });
+    if let Some(from) = &call.from {
+        object["from"] = serde_json::json!(validate_address(from)?);
+    }
+    if let Some(value) = &call.value {
+        let value = Amount::from_raw(value, network.decimals)
+            .map_err(|e| BlockchainError::InvalidRequest(format!("invalid value {}: {}", value, e)))?;
+        object["value"] = serde_json::json!(format!("0x{:x}", value.raw()));
+    }
+    Ok(object)
+}
+
+/// USD price of the native coin (the explorer's `exchange_rate`, read from `/stats`), or
+/// `None` when the explorer doesn't have one.
+pub(crate) async fn exchange_rate(network: &NetworkConfig) -> Option<String> {
+    match explorer_get::<ExplorerStats>(network, "/stats").await {
+        Ok(stats) => stats.coin_price,
+        Err(e) => {
+            println!("No exchange rate from the explorer: {}", e);
+            None
+        }
+    }
+}
+
+/// USD value of a raw native amount, to a millionth of a dollar.
+pub(crate) fn usd_value(raw: &str, decimals: u8, rate: &str) -> Option<String> {
+    let value = Amount::from_raw(raw, decimals).ok()?.value_at(rate).ok()?;
+    Some(value.format(&FormatOptions::new().precision(6)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    fn network() -> NetworkConfig {
+        serde_json::from_value(json!({"explorer_url": "http://localhost", "chain_id": 1, "decimals": 18})).unwrap()
+    }
+
+    fn history(reward: serde_json::Value) -> FeeHistory {
+        serde_json::from_value(json!({"oldestBlock": "0x10", "baseFeePerGas": ["0x1", "0x1"], "reward": reward})).unwrap()
+    }
+
+    fn gwei(amount: u64) -> U256 {
+        U256::from(amount) * U256::exp10(9)
+    }
+
+    #[test]
+    fn takes_the_median_of_each_percentile() {
+        let history = history(json!([
+            ["0x1", "0x5", "0x9"],
+            ["0x3", "0x6", "0xa"],
+            ["0x2", "0x4", "0xff"],
+        ]));
+        let fees = priority_percentiles(&history, U256::from(100)).unwrap();
+        assert_eq!(fees, [U256::from(2), U256::from(5), U256::from(10)]);
+    }
+
+    #[test]
+    fn empty_blocks_fall_back_to_the_suggested_fee() {
+        let fees = priority_percentiles(&history(json!([])), U256::from(7)).unwrap();
+        assert_eq!(fees, [U256::from(7); 3]);
+        assert!(priority_percentiles(&history(json!([["nope"]])), U256::zero()).is_err());
+    }
+
+    #[test]
+    fn prices_eip1559_tiers_at_twice_the_base_fee() {
+        let market = FeeMarket {
+            block_number: Some(1),
+            gas_price: gwei(12),
+            next_base_fee: Some(gwei(10)),
+            priority_fees: [gwei(1), gwei(2), gwei(3)],
+        };
+        assert_eq!(market.fees_for("slow"), Some((gwei(21), gwei(1))));
+        assert_eq!(market.fees_for("standard"), Some((gwei(22), gwei(2))));
+        assert_eq!(market.fees_for("fast"), Some((gwei(23), gwei(3))));
+        assert_eq!(market.fees_for("ludicrous"), None);
+        assert_eq!(market.expected_fee(2), gwei(13));
+    }
+
+    #[test]
+    fn legacy_chains_use_the_gas_price() {
+        let market = FeeMarket { block_number: None, gas_price: gwei(5), next_base_fee: None, priority_fees: [gwei(5); 3] };
+        assert_eq!(market.fees_for("fast"), Some((gwei(5), gwei(5))));
+        assert_eq!(market.expected_fee(0), gwei(5));
+    }
+
+    #[test]
+    fn builds_call_objects() {
+        let call = GasCall {
+            to: ALICE.to_lowercase(),
+            from: Some(ALICE.to_string()),
+            value: Some("1000".to_string()),
+            signature: Some("transfer(address,uint256)".to_string()),
+            args: vec![json!(ALICE), json!("1")],
+            ..Default::default()
+        };
+        let object = call_object(&network(), &call).unwrap();
+        assert_eq!(object["to"], ALICE);
+        assert_eq!(object["from"], ALICE);
+        assert_eq!(object["value"], "0x3e8");
+        assert!(object["data"].as_str().unwrap().starts_with("0xa9059cbb"));
+
+        let call = GasCall { to: ALICE.to_string(), data: Some("0xdeadbeef".to_string()), ..Default::default() };
+        assert_eq!(call_object(&network(), &call).unwrap()["data"], "0xdeadbeef");
+        let call = GasCall { to: ALICE.to_string(), ..Default::default() };
+        assert_eq!(call_object(&network(), &call).unwrap()["data"], "0x");
+    }
+
+    #[test]
+    fn rejects_ambiguous_or_invalid_calls() {
+        let both = GasCall {
+            to: ALICE.to_string(),
+            data: Some("0x".to_string()),
+            signature: Some("f()".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(call_object(&network(), &both), Err(BlockchainError::InvalidRequest(_))));
+        let bad_data = GasCall { to: ALICE.to_string(), data: Some("0xzz".to_string()), ..Default::default() };
+        assert!(matches!(call_object(&network(), &bad_data), Err(BlockchainError::InvalidRequest(_))));
+        let bad_to = GasCall { to: "0x1234".to_string(), ..Default::default() };
+        assert!(matches!(call_object(&network(), &bad_to), Err(BlockchainError::InvalidAddress(_))));
+    }
+
+    #[test]
+    fn values_costs_in_usd() {
+        assert_eq!(usd_value("21000000000000", 18, "2000").as_deref(), Some("0.042"));
+    }
+}
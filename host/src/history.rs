@@ -0,0 +1,289 @@
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+
+use crate::abi::Function;
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{explorer_get, format_units, validate_address, with_cursor, BlockchainError, ExplorerPage};
+use crate::rpc::{block_tag, parse_u64, RpcClient};
+
+/// A native or ERC-20 balance as of a past block.
+#[derive(Debug, Serialize)]
+pub struct HistoricalBalance {
+    pub address: String,
+    /// The ERC-20 contract, or `None` for the native balance.
+    pub token: Option<String>,
+    pub block_number: u64,
+    pub block_timestamp: String,
+    pub balance: String,
+    pub balance_formatted: Option<String>,
+    pub decimals: u8,
+}
+
+/// A change of an address's native balance.
+#[derive(Debug, Serialize)]
+pub struct BalanceHistoryEntry {
+    pub block_number: u64,
+    pub timestamp: Option<String>,
+    /// Balance after the block, in wei.
+    pub value: String,
+    pub value_formatted: Option<String>,
+    /// Signed change from the previous entry, in wei.
+    pub delta: Option<String>,
+    pub delta_formatted: Option<String>,
+    pub transaction_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceHistoryPage {
+    pub items: Vec<BalanceHistoryEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerCoinBalance {
+    block_number: u64,
+    block_timestamp: Option<String>,
+    value: String,
+    delta: Option<String>,
+    transaction_hash: Option<String>,
+}
+
+/// Balance of `address` at `block`, or at the last block mined at or before `timestamp`.
+/// Needs an RPC node that keeps historical state.
+pub async fn get_historical_balance(
+    network: &NetworkConfig,
+    address: &str,
+    token: Option<&str>,
+    block: Option<&str>,
+    timestamp: Option<&serde_json::Value>,
+) -> Result<HistoricalBalance, BlockchainError> {
+    let address = validate_address(address)?.to_string();
+    let token = token.map(validate_address).transpose()?.map(|token| token.to_string());
+    let rpc = RpcClient::new(network)?;
+
+    let requested = match (block, timestamp) {
+        (Some(_), Some(_)) => {
+            return Err(BlockchainError::InvalidRequest("pass either block or timestamp, not both".to_string()))
+        }
+        (_, Some(timestamp)) => format!("0x{:x}", block_at_timestamp(&rpc, parse_timestamp(timestamp)?).await?),
+        (block, None) => block_tag(block),
+    };
+    let header = rpc.get_block(&requested).await?
+        .ok_or_else(|| BlockchainError::NotFound(format!("block {}", requested)))?;
+    // Pin every read to the resolved number so a moving `latest` can't skew them
+    let block_number = parse_u64(&header.number)?;
+    let pinned = format!("0x{:x}", block_number);
+
+    let (balance, decimals) = match token.as_deref() {
+        None => (rpc.get_balance(&address, &pinned).await?, network.decimals),
+        Some(token) => {
+            let balance = call_u256(&rpc, token, "balanceOf(address)(uint256)", &[serde_json::json!(address)], &pinned).await?;
+            let decimals = call_u256(&rpc, token, "decimals()(uint8)", &[], &pinned).await?;
+            (balance, decimals.low_u32() as u8)
+        }
+    };
+
+    let balance = balance.to_string();
+    Ok(HistoricalBalance {
+        address,
+        token,
+        block_number,
+        block_timestamp: format_timestamp(parse_u64(&header.timestamp)?),
+        balance_formatted: format_units(&balance, decimals),
+        balance,
+        decimals,
+    })
+}
+
+/// One page of the explorer's record of native balance changes, newest first.
+pub async fn get_balance_history(
+    network: &NetworkConfig,
+    address: &str,
+    cursor: Option<&str>,
+) -> Result<BalanceHistoryPage, BlockchainError> {
+    let address = validate_address(address)?;
+
+    let path = with_cursor(format!("/addresses/{}/coin-balance-history", address), cursor);
+    let page: ExplorerPage<ExplorerCoinBalance> = explorer_get(network, &path).await?;
+    Ok(BalanceHistoryPage {
+        next_cursor: page.next_cursor(),
+        items: page.items.into_iter()
+            .map(|entry| BalanceHistoryEntry {
+                block_number: entry.block_number,
+                timestamp: entry.block_timestamp,
+                value_formatted: format_units(&entry.value, network.decimals),
+                value: entry.value,
+                delta_formatted: entry.delta.as_deref().and_then(|delta| format_signed_units(delta, network.decimals)),
+                delta: entry.delta,
+                transaction_hash: entry.transaction_hash,
+            })
+            .collect(),
+    })
+}
+
+/// The last block of `network` mined at or before `timestamp` (unix seconds).
+pub(crate) async fn block_at(network: &NetworkConfig, timestamp: u64) -> Result<u64, BlockchainError> {
+    block_at_timestamp(&RpcClient::new(network)?, timestamp).await
+}
+
+/// The last block mined at or before `timestamp`, by binary search over block headers.
+async fn block_at_timestamp(rpc: &RpcClient<'_>, timestamp: u64) -> Result<u64, BlockchainError> {
+    let latest = rpc.block_number().await?;
+    search_block(latest, timestamp, |number| block_timestamp(rpc, number)).await
+}
+
+/// Binary search blocks `0..=latest` for the last one whose timestamp is at or before `timestamp`.
+async fn search_block<F, Fut>(latest: u64, timestamp: u64, mut timestamp_of: F) -> Result<u64, BlockchainError>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: std::future::Future<Output = Result<u64, BlockchainError>>,
+{
+    if timestamp_of(latest).await? <= timestamp {
+        return Ok(latest);
+    }
+    if timestamp_of(0).await? > timestamp {
+        return Err(BlockchainError::InvalidRequest(format!(
+            "timestamp {} is before the first block",
+            format_timestamp(timestamp)
+        )));
+    }
+
+    // Invariant: block `low` is at or before the timestamp, block `high` after it
+    let (mut low, mut high) = (0, latest);
+    while high - low > 1 {
+        let middle = low + (high - low) / 2;
+        if timestamp_of(middle).await? <= timestamp {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Ok(low)
+}
+
+async fn block_timestamp(rpc: &RpcClient<'_>, number: u64) -> Result<u64, BlockchainError> {
+    let header = rpc.get_block(&format!("0x{:x}", number)).await?
+        .ok_or_else(|| BlockchainError::NotFound(format!("block {}", number)))?;
+    parse_u64(&header.timestamp)
+}
+
+async fn call_u256(
+    rpc: &RpcClient<'_>,
+    contract: &str,
+    signature: &str,
+    args: &[serde_json::Value],
+    block: &str,
+) -> Result<U256, BlockchainError> {
+    let function = Function::parse(signature)?;
+    let output = rpc.eth_call(contract, &function.encode_call(args)?, block).await?;
+    if output.len() < 32 {
+        return Err(BlockchainError::InvalidRequest(format!("{} is not an ERC-20 token", contract)));
+    }
+    Ok(U256::from_big_endian(&output[..32]))
+}
+
+/// Unix seconds, as a number or string, an RFC 3339 date-time, or a `YYYY-MM-DD` date (midnight UTC).
+pub(crate) fn parse_timestamp(value: &serde_json::Value) -> Result<u64, BlockchainError> {
+    let invalid = || BlockchainError::InvalidRequest(format!("invalid timestamp {}", value));
+    let text = match value {
+        serde_json::Value::Number(number) => return number.as_u64().ok_or_else(invalid),
+        serde_json::Value::String(text) => text.trim(),
+        _ => return Err(invalid()),
+    };
+
+    if let Ok(seconds) = text.parse::<u64>() {
+        return Ok(seconds);
+    }
+    let seconds = match DateTime::parse_from_rfc3339(text) {
+        Ok(time) => time.timestamp(),
+        Err(_) => NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .map_err(|_| invalid())?
+            .and_hms_opt(0, 0, 0)
+            .ok_or_else(invalid)?
+            .and_utc()
+            .timestamp(),
+    };
+    u64::try_from(seconds).map_err(|_| invalid())
+}
+
+fn format_timestamp(seconds: u64) -> String {
+    DateTime::<Utc>::from_timestamp(seconds as i64, 0)
+        .map(|time| time.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| seconds.to_string())
+}
+
+fn format_signed_units(raw: &str, decimals: u8) -> Option<String> {
+    match raw.strip_prefix('-') {
+        Some(magnitude) => format_units(magnitude, decimals).map(|amount| format!("-{}", amount)),
+        None => format_units(raw, decimals),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Blocks every 12 seconds from 1000, with blocks 5 and 6 sharing a timestamp.
+    fn timestamp_of(number: u64) -> std::future::Ready<Result<u64, BlockchainError>> {
+        let number = if number > 5 { number - 1 } else { number };
+        std::future::ready(Ok(1000 + number * 12))
+    }
+
+    async fn search(latest: u64, timestamp: u64) -> Result<u64, BlockchainError> {
+        search_block(latest, timestamp, timestamp_of).await
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp(&json!(1_700_000_000)).unwrap(), 1_700_000_000);
+        assert_eq!(parse_timestamp(&json!(" 1700000000 ")).unwrap(), 1_700_000_000);
+        assert_eq!(parse_timestamp(&json!("2023-11-14T22:13:20Z")).unwrap(), 1_700_000_000);
+        assert_eq!(parse_timestamp(&json!("2023-11-15T00:13:20+02:00")).unwrap(), 1_700_000_000);
+        assert_eq!(parse_timestamp(&json!("2024-01-01")).unwrap(), 1_704_067_200);
+        assert_eq!(parse_timestamp(&json!("1970-01-01")).unwrap(), 0);
+    }
+
+    #[test]
+    fn rejects_invalid_timestamps() {
+        for value in [json!(-1), json!(1.5), json!("1969-12-31"), json!("2024-13-01"), json!("yesterday"), json!(null)] {
+            assert!(matches!(parse_timestamp(&value), Err(BlockchainError::InvalidRequest(_))), "{}", value);
+        }
+    }
+
+    #[test]
+    fn formats_timestamps() {
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn formats_signed_units() {
+        assert_eq!(format_signed_units("1500000000000000000", 18).as_deref(), Some("1.5"));
+        assert_eq!(format_signed_units("-1500000000000000000", 18).as_deref(), Some("-1.5"));
+        assert_eq!(format_signed_units("-1", 6).as_deref(), Some("-0.000001"));
+        assert_eq!(format_signed_units("0", 18).as_deref(), Some("0"));
+        assert_eq!(format_signed_units("-abc", 18), None);
+        assert_eq!(format_signed_units("--1", 18), None);
+    }
+
+    #[tokio::test]
+    async fn finds_the_block_at_a_timestamp() {
+        // Exactly on a block, and between two blocks
+        assert_eq!(search(100, 1000 + 3 * 12).await.unwrap(), 3);
+        assert_eq!(search(100, 1000 + 3 * 12 + 11).await.unwrap(), 3);
+        // Blocks sharing a timestamp resolve to the later one
+        assert_eq!(search(100, 1000 + 5 * 12).await.unwrap(), 6);
+    }
+
+    #[tokio::test]
+    async fn block_search_boundaries() {
+        assert_eq!(search(100, 1000).await.unwrap(), 0);
+        assert_eq!(search(100, 1000 + 99 * 12).await.unwrap(), 100);
+        assert_eq!(search(100, u64::MAX).await.unwrap(), 100);
+        assert_eq!(search(0, 1000).await.unwrap(), 0);
+        assert_eq!(search(1, 1000 + 11).await.unwrap(), 0);
+        assert!(matches!(search(100, 999).await, Err(BlockchainError::InvalidRequest(_))));
+    }
+}
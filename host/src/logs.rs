@@ -0,0 +1,454 @@
+use serde::{Deserialize, Serialize};
+use uomi_common::Address;
+
+use crate::abi::{hex_bytes, to_hex, Event};
+use crate::api_service::{ChainSource, NetworkConfig};
+use crate::blockchain_service::{checksummed, explorer_get, validate_address, with_cursor, BlockchainError, ExplorerAddressRef, ExplorerPage};
+use crate::rpc::{parse_u64, Log, RpcClient};
+
+/// Stop scanning once this many logs were found; the caller continues from `next_cursor`.
+const DEFAULT_LOG_LIMIT: usize = 1000;
+/// `eth_getLogs` calls per request, so an open-ended range can't fan out into thousands of calls.
+pub(crate) const MAX_CHUNKS_PER_CALL: u64 = 20;
+
+/// One `topics` position: a single value, or any of several.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum TopicFilter {
+    One(String),
+    AnyOf(Vec<String>),
+}
+
+/// Parameters of the `get_logs` action.
+#[derive(Debug, Default, Deserialize)]
+pub struct LogQuery {
+    #[serde(default)]
+    pub address: Option<String>,
+    /// An event signature such as `Transfer(address indexed from, address indexed to, uint256 value)`,
+    /// or the name of a known event. Fills `topics[0]` when it is unset, and decodes matching logs.
+    #[serde(default)]
+    pub event: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<Option<TopicFilter>>,
+    #[serde(default)]
+    pub from_block: Option<String>,
+    #[serde(default)]
+    pub to_block: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogEntry {
+    pub address: String,
+    pub block_number: Option<u64>,
+    pub transaction_hash: Option<String>,
+    pub log_index: Option<u64>,
+    pub topics: Vec<String>,
+    pub data: String,
+    /// Canonical signature of the decoded event, e.g. `Transfer(address,address,uint256)`.
+    pub event: Option<String>,
+    /// Decoded arguments as `{name, type, indexed, value}`.
+    pub decoded: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogPage {
+    pub items: Vec<LogEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerLog {
+    address: ExplorerAddressRef,
+    block_number: Option<u64>,
+    transaction_hash: Option<String>,
+    index: Option<u64>,
+    #[serde(default)]
+    topics: Vec<Option<String>>,
+    data: String,
+    decoded: Option<ExplorerDecodedLog>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerDecodedLog {
+    method_call: String,
+    parameters: Vec<ExplorerLogParam>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerLogParam {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    indexed: bool,
+    value: serde_json::Value,
+}
+
+/// A validated query: normalized topics and the events used to decode results.
+struct LogFilter {
+    address: Option<Address>,
+    topics: Vec<Option<Vec<String>>>,
+    decoders: Vec<Event>,
+}
+
+/// Logs matching `query`, from `eth_getLogs` on RPC networks or the explorer's
+/// address logs on explorer networks.
+pub async fn get_logs(network: &NetworkConfig, query: &LogQuery) -> Result<LogPage, BlockchainError> {
+    let filter = LogFilter::new(query)?;
+    match network.source {
+        ChainSource::Rpc => get_logs_rpc(network, query, &filter).await,
+        ChainSource::Explorer => get_logs_explorer(network, query, &filter).await,
+    }
+}
+
+/// Logs matching `query` from `eth_getLogs`, whatever the network's source.
+pub async fn get_rpc_logs(network: &NetworkConfig, query: &LogQuery) -> Result<LogPage, BlockchainError> {
+    get_logs_rpc(network, query, &LogFilter::new(query)?).await
+}
+
+impl LogFilter {
+    fn new(query: &LogQuery) -> Result<Self, BlockchainError> {
+        let address = query.address.as_deref().map(validate_address).transpose()?;
+        let mut topics = query.topics.iter()
+            .map(|topic| {
+                topic.as_ref()
+                    .map(|topic| match topic {
+                        TopicFilter::One(topic) => Ok(vec![normalize_topic(topic)?]),
+                        TopicFilter::AnyOf(topics) => topics.iter().map(|topic| normalize_topic(topic)).collect(),
+                    })
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>, BlockchainError>>()?;
+
+        let mut decoders = Vec::new();
+        if let Some(event) = query.event.as_deref() {
+            let events = if event.contains('(') {
+                vec![Event::parse(event)?]
+            } else {
+                Event::known(Some(event))
+            };
+            if events.is_empty() {
+                return Err(BlockchainError::InvalidRequest(format!("unknown event {}", event)));
+            }
+
+            let mut event_topics: Vec<String> = events.iter().map(|e| format!("0x{}", to_hex(&e.topic()))).collect();
+            event_topics.dedup();
+            if topics.is_empty() {
+                topics.push(None);
+            }
+            topics[0].get_or_insert(event_topics);
+            decoders.extend(events);
+        }
+        decoders.extend(Event::known(None));
+
+        Ok(LogFilter { address, topics, decoders })
+    }
+
+    fn matches(&self, log: &LogEntry) -> bool {
+        self.address.is_none_or(|address| log.address == address.to_string())
+            && self.topics.iter().enumerate().all(|(i, options)| match options {
+                None => true,
+                Some(options) => log.topics.get(i).is_some_and(|topic| options.contains(topic)),
+            })
+    }
+
+    /// Decode with the first event whose topic and indexed layout fit the log.
+    fn decode(&self, topics: &[String], data: &str) -> Option<(String, Vec<serde_json::Value>)> {
+        let topics: Vec<Vec<u8>> = topics.iter().map(|topic| hex_bytes(topic)).collect::<Option<_>>()?;
+        let data = hex_bytes(data)?;
+        self.decoders.iter().find_map(|event| {
+            event.decode_log(&topics, &data).ok().map(|values| (event.signature(), values))
+        })
+    }
+}
+
+/// Scan the range in chunks of `log_chunk_size` blocks, halving the chunk when the
+/// node rejects a range as too large.
+async fn get_logs_rpc(network: &NetworkConfig, query: &LogQuery, filter: &LogFilter) -> Result<LogPage, BlockchainError> {
+    let rpc = RpcClient::new(network)?;
+    let latest = rpc.block_number().await?;
+    let to = match query.to_block.as_deref() {
+        Some(block) => resolve_block(block, latest)?.min(latest),
+        None => latest,
+    };
+    let (from, resume_after) = match (query.cursor.as_deref(), query.from_block.as_deref()) {
+        (Some(cursor), _) => parse_cursor(cursor)?,
+        (None, Some(block)) => (resolve_block(block, latest)?, None),
+        (None, None) => (to.saturating_sub(network.log_chunk_size.max(1) - 1), None),
+    };
+    if from > to {
+        return Err(BlockchainError::InvalidRequest(format!("from_block {} is after to_block {}", from, to)));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LOG_LIMIT);
+    let mut chunk_size = network.log_chunk_size.max(1);
+    let mut start = from;
+    let mut chunks = 0;
+    let mut items = Vec::new();
+
+    while start <= to && items.len() < limit && chunks < MAX_CHUNKS_PER_CALL {
+        let end = start.saturating_add(chunk_size - 1).min(to);
+        let mut params = serde_json::json!({
+            "fromBlock": format!("0x{:x}", start),
+            "toBlock": format!("0x{:x}", end),
+        });
+        if let Some(address) = filter.address {
+            params["address"] = serde_json::json!(address.to_string());
+        }
+        if !filter.topics.is_empty() {
+            params["topics"] = serde_json::json!(filter.topics);
+        }
+
+        match rpc.get_logs(&params).await {
+            Ok(logs) => {
+                items.extend(
+                    logs.into_iter()
+                        .map(|log| LogEntry::from_rpc(log, filter))
+                        .filter(|log| !is_before_cursor(log, from, resume_after)),
+                );
+                start = end + 1;
+                chunks += 1;
+            }
+            Err(e) if is_range_error(&e) && end > start => {
+                chunk_size = halve_chunk(start, end);
+                println!("eth_getLogs range too large, retrying with {} blocks", chunk_size);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut next_cursor = (start <= to).then(|| start.to_string());
+    // The last chunk may overshoot `limit`; the rest of it is picked up from the last log returned
+    if items.len() > limit {
+        items.truncate(limit);
+        if let Some(last) = items.last() {
+            next_cursor = match (last.block_number, last.log_index) {
+                (Some(block), Some(index)) => Some(format!("{}:{}", block, index)),
+                _ => next_cursor,
+            };
+        }
+    }
+    Ok(LogPage { items, next_cursor })
+}
+
+/// A cursor is the block to resume from, or `block:log_index` of the last log returned
+/// when a page ended partway through a block.
+fn parse_cursor(cursor: &str) -> Result<(u64, Option<u64>), BlockchainError> {
+    let invalid = || BlockchainError::InvalidRequest(format!("invalid cursor {}", cursor));
+    match cursor.split_once(':') {
+        Some((block, index)) => Ok((block.parse().map_err(|_| invalid())?, Some(index.parse().map_err(|_| invalid())?))),
+        None => Ok((cursor.parse().map_err(|_| invalid())?, None)),
+    }
+}
+
+/// Whether `log` was already returned by the page that ended at `block:resume_after`.
+fn is_before_cursor(log: &LogEntry, block: u64, resume_after: Option<u64>) -> bool {
+    match (resume_after, log.block_number, log.log_index) {
+        (Some(after), Some(log_block), Some(index)) => log_block == block && index <= after,
+        _ => false,
+    }
+}
+
+/// Chunk size to retry with after the node rejected `start..=end`.
+fn halve_chunk(start: u64, end: u64) -> u64 {
+    (end - start).div_ceil(2).max(1)
+}
+
+/// One page of the explorer's logs for `address`, filtered by topics and block range.
+async fn get_logs_explorer(network: &NetworkConfig, query: &LogQuery, filter: &LogFilter) -> Result<LogPage, BlockchainError> {
+    let address = filter.address.ok_or_else(|| {
+        BlockchainError::InvalidRequest("get_logs needs an address on explorer networks".to_string())
+    })?;
+    let from = query.from_block.as_deref().map(|block| resolve_block(block, u64::MAX)).transpose()?;
+    let to = query.to_block.as_deref().map(|block| resolve_block(block, u64::MAX)).transpose()?;
+
+    let path = with_cursor(format!("/addresses/{}/logs", address), query.cursor.as_deref());
+    let page: ExplorerPage<ExplorerLog> = explorer_get(network, &path).await?;
+    let mut next_cursor = page.next_cursor();
+
+    // Logs come newest first, so once a page reaches below `from_block` there is nothing left
+    let oldest = page.items.iter().filter_map(|log| log.block_number).min();
+    if from.zip(oldest).is_some_and(|(from, oldest)| oldest < from) {
+        next_cursor = None;
+    }
+
+    let items = page.items.into_iter()
+        .map(|log| LogEntry::from_explorer(log, filter))
+        .filter(|log| {
+            let block = log.block_number.unwrap_or_default();
+            filter.matches(log) && from.is_none_or(|from| block >= from) && to.is_none_or(|to| block <= to)
+        })
+        .collect();
+    Ok(LogPage { items, next_cursor })
+}
+
+impl LogEntry {
+    fn from_rpc(log: Log, filter: &LogFilter) -> Self {
+        let decoded = filter.decode(&log.topics, &log.data);
+        LogEntry {
+            address: checksummed(log.address),
+            block_number: log.block_number.as_deref().and_then(|n| parse_u64(n).ok()),
+            transaction_hash: log.transaction_hash,
+            log_index: log.log_index.as_deref().and_then(|n| parse_u64(n).ok()),
+            topics: log.topics,
+            data: log.data,
+            event: decoded.as_ref().map(|(event, _)| event.clone()),
+            decoded: decoded.map(|(_, values)| values),
+        }
+    }
+
+    fn from_explorer(log: ExplorerLog, filter: &LogFilter) -> Self {
+        let topics: Vec<String> = log.topics.into_iter().flatten().map(|topic| topic.to_lowercase()).collect();
+        // Prefer our own decoding, then the explorer's for verified contracts
+        let decoded = filter.decode(&topics, &log.data).or_else(|| {
+            log.decoded.map(|decoded| {
+                let event = Event::parse(&decoded.method_call)
+                    .map(|event| event.signature())
+                    .unwrap_or(decoded.method_call);
+                let values = decoded.parameters.into_iter()
+                    .map(|param| serde_json::json!({
+                        "name": param.name,
+                        "type": param.kind,
+                        "indexed": param.indexed,
+                        "value": param.value,
+                    }))
+                    .collect();
+                (event, values)
+            })
+        });
+        LogEntry {
+            address: checksummed(log.address.hash),
+            block_number: log.block_number,
+            transaction_hash: log.transaction_hash,
+            log_index: log.index,
+            topics,
+            data: log.data,
+            event: decoded.as_ref().map(|(event, _)| event.clone()),
+            decoded: decoded.map(|(_, values)| values),
+        }
+    }
+}
+
+/// A 32-byte topic in lowercase hex. Addresses are accepted and left-padded.
+fn normalize_topic(topic: &str) -> Result<String, BlockchainError> {
+    let bytes = hex_bytes(topic)
+        .filter(|bytes| bytes.len() == 32 || bytes.len() == 20)
+        .ok_or_else(|| BlockchainError::InvalidRequest(format!("invalid topic {}", topic)))?;
+    Ok(format!("0x{}{}", "00".repeat(32 - bytes.len()), to_hex(&bytes)))
+}
+
+/// A block number, `0x` quantity or tag; tags other than `earliest` mean `latest`.
+pub(crate) fn resolve_block(block: &str, latest: u64) -> Result<u64, BlockchainError> {
+    let invalid = || BlockchainError::InvalidRequest(format!("invalid block {}", block));
+    match block {
+        "earliest" => Ok(0),
+        "latest" | "pending" | "safe" | "finalized" => Ok(latest),
+        _ => match block.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16).map_err(|_| invalid()),
+            None => block.parse().map_err(|_| invalid()),
+        },
+    }
+}
+
+/// Nodes report oversized queries with different codes and wording. Rate limits share
+/// some of that wording (and `-32005` on some nodes) but must not shrink the range.
+fn is_range_error(error: &BlockchainError) -> bool {
+    match error {
+        BlockchainError::Rpc { code, message } => {
+            let message = message.to_lowercase();
+            let rate_limited = ["rate limit", "too many requests", "request rate", "rate exceeded", "request count"]
+                .iter()
+                .any(|hint| message.contains(hint));
+            let too_large = ["range", "more than", "results", "too many logs", "response size"]
+                .iter()
+                .any(|hint| message.contains(hint));
+            !rate_limited && (too_large || *code == -32005)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(code: i64, message: &str) -> BlockchainError {
+        BlockchainError::Rpc { code, message: message.to_string() }
+    }
+
+    fn log(block: u64, index: u64) -> LogEntry {
+        LogEntry {
+            address: String::new(),
+            block_number: Some(block),
+            transaction_hash: None,
+            log_index: Some(index),
+            topics: Vec::new(),
+            data: "0x".to_string(),
+            event: None,
+            decoded: None,
+        }
+    }
+
+    #[test]
+    fn resolves_numbers_quantities_and_tags() {
+        assert_eq!(resolve_block("1000", 5).unwrap(), 1000);
+        assert_eq!(resolve_block("0x3e8", 5).unwrap(), 1000);
+        assert_eq!(resolve_block("earliest", 5).unwrap(), 0);
+        for tag in ["latest", "pending", "safe", "finalized"] {
+            assert_eq!(resolve_block(tag, 5).unwrap(), 5);
+        }
+        assert!(resolve_block("0xzz", 5).is_err());
+        assert!(resolve_block("yesterday", 5).is_err());
+        assert!(resolve_block("-1", 5).is_err());
+    }
+
+    #[test]
+    fn normalizes_topics_to_32_bytes() {
+        let topic = "0xDDF252AD1BE2C89B69C2B068FC378DAA952BA7F163C4A11628F55A4DF523B3EF";
+        assert_eq!(normalize_topic(topic).unwrap(), topic.to_lowercase().replace("0X", "0x"));
+        assert_eq!(
+            normalize_topic("0xad749097119d27495987dA69F9D8E19366E2f287").unwrap(),
+            "0x000000000000000000000000ad749097119d27495987da69f9d8e19366e2f287"
+        );
+        assert!(normalize_topic("0x1234").is_err());
+        assert!(normalize_topic("not hex").is_err());
+    }
+
+    #[test]
+    fn halves_rejected_chunks_down_to_one_block() {
+        assert_eq!(halve_chunk(0, 9999), 5000);
+        assert_eq!(halve_chunk(100, 109), 5);
+        assert_eq!(halve_chunk(100, 101), 1);
+    }
+
+    #[test]
+    fn only_range_errors_shrink_the_chunk() {
+        assert!(is_range_error(&rpc_error(-32005, "query returned more than 10000 results")));
+        assert!(is_range_error(&rpc_error(-32000, "block range too large")));
+        assert!(is_range_error(&rpc_error(-32602, "exceed maximum block range: 5000")));
+        assert!(is_range_error(&rpc_error(-32000, "Log response size exceeded.")));
+        assert!(!is_range_error(&rpc_error(-32005, "daily request count exceeded, request rate limited")));
+        assert!(!is_range_error(&rpc_error(-32000, "rate limit exceeded")));
+        assert!(!is_range_error(&rpc_error(429, "Too Many Requests")));
+        assert!(!is_range_error(&rpc_error(-32000, "execution reverted")));
+        assert!(!is_range_error(&BlockchainError::NotFound("logs".to_string())));
+    }
+
+    #[test]
+    fn cursors_resume_after_the_last_log() {
+        assert_eq!(parse_cursor("1200").unwrap(), (1200, None));
+        assert_eq!(parse_cursor("1200:7").unwrap(), (1200, Some(7)));
+        assert!(parse_cursor("1200:").is_err());
+        assert!(parse_cursor("abc").is_err());
+
+        assert!(is_before_cursor(&log(1200, 7), 1200, Some(7)));
+        assert!(is_before_cursor(&log(1200, 3), 1200, Some(7)));
+        assert!(!is_before_cursor(&log(1200, 8), 1200, Some(7)));
+        assert!(!is_before_cursor(&log(1201, 0), 1200, Some(7)));
+        assert!(!is_before_cursor(&log(1200, 0), 1200, None));
+    }
+}
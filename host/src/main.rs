@@ -5,8 +5,52 @@ mod wasm_runtime;
 mod api_service;
 mod utils;
 mod blockchain_service;
+mod rpc;
+mod abi;
+mod logs;
+mod transaction;
+mod history;
+mod approvals;
+mod batch;
+mod cache;
+mod gas;
+mod nft;
+mod contract;
+mod prepare;
+mod audit;
+mod circuit_breaker;
+mod rate_limiter;
+mod quota;
+mod signer;
+mod usage;
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("usage") {
+        let group_by = args.get(2).map(String::as_str).unwrap_or("model");
+        let group_by = usage::GroupBy::parse(group_by)
+            .ok_or_else(|| anyhow::anyhow!("Unknown grouping '{}', expected model, agent or day", group_by))?;
+        let config = api_service::get_config()?;
+        usage::print_ledger_summary(&config.usage, group_by)?;
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("breakers") {
+        circuit_breaker::print_diagnostics();
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("signer") {
+        let config = api_service::get_config()?;
+        match args.get(2).map(String::as_str) {
+            Some("import") => {
+                let address = signer::import_key(&config.blockchain.signer)?;
+                println!("Imported {} into {}", address, config.blockchain.signer.keystore_path);
+            }
+            None | Some("status") => signer::print_status(&config.blockchain)?,
+            Some(other) => anyhow::bail!("Unknown signer command '{}', expected status or import", other),
+        }
+        return Ok(());
+    }
+
     let input_path = Path::new("./src/input.txt");
     let output_path = Path::new("./src/output.txt");
     
@@ -0,0 +1,203 @@
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+
+use crate::abi::Function;
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{explorer_get, validate_address, with_cursor, BlockchainError, ExplorerPage, ExplorerToken};
+use crate::rpc::RpcClient;
+
+const ERC721: &str = "ERC-721";
+const ERC1155: &str = "ERC-1155";
+
+/// NFTs held by an address, grouped by collection.
+#[derive(Debug, Serialize)]
+pub struct NftPage {
+    pub collections: Vec<NftCollection>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NftCollection {
+    pub contract_address: String,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    /// `ERC-721` or `ERC-1155`.
+    pub token_type: String,
+    /// Number of tokens held in the collection.
+    pub amount: Option<String>,
+    pub tokens: Vec<Nft>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Nft {
+    pub token_id: String,
+    /// Copies held, for ERC-1155 tokens.
+    pub amount: Option<String>,
+    pub name: Option<String>,
+    /// `tokenURI` / `uri` of the token, with `ipfs://` resolved through the gateway.
+    pub metadata_uri: Option<String>,
+    pub image_url: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerNftCollection {
+    token: ExplorerToken,
+    amount: Option<String>,
+    #[serde(default)]
+    token_instances: Vec<ExplorerNftInstance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerNftInstance {
+    id: String,
+    value: Option<String>,
+    image_url: Option<String>,
+    metadata: Option<serde_json::Value>,
+}
+
+/// One page of an address's ERC-721 and ERC-1155 holdings from the explorer. When the
+/// network has an `rpc_url`, each token's metadata URI is read from its contract.
+pub async fn get_nfts(
+    network: &NetworkConfig,
+    ipfs_gateway: &str,
+    address: &str,
+    token_type: Option<&str>,
+    cursor: Option<&str>,
+) -> Result<NftPage, BlockchainError> {
+    let address = validate_address(address)?;
+    let token_type = match token_type {
+        None => format!("{},{}", ERC721, ERC1155),
+        Some(kind @ (ERC721 | ERC1155)) => kind.to_string(),
+        Some(kind) => return Err(BlockchainError::InvalidRequest(format!("unsupported NFT type {}", kind))),
+    };
+
+    let path = with_cursor(format!("/addresses/{}/nft/collections?type={}", address, token_type), cursor);
+    let page: ExplorerPage<ExplorerNftCollection> = explorer_get(network, &path).await?;
+    let rpc = RpcClient::new(network).ok();
+
+    let mut collections = Vec::with_capacity(page.items.len());
+    let next_cursor = page.next_cursor();
+    for collection in page.items {
+        let token = collection.token;
+        let uris = join_all(collection.token_instances.iter().map(|instance| async {
+            match &rpc {
+                Some(rpc) => metadata_uri(rpc, &token.address, &token.token_type, &instance.id).await,
+                None => None,
+            }
+        }))
+        .await;
+
+        let tokens = collection.token_instances.into_iter().zip(uris)
+            .map(|(instance, uri)| Nft {
+                name: instance.metadata.as_ref()
+                    .and_then(|metadata| metadata.get("name"))
+                    .and_then(|name| name.as_str())
+                    .map(|name| name.to_string()),
+                metadata_uri: uri.map(|uri| resolve_ipfs(&uri, ipfs_gateway)),
+                image_url: instance.image_url
+                    .or_else(|| {
+                        let metadata = instance.metadata.as_ref()?;
+                        Some(metadata.get("image")?.as_str()?.to_string())
+                    })
+                    .map(|url| resolve_ipfs(&url, ipfs_gateway)),
+                amount: instance.value.filter(|_| token.token_type == ERC1155),
+                token_id: instance.id,
+                metadata: instance.metadata,
+            })
+            .collect();
+
+        collections.push(NftCollection {
+            contract_address: token.address,
+            name: token.name,
+            symbol: token.symbol,
+            token_type: token.token_type,
+            amount: collection.amount,
+            tokens,
+        });
+    }
+
+    Ok(NftPage { collections, next_cursor })
+}
+
+/// `tokenURI(id)` for ERC-721, `uri(id)` for ERC-1155 with the `{id}` placeholder filled in.
+async fn metadata_uri(rpc: &RpcClient<'_>, contract: &str, token_type: &str, token_id: &str) -> Option<String> {
+    let signature = if token_type == ERC1155 { "uri(uint256)(string)" } else { "tokenURI(uint256)(string)" };
+    let function = Function::parse(signature).ok()?;
+    let data = function.encode_call(&[serde_json::json!(token_id)]).ok()?;
+    let output = match rpc.eth_call(contract, &data, "latest").await {
+        Ok(output) => output,
+        Err(e) => {
+            println!("No metadata URI for {} #{}: {}", contract, token_id, e);
+            return None;
+        }
+    };
+    let uri = function.decode_output(&output).ok()?
+        .first()?
+        .get("value")?
+        .as_str()?
+        .to_string();
+
+    substitute_id(&uri, token_id)
+}
+
+/// Fill the EIP-1155 `{id}` placeholder with the decimal `token_id` as lowercase hex, zero-padded to 64 characters.
+fn substitute_id(uri: &str, token_id: &str) -> Option<String> {
+    if !uri.contains("{id}") {
+        return Some(uri.to_string());
+    }
+    let id = primitive_types::U256::from_dec_str(token_id).ok()?;
+    Some(uri.replace("{id}", &format!("{:064x}", id)))
+}
+
+/// Rewrite `ipfs://CID/path` (and `ipfs://ipfs/CID/path`) to a URL on the configured gateway.
+fn resolve_ipfs(uri: &str, gateway: &str) -> String {
+    match uri.strip_prefix("ipfs://") {
+        Some(path) => format!("{}/{}", gateway.trim_end_matches('/'), path.trim_start_matches("ipfs/")),
+        None => uri.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GATEWAY: &str = "https://ipfs.io/ipfs";
+    const CID: &str = "QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG";
+
+    #[test]
+    fn resolves_ipfs_uris() {
+        let expected = format!("{}/{}/1.json", GATEWAY, CID);
+        assert_eq!(resolve_ipfs(&format!("ipfs://{}/1.json", CID), GATEWAY), expected);
+        assert_eq!(resolve_ipfs(&format!("ipfs://ipfs/{}/1.json", CID), GATEWAY), expected);
+        assert_eq!(resolve_ipfs(&format!("ipfs://{}/1.json", CID), "https://ipfs.io/ipfs/"), expected);
+        assert_eq!(resolve_ipfs(&format!("ipfs://{}", CID), GATEWAY), format!("{}/{}", GATEWAY, CID));
+    }
+
+    #[test]
+    fn leaves_other_uris_alone() {
+        for uri in ["https://example.com/1.json", "data:application/json;base64,e30=", "ar://abc", ""] {
+            assert_eq!(resolve_ipfs(uri, GATEWAY), uri);
+        }
+    }
+
+    #[test]
+    fn substitutes_padded_hex_ids() {
+        let uri = "https://example.com/{id}.json";
+        assert_eq!(
+            substitute_id(uri, "1").unwrap(),
+            "https://example.com/0000000000000000000000000000000000000000000000000000000000000001.json"
+        );
+        let filled = substitute_id(uri, "314592").unwrap();
+        assert_eq!(filled, format!("https://example.com/{}4cce0.json", "0".repeat(59)));
+        let max = primitive_types::U256::MAX.to_string();
+        assert_eq!(substitute_id(uri, &max).unwrap(), format!("https://example.com/{}.json", "f".repeat(64)));
+        assert_eq!(substitute_id("ipfs://cid/{id}/{id}", "10").unwrap(), format!("ipfs://cid/{0:0>64}/{0:0>64}", "a"));
+    }
+
+    #[test]
+    fn substitution_needs_a_placeholder_and_a_decimal_id() {
+        assert_eq!(substitute_id("https://example.com/1.json", "not a number").unwrap(), "https://example.com/1.json");
+        assert_eq!(substitute_id("https://example.com/{id}.json", "0x1"), None);
+    }
+}
@@ -0,0 +1,440 @@
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+
+use uomi_common::{Address, Amount};
+
+use crate::abi::{to_hex, Function};
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{format_units, token_decimals, validate_address, BlockchainError};
+use crate::gas::{self, TRANSFER_GAS};
+use crate::rpc::RpcClient;
+
+/// Gas limit assumed for an ERC-20 transfer that can't be estimated.
+const TOKEN_TRANSFER_GAS: u64 = 100_000;
+
+/// A transfer of the native coin or an ERC-20 token, to be signed by the sender.
+#[derive(Debug, Default, Deserialize)]
+pub struct TransferRequest {
+    /// Sender, needed for the nonce and an exact gas estimate.
+    #[serde(default)]
+    pub from: Option<String>,
+    pub to: String,
+    /// Amount in whole units, e.g. `5` or `0.25`.
+    pub amount: String,
+    /// ERC-20 contract; the native coin when unset.
+    #[serde(default)]
+    pub token: Option<String>,
+    /// Fee tier: `slow`, `standard` (default) or `fast`.
+    #[serde(default)]
+    pub speed: Option<String>,
+}
+
+/// An unsigned EIP-1559 (type 2) transaction.
+#[derive(Debug, Clone)]
+pub struct Eip1559Transaction {
+    pub chain_id: u64,
+    /// `None` when the sender is unknown; the wallet fills it in.
+    pub nonce: Option<u64>,
+    pub max_priority_fee_per_gas: U256,
+    pub max_fee_per_gas: U256,
+    pub gas_limit: U256,
+    pub to: Address,
+    pub value: U256,
+    pub data: Vec<u8>,
+}
+
+impl Eip1559Transaction {
+    /// The transaction as `eth_sendTransaction` parameters, with hex quantities.
+    pub fn to_json(&self, from: Option<&Address>) -> serde_json::Value {
+        let mut json = serde_json::json!({
+            "type": "0x2",
+            "chainId": format!("0x{:x}", self.chain_id),
+            "to": self.to,
+            "value": format!("0x{:x}", self.value),
+            "data": format!("0x{}", to_hex(&self.data)),
+            "gas": format!("0x{:x}", self.gas_limit),
+            "maxFeePerGas": format!("0x{:x}", self.max_fee_per_gas),
+            "maxPriorityFeePerGas": format!("0x{:x}", self.max_priority_fee_per_gas),
+        });
+        if let Some(from) = from {
+            json["from"] = serde_json::json!(from);
+        }
+        if let Some(nonce) = self.nonce {
+            json["nonce"] = serde_json::json!(format!("0x{:x}", nonce));
+        }
+        json
+    }
+
+    /// The most the transaction can pay in fees.
+    pub fn max_fee_cost(&self) -> U256 {
+        self.gas_limit.saturating_mul(self.max_fee_per_gas)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreparedTransaction {
+    pub chain_id: u64,
+    pub from: Option<String>,
+    pub recipient: String,
+    /// ERC-20 contract, or `None` for the native coin.
+    pub token: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: u8,
+    pub amount: String,
+    pub amount_raw: String,
+    pub speed: String,
+    pub nonce: Option<u64>,
+    pub gas_limit: String,
+    pub max_fee_per_gas: String,
+    pub max_priority_fee_per_gas: String,
+    /// Gas limit times the max fee, in wei.
+    pub max_fee_cost: String,
+    pub max_fee_cost_formatted: Option<String>,
+    pub max_fee_cost_usd: Option<String>,
+    /// `eth_sendTransaction` parameters for the user's wallet.
+    pub transaction: serde_json::Value,
+    /// EIP-681 payment request for wallets that scan or open links.
+    pub payment_uri: String,
+    /// Problems the user should know about before signing, e.g. an insufficient balance.
+    pub warnings: Vec<String>,
+}
+
+/// Build an unsigned EIP-1559 transfer for the user to sign in their own wallet.
+/// Nothing is signed or sent.
+pub async fn prepare_transaction(
+    network: &NetworkConfig,
+    request: &TransferRequest,
+) -> Result<PreparedTransaction, BlockchainError> {
+    let rpc = RpcClient::new(network)?;
+    let from = request.from.as_deref().map(validate_address).transpose()?;
+    let mut warnings = Vec::new();
+    let transfer = build_transfer(network, &rpc, from.as_ref(), request, &mut warnings).await?;
+    let tx = &transfer.transaction;
+
+    if let Some(from) = &from {
+        let balance = rpc.get_balance(&from.to_string(), "latest").await?;
+        if balance < tx.value.saturating_add(tx.max_fee_cost()) {
+            warnings.push(format!(
+                "{} holds {} {}, less than the amount plus the maximum fee",
+                from,
+                format_units(&balance.to_string(), network.decimals).unwrap_or_else(|| balance.to_string()),
+                network.native_symbol
+            ));
+        }
+        if let Some(token) = &transfer.token {
+            let balance = token_call(&rpc, token, "balanceOf(address)(uint256)", &[serde_json::json!(from)]).await?;
+            if balance < transfer.amount.raw() {
+                warnings.push(format!("{} holds less than {} of the token", from, transfer.amount));
+            }
+        }
+    }
+
+    let payment_uri = payment_uri(&transfer);
+    let max_fee_cost = tx.max_fee_cost().to_string();
+    let max_fee_cost_usd = match gas::exchange_rate(network).await {
+        Some(rate) => gas::usd_value(&max_fee_cost, network.decimals, &rate),
+        None => None,
+    };
+    Ok(PreparedTransaction {
+        chain_id: tx.chain_id,
+        from: from.map(|from| from.to_string()),
+        recipient: transfer.recipient.to_string(),
+        token: transfer.token.map(|token| token.to_string()),
+        symbol: transfer.symbol,
+        decimals: transfer.amount.decimals(),
+        amount: transfer.amount.to_string(),
+        amount_raw: transfer.amount.raw().to_string(),
+        speed: transfer.speed,
+        nonce: tx.nonce,
+        gas_limit: tx.gas_limit.to_string(),
+        max_fee_per_gas: tx.max_fee_per_gas.to_string(),
+        max_priority_fee_per_gas: tx.max_priority_fee_per_gas.to_string(),
+        max_fee_cost_formatted: format_units(&max_fee_cost, network.decimals),
+        max_fee_cost,
+        max_fee_cost_usd,
+        transaction: tx.to_json(from.as_ref()),
+        payment_uri,
+        warnings,
+    })
+}
+
+/// A transfer resolved to its transaction.
+pub(crate) struct Transfer {
+    pub transaction: Eip1559Transaction,
+    pub recipient: Address,
+    pub token: Option<Address>,
+    pub symbol: Option<String>,
+    pub amount: Amount,
+    pub speed: String,
+}
+
+/// Resolve amount, calldata, nonce, gas limit and fees of a transfer. A gas estimate that
+/// fails (e.g. the sender can't afford the transfer) falls back to a default limit with a warning.
+pub(crate) async fn build_transfer(
+    network: &NetworkConfig,
+    rpc: &RpcClient<'_>,
+    from: Option<&Address>,
+    request: &TransferRequest,
+    warnings: &mut Vec<String>,
+) -> Result<Transfer, BlockchainError> {
+    let recipient = validate_address(&request.to)?;
+    let token = request.token.as_deref().map(validate_address).transpose()?;
+    let speed = request.speed.clone().unwrap_or_else(|| "standard".to_string());
+
+    let chain_id = rpc.chain_id().await?;
+    if chain_id != network.chain_id {
+        return Err(BlockchainError::Config(format!(
+            "rpc_url serves chain {}, but the network is configured for chain {}",
+            chain_id, network.chain_id
+        )));
+    }
+
+    let (decimals, symbol) = match &token {
+        None => (network.decimals, Some(network.native_symbol.clone())),
+        Some(token) => {
+            let decimals = token_call(rpc, token, "decimals()(uint8)", &[]).await
+                .map_err(|_| BlockchainError::InvalidRequest(format!("{} is not an ERC-20 token", token)))?;
+            let symbol = Function::parse("symbol()(string)")?;
+            let symbol = rpc.eth_call(&token.to_string(), &symbol.encode_call(&[])?, "latest").await.ok()
+                .and_then(|output| symbol.decode_output(&output).ok())
+                .and_then(|outputs| outputs.first()?.get("value")?.as_str().map(|symbol| symbol.to_string()));
+            (token_decimals(&token.to_string(), decimals)?, symbol)
+        }
+    };
+    let amount = Amount::from_decimal(&request.amount, decimals)
+        .map_err(|e| BlockchainError::InvalidRequest(format!("invalid amount {}: {}", request.amount, e)))?;
+    if amount.raw().is_zero() {
+        return Err(BlockchainError::InvalidRequest("amount must be greater than zero".to_string()));
+    }
+
+    let TransferCall { to, value, data, default_gas } = transfer_call(&recipient, token.as_ref(), &amount)?;
+
+    let nonce = match from {
+        Some(from) => Some(rpc.get_transaction_count(&from.to_string(), "pending").await?),
+        None => None,
+    };
+
+    let mut call = serde_json::json!({
+        "to": to,
+        "value": format!("0x{:x}", value),
+        "data": format!("0x{}", to_hex(&data)),
+    });
+    if let Some(from) = from {
+        call["from"] = serde_json::json!(from);
+    }
+    let gas_limit = match rpc.estimate_gas(&call).await {
+        // Leave 20% headroom for contract calls whose cost can shift before inclusion
+        Ok(estimate) if estimate > U256::from(TRANSFER_GAS) => estimate.saturating_mul(6.into()) / 5,
+        Ok(estimate) => estimate,
+        Err(e @ BlockchainError::Rpc { .. }) => {
+            warnings.push(format!("Gas estimation failed ({}), using a limit of {}", e, default_gas));
+            U256::from(default_gas)
+        }
+        Err(e) => return Err(e),
+    };
+
+    let (max_fee_per_gas, max_priority_fee_per_gas) = gas::fee_market(rpc, None).await?
+        .fees_for(&speed)
+        .ok_or_else(|| BlockchainError::InvalidRequest(format!("unknown speed {}, expected slow, standard or fast", speed)))?;
+
+    Ok(Transfer {
+        transaction: Eip1559Transaction {
+            chain_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            to,
+            value,
+            data,
+        },
+        recipient,
+        token,
+        symbol,
+        amount,
+        speed,
+    })
+}
+
+/// Target, value and calldata of a transfer, with the gas limit to fall back on.
+#[derive(Debug, PartialEq)]
+struct TransferCall {
+    to: Address,
+    value: U256,
+    data: Vec<u8>,
+    default_gas: u64,
+}
+
+/// A native transfer sends `amount` to the recipient; an ERC-20 transfer sends nothing to
+/// the token contract and calls `transfer(recipient, amount)` on it.
+fn transfer_call(recipient: &Address, token: Option<&Address>, amount: &Amount) -> Result<TransferCall, BlockchainError> {
+    match token {
+        None => Ok(TransferCall { to: *recipient, value: amount.raw(), data: Vec::new(), default_gas: TRANSFER_GAS }),
+        Some(token) => {
+            let transfer = Function::parse("transfer(address,uint256)")?;
+            let args = [serde_json::json!(recipient), serde_json::json!(amount.raw().to_string())];
+            Ok(TransferCall { to: *token, value: U256::zero(), data: transfer.encode_call(&args)?, default_gas: TOKEN_TRANSFER_GAS })
+        }
+    }
+}
+
+/// EIP-681 URI: `ethereum:<to>@<chain>?value=<wei>` for the native coin, or
+/// `ethereum:<token>@<chain>/transfer?address=<recipient>&uint256=<amount>` for an ERC-20.
+fn payment_uri(transfer: &Transfer) -> String {
+    let tx = &transfer.transaction;
+    match &transfer.token {
+        None => format!("ethereum:{}@{}?value={}", transfer.recipient, tx.chain_id, tx.value),
+        Some(token) => format!(
+            "ethereum:{}@{}/transfer?address={}&uint256={}",
+            token,
+            tx.chain_id,
+            transfer.recipient,
+            transfer.amount.raw()
+        ),
+    }
+}
+
+/// Call a token view function returning a single integer.
+async fn token_call(
+    rpc: &RpcClient<'_>,
+    token: &Address,
+    signature: &str,
+    args: &[serde_json::Value],
+) -> Result<U256, BlockchainError> {
+    let function = Function::parse(signature)?;
+    let output = rpc.eth_call(&token.to_string(), &function.encode_call(args)?, "latest").await?;
+    if output.len() < 32 {
+        return Err(BlockchainError::InvalidRequest(format!("{} is not an ERC-20 token", token)));
+    }
+    Ok(U256::from_big_endian(&output[..32]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECIPIENT: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const TOKEN: &str = "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB";
+
+    fn transfer(token: Option<&str>, amount: &str, decimals: u8) -> Transfer {
+        let recipient: Address = RECIPIENT.parse().unwrap();
+        let token: Option<Address> = token.map(|token| token.parse().unwrap());
+        let amount = Amount::from_decimal(amount, decimals).unwrap();
+        let TransferCall { to, value, data, default_gas } = transfer_call(&recipient, token.as_ref(), &amount).unwrap();
+        Transfer {
+            transaction: Eip1559Transaction {
+                chain_id: 4386,
+                nonce: Some(7),
+                max_priority_fee_per_gas: U256::from(1_000_000_000u64),
+                max_fee_per_gas: U256::from(30_000_000_000u64),
+                gas_limit: U256::from(default_gas),
+                to,
+                value,
+                data,
+            },
+            recipient,
+            token,
+            symbol: None,
+            amount,
+            speed: "standard".to_string(),
+        }
+    }
+
+    #[test]
+    fn native_transfer_call_pays_the_recipient() {
+        let recipient: Address = RECIPIENT.parse().unwrap();
+        let amount = Amount::from_decimal("1.5", 18).unwrap();
+        assert_eq!(
+            transfer_call(&recipient, None, &amount).unwrap(),
+            TransferCall {
+                to: recipient,
+                value: U256::from(1_500_000_000_000_000_000u64),
+                data: Vec::new(),
+                default_gas: TRANSFER_GAS,
+            }
+        );
+    }
+
+    #[test]
+    fn erc20_transfer_call_targets_the_token() {
+        let recipient: Address = RECIPIENT.parse().unwrap();
+        let token: Address = TOKEN.parse().unwrap();
+        let amount = Amount::from_decimal("2.5", 6).unwrap();
+        let call = transfer_call(&recipient, Some(&token), &amount).unwrap();
+        assert_eq!(call.to, token);
+        assert_eq!(call.value, U256::zero());
+        assert_eq!(call.default_gas, TOKEN_TRANSFER_GAS);
+        // transfer(address,uint256): selector, recipient and 2500000 (0x2625a0), each padded to 32 bytes
+        assert_eq!(
+            to_hex(&call.data),
+            concat!(
+                "a9059cbb",
+                "0000000000000000000000005aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+                "00000000000000000000000000000000000000000000000000000000002625a0",
+            )
+        );
+    }
+
+    #[test]
+    fn native_payment_uri() {
+        assert_eq!(
+            payment_uri(&transfer(None, "1.5", 18)),
+            format!("ethereum:{}@4386?value=1500000000000000000", RECIPIENT)
+        );
+        // Values past u64 are written out in full, not in exponent form
+        assert_eq!(
+            payment_uri(&transfer(None, "100", 18)),
+            format!("ethereum:{}@4386?value=100000000000000000000", RECIPIENT)
+        );
+    }
+
+    #[test]
+    fn erc20_payment_uri() {
+        assert_eq!(
+            payment_uri(&transfer(Some(TOKEN), "2.5", 6)),
+            format!("ethereum:{}@4386/transfer?address={}&uint256=2500000", TOKEN, RECIPIENT)
+        );
+    }
+
+    #[test]
+    fn native_transaction_json() {
+        let tx = transfer(None, "1.5", 18).transaction;
+        let from: Address = TOKEN.parse().unwrap();
+        assert_eq!(
+            tx.to_json(Some(&from)),
+            serde_json::json!({
+                "type": "0x2",
+                "chainId": "0x1122",
+                "from": TOKEN,
+                "to": RECIPIENT,
+                "value": "0x14d1120d7b160000",
+                "data": "0x",
+                "gas": "0x5208",
+                "maxFeePerGas": "0x6fc23ac00",
+                "maxPriorityFeePerGas": "0x3b9aca00",
+                "nonce": "0x7",
+            })
+        );
+    }
+
+    #[test]
+    fn erc20_transaction_json() {
+        let mut tx = transfer(Some(TOKEN), "2.5", 6).transaction;
+        tx.nonce = None;
+        let json = tx.to_json(None);
+        assert_eq!(json["to"], TOKEN);
+        assert_eq!(json["value"], "0x0");
+        assert!(json["data"].as_str().unwrap().starts_with("0xa9059cbb"));
+        assert_eq!(json["gas"], format!("0x{:x}", TOKEN_TRANSFER_GAS));
+        assert!(json.get("from").is_none());
+        assert!(json.get("nonce").is_none());
+    }
+
+    #[test]
+    fn max_fee_cost_saturates() {
+        let mut tx = transfer(None, "1", 18).transaction;
+        assert_eq!(tx.max_fee_cost(), U256::from(21_000u64 * 30_000_000_000));
+        tx.gas_limit = U256::MAX;
+        assert_eq!(tx.max_fee_cost(), U256::MAX);
+    }
+}
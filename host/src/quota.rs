@@ -0,0 +1,153 @@
+use thiserror::Error;
+
+use crate::api_service::{QuotaLimits, UomiConfig};
+use crate::usage::{TokenUsage, UsageTotals};
+
+#[derive(Error, Debug)]
+pub enum QuotaError {
+    #[error("AI call limit reached: {used}/{limit} calls")]
+    Calls { used: u32, limit: u32 },
+    #[error("AI token budget exhausted: {used}/{limit} tokens")]
+    Tokens { used: u64, limit: u64 },
+    #[error("AI cost budget exhausted: {used:.4}/{limit:.4}")]
+    Cost { used: f64, limit: f64 },
+}
+
+/// Tracks AI usage of a single run against the configured limits. Nothing carries over
+/// between runs; the usage ledger is for reporting only.
+#[derive(Debug)]
+pub struct Quota {
+    agent_id: String,
+    limits: QuotaLimits,
+    totals: UsageTotals,
+}
+
+impl Quota {
+    pub fn from_config(config: &UomiConfig) -> Self {
+        Quota {
+            agent_id: config.agent_id.clone(),
+            limits: config.quota.limits_for(&config.agent_id),
+            totals: UsageTotals::default(),
+        }
+    }
+
+    /// Check whether another call is allowed. Token and cost budgets are checked
+    /// against what has been consumed so far, so the last call may overshoot them, and
+    /// a call that falls back to other models may overshoot `max_calls`.
+    pub fn check(&self) -> Result<(), QuotaError> {
+        if let Some(limit) = self.limits.max_calls {
+            if self.totals.calls >= limit {
+                return Err(QuotaError::Calls { used: self.totals.calls, limit });
+            }
+        }
+        if let Some(limit) = self.limits.max_tokens {
+            if self.totals.total_tokens() >= limit {
+                return Err(QuotaError::Tokens { used: self.totals.total_tokens(), limit });
+            }
+        }
+        if let Some(limit) = self.limits.max_cost {
+            if self.totals.cost >= limit {
+                return Err(QuotaError::Cost { used: self.totals.cost, limit });
+            }
+        }
+        Ok(())
+    }
+
+    /// Count model requests, whether they were answered or not.
+    pub fn record_calls(&mut self, calls: u32) {
+        self.totals.calls += calls;
+    }
+
+    /// Add the tokens and cost of an answered call.
+    pub fn record_usage(&mut self, usage: &TokenUsage, cost: f64) {
+        self.totals.prompt_tokens += usage.prompt_tokens;
+        self.totals.completion_tokens += usage.completion_tokens;
+        self.totals.cost += cost;
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "AI usage for {}: {} calls, {} tokens ({} prompt / {} completion), estimated cost {:.4}",
+            self.agent_id,
+            self.totals.calls,
+            self.totals.total_tokens(),
+            self.totals.prompt_tokens,
+            self.totals.completion_tokens,
+            self.totals.cost
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_service::QuotaConfig;
+    use std::collections::HashMap;
+
+    fn quota(max_calls: Option<u32>, max_tokens: Option<u64>, max_cost: Option<f64>) -> Quota {
+        Quota {
+            agent_id: "agent".to_string(),
+            limits: QuotaLimits { max_calls, max_tokens, max_cost },
+            totals: UsageTotals::default(),
+        }
+    }
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> TokenUsage {
+        TokenUsage { prompt_tokens, completion_tokens }
+    }
+
+    #[test]
+    fn no_limits_allow_everything() {
+        let mut quota = quota(None, None, None);
+        quota.record_calls(1000);
+        quota.record_usage(&usage(u32::MAX as u64, 1), 1e9);
+        assert!(quota.check().is_ok());
+    }
+
+    #[test]
+    fn every_attempt_counts_toward_max_calls() {
+        let mut quota = quota(Some(3), None, None);
+        // A call that failed over to a fallback model
+        quota.record_calls(2);
+        assert!(quota.check().is_ok());
+        quota.record_calls(1);
+        assert!(matches!(quota.check(), Err(QuotaError::Calls { used: 3, limit: 3 })));
+    }
+
+    #[test]
+    fn token_budget_is_checked_before_the_call() {
+        let mut quota = quota(None, Some(100), None);
+        quota.record_calls(1);
+        quota.record_usage(&usage(40, 59), 0.0);
+        assert!(quota.check().is_ok());
+        // The call that crosses the budget completes; the next one is refused
+        quota.record_calls(1);
+        quota.record_usage(&usage(500, 0), 0.0);
+        assert!(matches!(quota.check(), Err(QuotaError::Tokens { used: 599, limit: 100 })));
+    }
+
+    #[test]
+    fn cost_budget_is_checked_before_the_call() {
+        let mut quota = quota(None, None, Some(0.5));
+        quota.record_usage(&usage(0, 0), 0.49);
+        assert!(quota.check().is_ok());
+        quota.record_usage(&usage(0, 0), 0.01);
+        assert!(matches!(quota.check(), Err(QuotaError::Cost { .. })));
+    }
+
+    #[test]
+    fn agent_limits_override_the_defaults() {
+        let config = QuotaConfig {
+            per_run: QuotaLimits { max_calls: Some(10), max_tokens: Some(1000), max_cost: None },
+            agents: HashMap::from([(
+                "agent".to_string(),
+                QuotaLimits { max_calls: Some(2), max_tokens: None, max_cost: Some(1.0) },
+            )]),
+        };
+        let limits = config.limits_for("agent");
+        assert_eq!(limits.max_calls, Some(2));
+        assert_eq!(limits.max_tokens, Some(1000));
+        assert_eq!(limits.max_cost, Some(1.0));
+        assert_eq!(config.limits_for("other").max_calls, Some(10));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::api_service::RateLimitConfig;
+
+/// Token bucket refilled continuously at `capacity` units per minute.
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// A bucket for a configured limit. A limit of 0 means no limit: an empty bucket
+    /// would never refill.
+    fn for_limit(per_minute: Option<u64>) -> Option<Self> {
+        let per_minute = per_minute.filter(|limit| *limit > 0)? as f64;
+        Some(Bucket {
+            capacity: per_minute,
+            available: per_minute,
+            refilled_at: Instant::now(),
+        })
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.refilled_at = now;
+    }
+
+    /// Time until `amount` units are available.
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = amount - self.available;
+        if missing <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(missing * 60.0 / self.capacity)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Limiter {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+static LIMITERS: Mutex<Option<HashMap<String, Limiter>>> = Mutex::new(None);
+
+/// Wait until `key` may send another request costing `tokens` tokens, then take them
+/// from its buckets. Requests over the limit are queued, never rejected.
+pub async fn acquire(key: &str, limits: &RateLimitConfig, tokens: u64) {
+    let unlimited = |limit: Option<u64>| limit.is_none_or(|limit| limit == 0);
+    if unlimited(limits.requests_per_minute.map(u64::from)) && unlimited(limits.tokens_per_minute) {
+        return;
+    }
+
+    loop {
+        let wait = {
+            let mut guard = LIMITERS.lock().unwrap_or_else(|e| e.into_inner());
+            let limiter = guard.get_or_insert_with(HashMap::new)
+                .entry(key.to_string())
+                .or_insert_with(|| Limiter {
+                    requests: Bucket::for_limit(limits.requests_per_minute.map(u64::from)),
+                    tokens: Bucket::for_limit(limits.tokens_per_minute),
+                });
+
+            let mut wait = Duration::ZERO;
+            if let Some(bucket) = limiter.requests.as_mut() {
+                bucket.refill();
+                wait = wait.max(bucket.wait_for(1.0));
+            }
+            if let Some(bucket) = limiter.tokens.as_mut() {
+                bucket.refill();
+                // A single oversized request only has to wait for a full bucket.
+                wait = wait.max(bucket.wait_for((tokens as f64).min(bucket.capacity)));
+            }
+
+            if wait.is_zero() {
+                if let Some(bucket) = limiter.requests.as_mut() {
+                    bucket.available -= 1.0;
+                }
+                if let Some(bucket) = limiter.tokens.as_mut() {
+                    bucket.available -= tokens as f64;
+                }
+                return;
+            }
+            wait
+        };
+
+        println!("Rate limit reached for {}, waiting {} ms", key, wait.as_millis());
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Correct the token bucket once the real usage of a request is known.
+/// A negative balance delays the next request until it is paid back.
+pub fn settle_tokens(key: &str, estimated: u64, actual: u64) {
+    let mut guard = LIMITERS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(bucket) = guard.as_mut()
+        .and_then(|limiters| limiters.get_mut(key))
+        .and_then(|limiter| limiter.tokens.as_mut())
+    {
+        bucket.available += estimated as f64 - actual as f64;
+    }
+}
+
+/// Rough token count for a prompt, used before the provider reports real usage.
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.len() as u64).div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_limit_means_no_limit() {
+        assert!(Bucket::for_limit(Some(0)).is_none());
+        assert!(Bucket::for_limit(None).is_none());
+    }
+
+    #[test]
+    fn waits_for_missing_capacity() {
+        let mut bucket = Bucket::for_limit(Some(60)).unwrap();
+        assert_eq!(bucket.wait_for(60.0), Duration::ZERO);
+        bucket.available = 0.0;
+        // 60 per minute refills one unit per second
+        assert_eq!(bucket.wait_for(2.0), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn zero_limits_never_wait() {
+        let limits = RateLimitConfig { requests_per_minute: Some(0), tokens_per_minute: Some(0) };
+        let acquired = tokio::time::timeout(Duration::from_secs(1), async {
+            for _ in 0..3 {
+                acquire("zero-limit-test", &limits, 100).await;
+            }
+        });
+        assert!(acquired.await.is_ok());
+    }
+}
@@ -0,0 +1,226 @@
+use primitive_types::U256;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::abi::{hex_bytes, to_hex};
+use crate::api_service::NetworkConfig;
+use crate::blockchain_service::{send_request, BlockchainError};
+
+/// Minimal Ethereum JSON-RPC client for a configured network.
+pub struct RpcClient<'a> {
+    network: &'a NetworkConfig,
+    url: &'a str,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcResponse<T> {
+    result: Option<T>,
+    error: Option<RpcErrorBody>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RpcErrorBody {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+    pub address: String,
+    pub topics: Vec<String>,
+    pub data: String,
+    pub block_number: Option<String>,
+    pub transaction_hash: Option<String>,
+    pub log_index: Option<String>,
+}
+
+/// A transaction receipt as returned by the node; quantities stay hex-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionReceipt {
+    pub transaction_hash: String,
+    pub block_number: Option<String>,
+    pub from: String,
+    pub to: Option<String>,
+    pub contract_address: Option<String>,
+    /// `0x1` on success, `0x0` when the transaction reverted.
+    pub status: Option<String>,
+    pub gas_used: String,
+    pub effective_gas_price: Option<String>,
+    pub logs: Vec<Log>,
+}
+
+/// A transaction as returned by `eth_getTransactionByHash`; quantities stay hex-encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction {
+    pub hash: String,
+    pub block_number: Option<String>,
+    pub from: String,
+    pub to: Option<String>,
+    pub value: String,
+    pub input: String,
+    pub gas_price: Option<String>,
+}
+
+/// `eth_feeHistory` result: base fees of the range plus the next block, and the priority
+/// fees paid at each requested percentile.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeeHistory {
+    pub oldest_block: String,
+    #[serde(default)]
+    pub base_fee_per_gas: Vec<String>,
+    #[serde(default)]
+    pub reward: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockHeader {
+    pub number: String,
+    pub timestamp: String,
+}
+
+impl<'a> RpcClient<'a> {
+    pub fn new(network: &'a NetworkConfig) -> Result<Self, BlockchainError> {
+        let url = network.rpc_url.as_deref()
+            .ok_or_else(|| BlockchainError::Config("network has no rpc_url".to_string()))?;
+        Ok(RpcClient { network, url, next_id: AtomicU64::new(1) })
+    }
+
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<T, BlockchainError> {
+        self.call_optional(method, params).await?.ok_or_else(|| BlockchainError::Rpc {
+            code: 0,
+            message: format!("{} returned no result", method),
+        })
+    }
+
+    /// Like [`RpcClient::call`], for methods that return `null` when nothing is found.
+    pub async fn call_optional<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<Option<T>, BlockchainError> {
+        let body = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id.fetch_add(1, Ordering::Relaxed),
+            "method": method,
+            "params": params,
+        });
+        let response_text = send_request("rpc", self.network, self.url, Some(body.to_string())).await?;
+        let response: RpcResponse<T> = serde_json::from_str(&response_text)?;
+
+        if let Some(error) = response.error {
+            return Err(BlockchainError::Rpc { code: error.code, message: error.message });
+        }
+        Ok(response.result)
+    }
+
+    pub async fn block_number(&self) -> Result<u64, BlockchainError> {
+        parse_u64(&self.call::<String>("eth_blockNumber", serde_json::json!([])).await?)
+    }
+
+    pub async fn get_balance(&self, address: &str, block: &str) -> Result<U256, BlockchainError> {
+        parse_u256(&self.call::<String>("eth_getBalance", serde_json::json!([address, block])).await?)
+    }
+
+    pub async fn get_transaction_count(&self, address: &str, block: &str) -> Result<u64, BlockchainError> {
+        parse_u64(&self.call::<String>("eth_getTransactionCount", serde_json::json!([address, block])).await?)
+    }
+
+    pub async fn get_code(&self, address: &str, block: &str) -> Result<String, BlockchainError> {
+        self.call("eth_getCode", serde_json::json!([address, block])).await
+    }
+
+    /// The 32-byte word at `slot` of a contract's storage.
+    pub async fn get_storage_at(&self, address: &str, slot: &str, block: &str) -> Result<Vec<u8>, BlockchainError> {
+        let result: String = self.call("eth_getStorageAt", serde_json::json!([address, slot, block])).await?;
+        hex_bytes(&result).ok_or_else(|| BlockchainError::Rpc {
+            code: 0,
+            message: format!("invalid eth_getStorageAt result: {}", result),
+        })
+    }
+
+    /// Execute a read-only call and return the raw return data.
+    pub async fn eth_call(&self, to: &str, data: &[u8], block: &str) -> Result<Vec<u8>, BlockchainError> {
+        let call = serde_json::json!({ "to": to, "data": format!("0x{}", to_hex(data)) });
+        let result: String = self.call("eth_call", serde_json::json!([call, block])).await?;
+        hex_bytes(&result).ok_or_else(|| BlockchainError::Rpc {
+            code: 0,
+            message: format!("invalid eth_call result: {}", result),
+        })
+    }
+
+    /// Header of a block by number or tag, without its transactions.
+    pub async fn get_block(&self, block: &str) -> Result<Option<BlockHeader>, BlockchainError> {
+        self.call_optional("eth_getBlockByNumber", serde_json::json!([block, false])).await
+    }
+
+    /// `eth_getLogs` with a filter object (`address`, `topics`, `fromBlock`, `toBlock`).
+    pub async fn get_logs(&self, filter: &serde_json::Value) -> Result<Vec<Log>, BlockchainError> {
+        self.call("eth_getLogs", serde_json::json!([filter])).await
+    }
+
+    pub async fn chain_id(&self) -> Result<u64, BlockchainError> {
+        parse_u64(&self.call::<String>("eth_chainId", serde_json::json!([])).await?)
+    }
+
+    /// Submit a signed transaction and return its hash.
+    pub async fn send_raw_transaction(&self, raw: &[u8]) -> Result<String, BlockchainError> {
+        self.call("eth_sendRawTransaction", serde_json::json!([format!("0x{}", to_hex(raw))])).await
+    }
+
+    pub async fn gas_price(&self) -> Result<U256, BlockchainError> {
+        parse_u256(&self.call::<String>("eth_gasPrice", serde_json::json!([])).await?)
+    }
+
+    /// Fee history of the `blocks` blocks up to `newest`, with priority fees at `percentiles`.
+    pub async fn fee_history(&self, blocks: u64, newest: &str, percentiles: &[f64]) -> Result<FeeHistory, BlockchainError> {
+        self.call("eth_feeHistory", serde_json::json!([format!("0x{:x}", blocks), newest, percentiles])).await
+    }
+
+    /// `eth_estimateGas` for a call object (`from`, `to`, `value`, `data`).
+    pub async fn estimate_gas(&self, call: &serde_json::Value) -> Result<U256, BlockchainError> {
+        parse_u256(&self.call::<String>("eth_estimateGas", serde_json::json!([call])).await?)
+    }
+
+    pub async fn get_transaction(&self, hash: &str) -> Result<Option<RpcTransaction>, BlockchainError> {
+        self.call_optional("eth_getTransactionByHash", serde_json::json!([hash])).await
+    }
+
+    pub async fn get_transaction_receipt(&self, hash: &str) -> Result<Option<TransactionReceipt>, BlockchainError> {
+        self.call_optional("eth_getTransactionReceipt", serde_json::json!([hash])).await
+    }
+}
+
+/// Normalize a block parameter: decimal numbers become hex quantities, tags
+/// (`latest`, `earliest`, `pending`, ...) and hex numbers pass through. Defaults to `latest`.
+pub fn block_tag(block: Option<&str>) -> String {
+    match block {
+        None => "latest".to_string(),
+        Some(block) => match block.parse::<u64>() {
+            Ok(number) => format!("0x{:x}", number),
+            Err(_) => block.to_string(),
+        },
+    }
+}
+
+pub fn parse_u64(quantity: &str) -> Result<u64, BlockchainError> {
+    u64::from_str_radix(quantity.trim_start_matches("0x"), 16)
+        .map_err(|e| BlockchainError::Rpc { code: 0, message: format!("invalid quantity {}: {}", quantity, e) })
+}
+
+pub fn parse_u256(quantity: &str) -> Result<U256, BlockchainError> {
+    let digits = quantity.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Ok(U256::zero());
+    }
+    U256::from_str_radix(digits, 16)
+        .map_err(|e| BlockchainError::Rpc { code: 0, message: format!("invalid quantity {}: {:?}", quantity, e) })
+}
@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+
+use crate::abi::{hex_bytes, to_hex, Event, Function};
+use crate::api_service::{ChainSource, NetworkConfig};
+use crate::blockchain_service::{
+    checksummed, explorer_get, format_units, BlockchainError, ExplorerAddressRef, ExplorerFee, ExplorerSmartContract,
+    ExplorerToken,
+};
+use crate::rpc::{parse_u256, parse_u64, Log, RpcClient};
+
+/// A single transaction with its outcome, decoded method and token movements.
+#[derive(Debug, Serialize)]
+pub struct TransactionDetails {
+    pub hash: String,
+    /// `success`, `failed` or `pending`.
+    pub status: String,
+    pub block_number: Option<u64>,
+    pub timestamp: Option<String>,
+    pub from: String,
+    pub to: Option<String>,
+    /// Address of the contract created by the transaction, if any.
+    pub contract_address: Option<String>,
+    pub value: String,
+    pub value_formatted: Option<String>,
+    pub gas_used: Option<String>,
+    pub gas_price: Option<String>,
+    pub fee: Option<String>,
+    pub fee_formatted: Option<String>,
+    pub method: Option<DecodedCall>,
+    pub token_transfers: Vec<TokenTransfer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DecodedCall {
+    pub name: String,
+    /// Canonical signature, e.g. `transfer(address,uint256)`.
+    pub signature: String,
+    pub selector: String,
+    /// Decoded arguments as `{name, type, value}`.
+    pub arguments: Vec<serde_json::Value>,
+    /// `verified_abi` or `signature_table`.
+    pub source: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenTransfer {
+    pub token: String,
+    pub symbol: Option<String>,
+    pub token_type: Option<String>,
+    pub from: String,
+    pub to: String,
+    /// Raw amount, for fungible tokens.
+    pub value: Option<String>,
+    pub value_formatted: Option<String>,
+    /// Token ID, for NFTs.
+    pub token_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerTransactionDetails {
+    hash: String,
+    status: Option<String>,
+    #[serde(alias = "block")]
+    block_number: Option<u64>,
+    timestamp: Option<String>,
+    from: ExplorerAddressRef,
+    to: Option<ExplorerAddressRef>,
+    created_contract: Option<ExplorerAddressRef>,
+    value: String,
+    gas_used: Option<String>,
+    gas_price: Option<String>,
+    fee: Option<ExplorerFee>,
+    decoded_input: Option<ExplorerDecodedInput>,
+    raw_input: Option<String>,
+    token_transfers: Option<Vec<ExplorerTokenTransfer>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerDecodedInput {
+    method_call: String,
+    method_id: String,
+    parameters: Vec<ExplorerInputParam>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerInputParam {
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerTokenTransfer {
+    token: ExplorerToken,
+    from: ExplorerAddressRef,
+    to: ExplorerAddressRef,
+    total: Option<ExplorerTransferTotal>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplorerTransferTotal {
+    value: Option<String>,
+    token_id: Option<String>,
+    decimals: Option<String>,
+}
+
+/// Look up a transaction by hash on the network's configured source.
+pub async fn get_transaction(network: &NetworkConfig, hash: &str) -> Result<TransactionDetails, BlockchainError> {
+    let hash = validate_hash(hash)?;
+    match network.source {
+        ChainSource::Explorer => get_transaction_explorer(network, &hash).await,
+        ChainSource::Rpc => get_transaction_rpc(network, &hash).await,
+    }
+}
+
+async fn get_transaction_explorer(network: &NetworkConfig, hash: &str) -> Result<TransactionDetails, BlockchainError> {
+    let tx: ExplorerTransactionDetails = match explorer_get(network, &format!("/transactions/{}", hash)).await {
+        Err(BlockchainError::ApiError { status: 404, .. }) => {
+            return Err(BlockchainError::NotFound(format!("transaction {}", hash)))
+        }
+        result => result?,
+    };
+
+    // The explorer decodes calldata of verified contracts; fall back to the local table
+    let method = match tx.decoded_input {
+        Some(decoded) => Some(DecodedCall {
+            name: decoded.method_call.split('(').next().unwrap_or_default().trim().to_string(),
+            signature: Function::parse(&decoded.method_call)
+                .map(|function| function.signature())
+                .unwrap_or(decoded.method_call),
+            selector: decoded.method_id,
+            arguments: decoded.parameters.into_iter()
+                .map(|param| serde_json::json!({ "name": param.name, "type": param.kind, "value": param.value }))
+                .collect(),
+            source: "verified_abi",
+        }),
+        None => tx.raw_input.as_deref()
+            .and_then(hex_bytes)
+            .and_then(|calldata| decode_with(Function::known(&calldata)?, &calldata, "signature_table")),
+    };
+
+    let fee = tx.fee.and_then(|fee| fee.value);
+    Ok(TransactionDetails {
+        hash: tx.hash,
+        status: match tx.status.as_deref() {
+            Some("ok") => "success",
+            Some(_) => "failed",
+            None => "pending",
+        }
+        .to_string(),
+        block_number: tx.block_number,
+        timestamp: tx.timestamp,
+        from: tx.from.hash,
+        to: tx.to.map(|to| to.hash),
+        contract_address: tx.created_contract.map(|contract| contract.hash),
+        value_formatted: format_units(&tx.value, network.decimals),
+        value: tx.value,
+        gas_used: tx.gas_used,
+        gas_price: tx.gas_price,
+        fee_formatted: fee.as_deref().and_then(|fee| format_units(fee, network.decimals)),
+        fee,
+        method,
+        token_transfers: tx.token_transfers.unwrap_or_default().into_iter()
+            .map(|transfer| {
+                let total = transfer.total;
+                let value = total.as_ref().and_then(|total| total.value.clone());
+                let decimals = total.as_ref()
+                    .and_then(|total| total.decimals.as_ref())
+                    .or(transfer.token.decimals.as_ref())
+                    .and_then(|decimals| decimals.parse().ok());
+                TokenTransfer {
+                    token: transfer.token.address,
+                    symbol: transfer.token.symbol,
+                    token_type: Some(transfer.token.token_type),
+                    from: transfer.from.hash,
+                    to: transfer.to.hash,
+                    value_formatted: value.as_deref().zip(decimals).and_then(|(value, decimals)| format_units(value, decimals)),
+                    value,
+                    token_id: total.and_then(|total| total.token_id),
+                }
+            })
+            .collect(),
+    })
+}
+
+async fn get_transaction_rpc(network: &NetworkConfig, hash: &str) -> Result<TransactionDetails, BlockchainError> {
+    let rpc = RpcClient::new(network)?;
+    let tx = rpc.get_transaction(hash).await?
+        .ok_or_else(|| BlockchainError::NotFound(format!("transaction {}", hash)))?;
+    let receipt = rpc.get_transaction_receipt(hash).await?;
+
+    let calldata = hex_bytes(&tx.input).unwrap_or_default();
+    let method = match tx.to.as_deref() {
+        Some(to) if calldata.len() >= 4 => decode_call(network, to, &calldata).await,
+        _ => None,
+    };
+
+    let value = parse_u256(&tx.value)?.to_string();
+    let gas_used = receipt.as_ref().map(|receipt| parse_u256(&receipt.gas_used)).transpose()?;
+    let gas_price = receipt.as_ref()
+        .and_then(|receipt| receipt.effective_gas_price.as_deref())
+        .or(tx.gas_price.as_deref())
+        .map(parse_u256)
+        .transpose()?;
+    let fee = gas_used.zip(gas_price).map(|(gas_used, gas_price)| gas_used.saturating_mul(gas_price).to_string());
+
+    Ok(TransactionDetails {
+        hash: tx.hash,
+        status: match receipt.as_ref().map(|receipt| receipt.status.as_deref()) {
+            None => "pending",
+            Some(Some("0x1")) | Some(None) => "success",
+            Some(Some(_)) => "failed",
+        }
+        .to_string(),
+        block_number: tx.block_number.as_deref().map(parse_u64).transpose()?,
+        timestamp: None,
+        from: checksummed(tx.from),
+        to: tx.to.map(checksummed),
+        contract_address: receipt.as_ref().and_then(|receipt| receipt.contract_address.clone()).map(checksummed),
+        value_formatted: format_units(&value, network.decimals),
+        value,
+        gas_used: gas_used.map(|gas| gas.to_string()),
+        gas_price: gas_price.map(|price| price.to_string()),
+        fee_formatted: fee.as_deref().and_then(|fee| format_units(fee, network.decimals)),
+        fee,
+        method,
+        token_transfers: receipt.map(|receipt| decode_transfers(&receipt.logs)).unwrap_or_default(),
+    })
+}
+
+/// Decode calldata sent to `contract`, preferring its verified ABI on the explorer.
+async fn decode_call(network: &NetworkConfig, contract: &str, calldata: &[u8]) -> Option<DecodedCall> {
+    let verified = verified_abi(network, contract).await
+        .and_then(|abi| Function::from_abi_by_selector(&abi, calldata));
+    match verified {
+        Some(function) => decode_with(function, calldata, "verified_abi"),
+        None => decode_with(Function::known(calldata)?, calldata, "signature_table"),
+    }
+}
+
+fn decode_with(function: Function, calldata: &[u8], source: &'static str) -> Option<DecodedCall> {
+    Some(DecodedCall {
+        name: function.name.clone(),
+        signature: function.signature(),
+        selector: format!("0x{}", to_hex(&function.selector())),
+        // A matching selector with undecodable arguments still names the method
+        arguments: function.decode_input(calldata).unwrap_or_default(),
+        source,
+    })
+}
+
+/// The verified ABI of `address`, or `None` when the explorer has none or can't be reached.
+async fn verified_abi(network: &NetworkConfig, address: &str) -> Option<serde_json::Value> {
+    match explorer_get::<ExplorerSmartContract>(network, &format!("/smart-contracts/{}", address)).await {
+        Ok(contract) => contract.abi,
+        Err(e) => {
+            println!("No verified ABI for {}: {}", address, e);
+            None
+        }
+    }
+}
+
+/// ERC-20 and ERC-721 transfers among a receipt's logs.
+fn decode_transfers(logs: &[Log]) -> Vec<TokenTransfer> {
+    let events = Event::known(Some("Transfer"));
+    logs.iter()
+        .filter_map(|log| {
+            let topics: Vec<Vec<u8>> = log.topics.iter().map(|topic| hex_bytes(topic)).collect::<Option<_>>()?;
+            let data = hex_bytes(&log.data)?;
+            let (event, values) = events.iter().find_map(|event| Some((event, event.decode_log(&topics, &data).ok()?)))?;
+            let value_at = |i: usize| values.get(i)?.get("value")?.as_str().map(|value| value.to_string());
+
+            // ERC-721 indexes the token ID, ERC-20 keeps the amount in the data
+            let is_nft = event.inputs.iter().all(|(_, indexed)| *indexed);
+            Some(TokenTransfer {
+                token: checksummed(log.address.clone()),
+                symbol: None,
+                token_type: Some(if is_nft { "ERC-721" } else { "ERC-20" }.to_string()),
+                from: value_at(0)?,
+                to: value_at(1)?,
+                value: if is_nft { None } else { value_at(2) },
+                value_formatted: None,
+                token_id: if is_nft { value_at(2) } else { None },
+            })
+        })
+        .collect()
+}
+
+/// A transaction hash: `0x` followed by 64 hex digits, returned in lowercase.
+pub(crate) fn validate_hash(hash: &str) -> Result<String, BlockchainError> {
+    let hash = hash.trim();
+    let digits = hash.strip_prefix("0x").unwrap_or_default();
+    if digits.len() != 64 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(BlockchainError::InvalidRequest(format!("invalid transaction hash {}", hash)));
+    }
+    Ok(hash.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSFER_TOPIC: &str = "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef";
+    const TOKEN: &str = "0xdbf03b407c01e7cd3cbea99509d93f8dddc8c6fb";
+    const ALICE: &str = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    const BOB: &str = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+
+    fn topic(address: &str) -> String {
+        format!("0x{:0>64}", address[2..].to_lowercase())
+    }
+
+    fn log(topics: Vec<String>, data: &str) -> Log {
+        Log {
+            address: TOKEN.to_string(),
+            topics,
+            data: data.to_string(),
+            block_number: Some("0x1".to_string()),
+            transaction_hash: None,
+            log_index: Some("0x0".to_string()),
+        }
+    }
+
+    #[test]
+    fn decodes_erc20_transfer_logs() {
+        // 1.5 tokens with 18 decimals
+        let data = format!("0x{:064x}", 1_500_000_000_000_000_000u128);
+        let transfers = decode_transfers(&[log(vec![TRANSFER_TOPIC.to_string(), topic(ALICE), topic(BOB)], &data)]);
+        assert_eq!(transfers.len(), 1);
+        let transfer = &transfers[0];
+        assert_eq!(transfer.token, "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB");
+        assert_eq!(transfer.token_type.as_deref(), Some("ERC-20"));
+        assert_eq!(transfer.from, ALICE);
+        assert_eq!(transfer.to, BOB);
+        assert_eq!(transfer.value.as_deref(), Some("1500000000000000000"));
+        assert_eq!(transfer.token_id, None);
+    }
+
+    #[test]
+    fn decodes_erc721_transfer_logs() {
+        let token_id = format!("0x{:064x}", 42);
+        let transfers = decode_transfers(&[log(vec![TRANSFER_TOPIC.to_string(), topic(ALICE), topic(BOB), token_id], "0x")]);
+        assert_eq!(transfers.len(), 1);
+        assert_eq!(transfers[0].token_type.as_deref(), Some("ERC-721"));
+        assert_eq!(transfers[0].token_id.as_deref(), Some("42"));
+        assert_eq!(transfers[0].value, None);
+    }
+
+    #[test]
+    fn skips_malformed_and_unrelated_logs() {
+        let amount = format!("0x{:064x}", 1);
+        let logs = [
+            // Not hex
+            log(vec![TRANSFER_TOPIC.to_string(), "0xnothex".to_string(), topic(BOB)], &amount),
+            // Missing the recipient topic
+            log(vec![TRANSFER_TOPIC.to_string(), topic(ALICE)], &amount),
+            // Approval, not Transfer
+            log(
+                vec![
+                    "0x8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925".to_string(),
+                    topic(ALICE),
+                    topic(BOB),
+                ],
+                &amount,
+            ),
+            // Amount missing from the data
+            log(vec![TRANSFER_TOPIC.to_string(), topic(ALICE), topic(BOB)], "0x"),
+        ];
+        assert!(decode_transfers(&logs).is_empty());
+    }
+
+    #[test]
+    fn validates_transaction_hashes() {
+        let hash = format!("0x{}", "Ab".repeat(32));
+        assert_eq!(validate_hash(&format!("  {} ", hash)).unwrap(), hash.to_lowercase());
+        for bad in [
+            format!("0x{}", "ab".repeat(31)),
+            format!("0x{}", "ab".repeat(33)),
+            "ab".repeat(32),
+            format!("0x{}zz", "ab".repeat(31)),
+            String::new(),
+        ] {
+            assert!(matches!(validate_hash(&bad), Err(BlockchainError::InvalidRequest(_))), "{}", bad);
+        }
+    }
+}
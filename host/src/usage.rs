@@ -0,0 +1,312 @@
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::OpenOptions, io::Write, path::Path};
+
+use crate::api_service::{ModelPricing, ServiceError, UomiConfig, UsageConfig};
+
+/// Token counts reported by a model provider for a single call.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Extract usage from a raw provider response.
+    ///
+    /// Understands the OpenAI (`usage.prompt_tokens`), Ollama (`prompt_eval_count` /
+    /// `eval_count`) and UOMI (`total_tokens_generated`) formats. Unknown formats count as zero.
+    pub fn from_response(body: &[u8]) -> TokenUsage {
+        let json: serde_json::Value = match serde_json::from_slice(body) {
+            Ok(json) => json,
+            Err(_) => return TokenUsage::default(),
+        };
+        let field = |value: &serde_json::Value, key: &str| value.get(key).and_then(|v| v.as_u64());
+
+        if let Some(usage) = json.get("usage") {
+            return TokenUsage {
+                prompt_tokens: field(usage, "prompt_tokens").unwrap_or_default(),
+                completion_tokens: field(usage, "completion_tokens").unwrap_or_default(),
+            };
+        }
+
+        if json.get("eval_count").is_some() || json.get("prompt_eval_count").is_some() {
+            return TokenUsage {
+                prompt_tokens: field(&json, "prompt_eval_count").unwrap_or_default(),
+                completion_tokens: field(&json, "eval_count").unwrap_or_default(),
+            };
+        }
+
+        TokenUsage {
+            prompt_tokens: 0,
+            completion_tokens: field(&json, "total_tokens_generated").unwrap_or_default(),
+        }
+    }
+}
+
+impl ModelPricing {
+    /// Estimated cost of a call in the configured currency.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 / 1000.0) * self.input_per_1k
+            + (usage.completion_tokens as f64 / 1000.0) * self.output_per_1k
+    }
+}
+
+/// Running totals of AI usage for one agent run.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct UsageTotals {
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageTotals {
+    pub fn add(&mut self, usage: &TokenUsage, cost: f64) {
+        self.calls += 1;
+        self.prompt_tokens += usage.prompt_tokens;
+        self.completion_tokens += usage.completion_tokens;
+        self.cost += cost;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// One model call, as written to the run report and the cumulative ledger.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: String,
+    pub agent_id: String,
+    pub model_id: i32,
+    pub model: String,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost: f64,
+}
+
+impl UsageRecord {
+    pub fn new(config: &UomiConfig, model_id: i32, usage: &TokenUsage) -> Self {
+        let model = config.models.get(&model_id.to_string());
+        UsageRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            agent_id: config.agent_id.clone(),
+            model_id,
+            model: model.map(|m| m.name.clone()).unwrap_or_default(),
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            cost: model.map(|m| m.pricing.cost(usage)).unwrap_or_default(),
+        }
+    }
+
+    fn usage(&self) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens,
+            completion_tokens: self.completion_tokens,
+        }
+    }
+}
+
+/// Per-run usage report.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub agent_id: String,
+    pub started_at: String,
+    pub finished_at: String,
+    pub totals: UsageTotals,
+    pub calls: Vec<UsageRecord>,
+}
+
+impl UsageReport {
+    pub fn new(agent_id: &str, started_at: String, calls: Vec<UsageRecord>) -> Self {
+        let mut totals = UsageTotals::default();
+        for record in &calls {
+            totals.add(&record.usage(), record.cost);
+        }
+        UsageReport {
+            agent_id: agent_id.to_string(),
+            started_at,
+            finished_at: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            totals,
+            calls,
+        }
+    }
+}
+
+/// Write the run report and append its calls to the ledger.
+pub fn save_report(config: &UsageConfig, report: &UsageReport) -> Result<(), ServiceError> {
+    std::fs::write(&config.report_path, serde_json::to_vec_pretty(report)?)?;
+
+    let mut ledger = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.ledger_path)?;
+    for record in &report.calls {
+        writeln!(ledger, "{}", serde_json::to_string(record)?)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GroupBy {
+    Model,
+    Agent,
+    Day,
+}
+
+impl GroupBy {
+    pub fn parse(value: &str) -> Option<GroupBy> {
+        match value {
+            "model" => Some(GroupBy::Model),
+            "agent" => Some(GroupBy::Agent),
+            "day" => Some(GroupBy::Day),
+            _ => None,
+        }
+    }
+
+    fn key(&self, record: &UsageRecord) -> String {
+        match self {
+            GroupBy::Model => record.model.clone(),
+            GroupBy::Agent => record.agent_id.clone(),
+            GroupBy::Day => record.timestamp.chars().take(10).collect(),
+        }
+    }
+}
+
+/// Aggregate the ledger file, sorted by group key.
+pub fn summarize_ledger(path: &str, group_by: GroupBy) -> Result<BTreeMap<String, UsageTotals>, ServiceError> {
+    if !Path::new(path).exists() {
+        return Ok(BTreeMap::new());
+    }
+
+    summarize(&std::fs::read_to_string(path)?, group_by)
+}
+
+/// Aggregate ledger lines, one JSON record per line.
+fn summarize(ledger: &str, group_by: GroupBy) -> Result<BTreeMap<String, UsageTotals>, ServiceError> {
+    let mut groups: BTreeMap<String, UsageTotals> = BTreeMap::new();
+    for line in ledger.lines().filter(|line| !line.trim().is_empty()) {
+        let record: UsageRecord = serde_json::from_str(line)?;
+        groups
+            .entry(group_by.key(&record))
+            .or_default()
+            .add(&record.usage(), record.cost);
+    }
+    Ok(groups)
+}
+
+pub fn print_ledger_summary(config: &UsageConfig, group_by: GroupBy) -> Result<(), ServiceError> {
+    let groups = summarize_ledger(&config.ledger_path, group_by)?;
+    if groups.is_empty() {
+        println!("No usage recorded in {}", config.ledger_path);
+        return Ok(());
+    }
+
+    println!("{:<28} {:>8} {:>12} {:>12} {:>12}", format!("{:?}", group_by).to_lowercase(), "calls", "prompt", "completion", "cost");
+    for (key, totals) in &groups {
+        println!(
+            "{:<28} {:>8} {:>12} {:>12} {:>12.4}",
+            key, totals.calls, totals.prompt_tokens, totals.completion_tokens, totals.cost
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(response: serde_json::Value) -> TokenUsage {
+        TokenUsage::from_response(response.to_string().as_bytes())
+    }
+
+    #[test]
+    fn reads_openai_usage() {
+        let usage = parse(json!({
+            "choices": [{"message": {"role": "assistant", "content": "hi"}}],
+            "usage": {"prompt_tokens": 12, "completion_tokens": 5, "total_tokens": 17},
+        }));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (12, 5));
+    }
+
+    #[test]
+    fn reads_ollama_usage() {
+        let usage = parse(json!({"response": "hi", "done": true, "prompt_eval_count": 26, "eval_count": 298}));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (26, 298));
+        // Ollama leaves prompt_eval_count out when the prompt was cached
+        let usage = parse(json!({"response": "hi", "eval_count": 7}));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (0, 7));
+    }
+
+    #[test]
+    fn reads_uomi_usage() {
+        let usage = parse(json!({"response": "hi", "time_taken": 0.5, "total_tokens_generated": 100}));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (0, 100));
+    }
+
+    #[test]
+    fn unknown_formats_count_as_zero() {
+        let usage = TokenUsage::from_response(b"not json");
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (0, 0));
+        let usage = parse(json!({"response": "hi"}));
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (0, 0));
+    }
+
+    #[test]
+    fn prices_per_thousand_tokens() {
+        let pricing = ModelPricing { input_per_1k: 0.5, output_per_1k: 1.5 };
+        let cost = pricing.cost(&TokenUsage { prompt_tokens: 2000, completion_tokens: 500 });
+        assert!((cost - 1.75).abs() < 1e-9);
+    }
+
+    fn record(timestamp: &str, agent_id: &str, model: &str, prompt_tokens: u64, cost: f64) -> String {
+        serde_json::to_string(&UsageRecord {
+            timestamp: timestamp.to_string(),
+            agent_id: agent_id.to_string(),
+            model_id: 1,
+            model: model.to_string(),
+            prompt_tokens,
+            completion_tokens: 10,
+            cost,
+        })
+        .unwrap()
+    }
+
+    fn ledger() -> String {
+        [
+            record("2026-01-01T10:00:00Z", "vault", "gpt-4o-mini", 100, 0.25),
+            record("2026-01-01T23:59:59Z", "vault", "llama3", 50, 0.0),
+            String::new(),
+            record("2026-01-02T00:00:00Z", "other", "gpt-4o-mini", 200, 0.5),
+        ]
+        .join("\n")
+    }
+
+    #[test]
+    fn groups_ledger_by_model() {
+        let groups = summarize(&ledger(), GroupBy::Model).unwrap();
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["gpt-4o-mini", "llama3"]);
+        let gpt = &groups["gpt-4o-mini"];
+        assert_eq!((gpt.calls, gpt.prompt_tokens, gpt.completion_tokens), (2, 300, 20));
+        assert!((gpt.cost - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn groups_ledger_by_agent_and_day() {
+        let groups = summarize(&ledger(), GroupBy::Agent).unwrap();
+        assert_eq!(groups["vault"].calls, 2);
+        assert_eq!(groups["other"].calls, 1);
+
+        let groups = summarize(&ledger(), GroupBy::Day).unwrap();
+        assert_eq!(groups.keys().collect::<Vec<_>>(), ["2026-01-01", "2026-01-02"]);
+        assert_eq!(groups["2026-01-01"].prompt_tokens, 150);
+    }
+
+    #[test]
+    fn rejects_corrupt_ledger_lines() {
+        assert!(summarize("{not json}", GroupBy::Model).is_err());
+        assert!(summarize("", GroupBy::Model).unwrap().is_empty());
+    }
+}
@@ -6,4 +6,15 @@ pub fn generate_wasm_data(data: Vec<u8>) -> Vec<u8> {
     wasm_data.extend(data);
     
     wasm_data
-}
\ No newline at end of file
+}
+
+/// JSON error payload handed back to the agent in place of a service response.
+pub fn error_payload(code: &str, message: &str) -> Vec<u8> {
+    serde_json::to_vec(&serde_json::json!({
+        "error": {
+            "code": code,
+            "message": message,
+        }
+    }))
+    .unwrap_or_default()
+}
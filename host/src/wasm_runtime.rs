@@ -1,13 +1,26 @@
-use crate::api_service::{call_service_api, get_file_from_cid, get_file};
-use crate::blockchain_service::get_wallet_balance;
-use crate::utils::generate_wasm_data;
+use crate::api_service::{call_service_api, get_config, get_file_from_cid, get_file};
+use crate::blockchain_service::{dispatch, BlockchainCall, BlockchainError};
+use crate::cache;
+use crate::quota::Quota;
+use crate::usage::{save_report, TokenUsage, UsageRecord, UsageReport};
+use crate::utils::{error_payload, generate_wasm_data};
+
+struct HostState {
+    io: Vec<u8>,
+    quota: Quota,
+    usage: Vec<UsageRecord>,
+}
 
 pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error> {
+    let config = get_config().map_err(|e| wasmi::core::Trap::new(e.to_string()))?;
+    let started_at = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
     let engine = wasmi::Engine::default();
     let module = wasmi::Module::new(&engine, &wasm[..])?;
-    let mut store = wasmi::Store::new(&engine, input);
-
-    type HostState = Vec<u8>;
+    let mut store = wasmi::Store::new(&engine, HostState {
+        io: input,
+        quota: Quota::from_config(&config),
+        usage: Vec::new(),
+    });
 
     let console_log = wasmi::Func::wrap(
         &mut store,
@@ -30,7 +43,7 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
     let get_input_data = wasmi::Func::wrap(
         &mut store,
         move |mut caller: wasmi::Caller<'_, HostState>, ptr: i32, _len: i32| {
-            let data_to_write = generate_wasm_data(caller.data().clone());
+            let data_to_write = generate_wasm_data(caller.data().io.clone());
         
             let memory = caller
                 .get_export("memory")
@@ -56,7 +69,7 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
                 .read(&caller, ptr as usize, &mut buffer)
                 .expect("Failed to read memory");
 
-            *caller.data_mut() = buffer;
+            caller.data_mut().io = buffer;
         }
     );
 
@@ -74,13 +87,40 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
                 .read(&caller, ptr as usize, &mut input_for_service)
                 .expect("Failed to read memory");
 
-            // Convert model from i32 to AiModelKey type
-            let service_output_text = async {
-                call_service_api(model, input_for_service).unwrap()
+            let service_output = match caller.data().quota.check() {
+                Ok(()) => {
+                    // Convert model from i32 to AiModelKey type
+                    let mut attempts = 0;
+                    let service_output_text = async {
+                        call_service_api(model, input_for_service, &mut attempts)
+                    };
+
+                    let result = futures::executor::block_on(service_output_text);
+                    // Failed models and fallbacks count toward max_calls too
+                    caller.data_mut().quota.record_calls(attempts);
+                    match result {
+                        Ok((served_by, service_output)) => {
+                            let usage = TokenUsage::from_response(&service_output);
+                            if let Ok(config) = get_config() {
+                                let record = UsageRecord::new(&config, served_by, &usage);
+                                caller.data_mut().quota.record_usage(&usage, record.cost);
+                                caller.data_mut().usage.push(record);
+                            }
+                            service_output
+                        }
+                        Err(e) => {
+                            println!("AI service error: {}", e);
+                            let code = if e.is_unavailable() { "model_unavailable" } else { "model_error" };
+                            error_payload(code, &e.to_string())
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("AI quota exceeded: {}", e);
+                    error_payload("quota_exceeded", &e.to_string())
+                }
             };
 
-            let service_output = futures::executor::block_on(service_output_text);
-            
             let service_output = generate_wasm_data(service_output);
 
             let memory = caller
@@ -149,7 +189,7 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
 
     let call_blockchain = wasmi::Func::wrap(
         &mut store,
-        move |mut caller: wasmi::Caller<'_, HostState>, ptr: i32, len: i32, output_ptr: i32, _: i32| -> i32 {
+        move |mut caller: wasmi::Caller<'_, HostState>, ptr: i32, len: i32, output_ptr: i32, output_len: i32| -> i32 {
             let memory = caller
                 .get_export("memory")
                 .and_then(wasmi::Extern::into_memory)
@@ -160,30 +200,38 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
                 .read(&caller, ptr as usize, &mut buffer)
                 .expect("Failed to read memory");
 
-            let request = String::from_utf8(buffer).unwrap();
-            let request_json: serde_json::Value = serde_json::from_str(&request).unwrap();
-
-            if let Some(address) = request_json.get("address").and_then(|v| v.as_str()) {
-                let rt = tokio::runtime::Runtime::new().unwrap();
-                match rt.block_on(async { get_wallet_balance(address).await }) {
-                    Ok(balance) => {
-                        let balance_json = serde_json::to_vec(&balance).unwrap();
-                        let data_to_write = generate_wasm_data(balance_json);
-                        
-                        memory
-                            .write(&mut caller, output_ptr as usize, &data_to_write)
-                            .expect("Failed to write memory");
-                        0 // Success
-                    }
-                    Err(e) => {
-                        println!("Blockchain service error: {:?}", e);
-                        1 // Error
-                    }
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let result = BlockchainCall::parse(&buffer)
+                .and_then(|call| rt.block_on(async { dispatch(call).await }));
+
+            let (payload, code) = match result {
+                Ok(response) => (serde_json::to_vec(&response).unwrap(), 0), // Success
+                Err(e) => {
+                    println!("Blockchain service error: {:?}", e);
+                    (e.to_chain_error().to_payload(), e.code())
+                }
+            };
+
+            let mut data_to_write = generate_wasm_data(payload);
+            let mut code = code;
+            if data_to_write.len() > output_len as usize {
+                let error = BlockchainError::InvalidRequest(format!(
+                    "response of {} bytes is larger than the agent's {}-byte buffer; narrow the request",
+                    data_to_write.len(),
+                    output_len
+                ));
+                println!("Blockchain service error: {:?}", error);
+                data_to_write = generate_wasm_data(error.to_chain_error().to_payload());
+                code = error.code();
+                // Not even the error fits: leave the buffer alone and let the code speak
+                if data_to_write.len() > output_len as usize {
+                    return code;
                 }
-            } else {
-                println!("Invalid blockchain request format");
-                1 // Error
             }
+            memory
+                .write(&mut caller, output_ptr as usize, &data_to_write)
+                .expect("Failed to write memory");
+            code
         }
     );
 
@@ -196,10 +244,20 @@ pub fn run_wasm(wasm: Vec<u8>, input: Vec<u8>) -> Result<Vec<u8>, wasmi::Error>
     linker.define("env", "get_input_file", get_input_file)?;
     linker.define("env", "call_blockchain", call_blockchain)?;
 
-    let instance = linker.instantiate(&mut store, &module)?.start(&mut store)?;
-    let wasm_run = instance.get_typed_func::<(), ()>(&store, "run")?;
+    // A trap must not lose the AI calls already made, so the report is written either way
+    let result = linker.instantiate(&mut store, &module)
+        .and_then(|instance| instance.start(&mut store))
+        .and_then(|instance| instance.get_typed_func::<(), ()>(&store, "run"))
+        .and_then(|wasm_run| wasm_run.call(&mut store, ()).map_err(wasmi::Error::from));
+
+    let state = store.into_data();
+    cache::flush(&config.blockchain.cache);
+    println!("{}", state.quota.summary());
 
-    wasm_run.call(&mut store, ())?;
+    let report = UsageReport::new(&config.agent_id, started_at, state.usage);
+    if let Err(e) = save_report(&config.usage, &report) {
+        println!("Failed to save usage report: {}", e);
+    }
 
-    Ok(store.into_data())
+    result.map(|_| state.io)
 }